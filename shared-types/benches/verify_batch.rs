@@ -0,0 +1,63 @@
+//! Time `verify_batch_local` and, with the `rayon` feature, `verify_batch_local_parallel` over a
+//! batch of signed packages:
+//!
+//! ```sh
+//! cargo bench --features rayon
+//! ```
+
+use libsecp256k1::{Message, PublicKey, SecretKey};
+use shared_types::{signed_message_hash, verify_batch_local, SignaturePackage, HASH_ALGO_KECCAK256};
+use solana_program::pubkey::Pubkey;
+use std::time::{Duration, Instant};
+
+const PROGRAM_ID: Pubkey = Pubkey::new_from_array([0x42; 32]);
+const PAYER: Pubkey = Pubkey::new_from_array([0x22; 32]);
+const CHAIN_ID: u64 = 0x4444_4444_4444_4444;
+const NOW: i64 = 1_700_000_000;
+const BATCH_SIZE: usize = 1_000;
+const ROUNDS: u32 = 5;
+
+fn signed_batch() -> Vec<SignaturePackage> {
+    let secret_key = SecretKey::parse(&[0x11; 32]).unwrap();
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+    (0..BATCH_SIZE)
+        .map(|index| {
+            let mut data = [0u8; 32];
+            data[..8].copy_from_slice(&(index as u64).to_le_bytes());
+            let expiry_unix = NOW + 60;
+            let tag = [0; 8];
+            let message_hash = signed_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &data, expiry_unix, &tag, HASH_ALGO_KECCAK256).unwrap();
+            let (signature, recovery_id) = libsecp256k1::sign(&Message::parse(&message_hash), &secret_key);
+            SignaturePackage {
+                verifier_signature: signature.serialize(),
+                recovery_id: recovery_id.serialize(),
+                public_key,
+                data,
+                expiry_unix,
+                hash_algo: HASH_ALGO_KECCAK256,
+                tag,
+            }
+        })
+        .collect()
+}
+
+/// Best of `ROUNDS` runs of `verify`, which has to accept every package.
+fn time<T, E>(name: &str, verify: impl Fn() -> Vec<Result<T, E>>) {
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let results = verify();
+        best = best.min(start.elapsed());
+        assert!(results.iter().all(Result::is_ok), "{} rejected a valid package", name);
+    }
+    let per_package = best / BATCH_SIZE as u32;
+    println!("{:<28} {:>10.2?} per batch of {}, {:>8.2?} per package", name, best, BATCH_SIZE, per_package);
+}
+
+fn main() {
+    let packages = signed_batch();
+
+    time("verify_batch_local", || verify_batch_local(&PROGRAM_ID, &PAYER, CHAIN_ID, &packages, NOW));
+    #[cfg(feature = "rayon")]
+    time("verify_batch_local_parallel", || shared_types::verify_batch_local_parallel(&PROGRAM_ID, &PAYER, CHAIN_ID, &packages, NOW));
+}
@@ -0,0 +1,213 @@
+use solana_program::program_error::ProgramError;
+use std::fmt;
+use crate::error_codes::*;
+use crate::verify::MAX_RECOVERY_ID;
+
+/// Errors returned by the program as `ProgramError::Custom(code)`, so a client can tell
+/// a signature that could not be recovered apart from one signed by the wrong key. The codes are
+/// pinned in `error_codes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The public key could not be recovered from the signature.
+    RecoveryFailed,
+    /// The recovered public key does not match the expected public key.
+    PublicKeyMismatch,
+    /// The instruction data could not be decoded into a signature package.
+    MalformedPackage,
+    /// The recovery ID is not one secp256k1 recovery accepts.
+    InvalidRecoveryId,
+    /// A batch holds more packages than `MAX_BATCH_SIZE`.
+    BatchTooLarge,
+    /// The signer is not the admin stored in the config.
+    Unauthorized,
+    /// The registry already holds `MAX_REGISTERED_SIGNERS` signers.
+    RegistryFull,
+    /// The signer is already in the registry.
+    SignerAlreadyRegistered,
+    /// The signer is not in the registry.
+    SignerNotRegistered,
+    /// The Ethereum address derived from the recovered public key does not match the expected address.
+    EthAddressMismatch,
+    /// The package's nonce is not greater than the last nonce accepted for the signer.
+    StaleNonce,
+    /// The current time is past the package's `expiry_unix`.
+    SignatureExpired,
+    /// Fewer than `threshold` distinct registered signers signed the data.
+    ThresholdNotMet,
+    /// More than one package in a threshold instruction was signed by the same key.
+    DuplicateSigner,
+    /// The packages in a threshold instruction don't all sign the same data.
+    DataMismatch,
+    /// The signature's `s` value is in the upper half of the curve order.
+    MalleableSignature,
+    /// The instruction's mode is disabled in the config's `enabled_modes`.
+    ModeDisabled,
+    /// The recovered public key is not a member of the committee.
+    NotCommitteeMember,
+    /// `AcceptAdmin` was sent with no admin proposed in the config.
+    NoPendingAdmin,
+    /// A `VerifySlotBound` slot is ahead of the current slot or more than `max_slot_age` behind it.
+    SlotOutOfRange,
+    /// The message is longer than `MAX_MESSAGE_LEN`.
+    MessageTooLarge,
+    /// The verified data doesn't satisfy the config's `data_rule`.
+    InvalidData,
+}
+
+impl VerifyError {
+    /// The error for a `ProgramError::Custom` code returned by the program, or `None` if the code
+    /// isn't one of the program's.
+    pub fn from_code(code: u32) -> Option<Self> {
+        let error = match code {
+            RECOVERY_FAILED => VerifyError::RecoveryFailed,
+            PUBLIC_KEY_MISMATCH => VerifyError::PublicKeyMismatch,
+            MALFORMED_PACKAGE => VerifyError::MalformedPackage,
+            INVALID_RECOVERY_ID => VerifyError::InvalidRecoveryId,
+            BATCH_TOO_LARGE => VerifyError::BatchTooLarge,
+            UNAUTHORIZED => VerifyError::Unauthorized,
+            REGISTRY_FULL => VerifyError::RegistryFull,
+            SIGNER_ALREADY_REGISTERED => VerifyError::SignerAlreadyRegistered,
+            SIGNER_NOT_REGISTERED => VerifyError::SignerNotRegistered,
+            ETH_ADDRESS_MISMATCH => VerifyError::EthAddressMismatch,
+            STALE_NONCE => VerifyError::StaleNonce,
+            SIGNATURE_EXPIRED => VerifyError::SignatureExpired,
+            THRESHOLD_NOT_MET => VerifyError::ThresholdNotMet,
+            DUPLICATE_SIGNER => VerifyError::DuplicateSigner,
+            DATA_MISMATCH => VerifyError::DataMismatch,
+            MALLEABLE_SIGNATURE => VerifyError::MalleableSignature,
+            MODE_DISABLED => VerifyError::ModeDisabled,
+            NOT_COMMITTEE_MEMBER => VerifyError::NotCommitteeMember,
+            NO_PENDING_ADMIN => VerifyError::NoPendingAdmin,
+            SLOT_OUT_OF_RANGE => VerifyError::SlotOutOfRange,
+            MESSAGE_TOO_LARGE => VerifyError::MessageTooLarge,
+            INVALID_DATA => VerifyError::InvalidData,
+            _ => return None,
+        };
+        Some(error)
+    }
+
+    /// The error's `ProgramError::Custom` code, its constant in `error_codes`.
+    pub fn code(self) -> u32 {
+        match self {
+            VerifyError::RecoveryFailed => RECOVERY_FAILED,
+            VerifyError::PublicKeyMismatch => PUBLIC_KEY_MISMATCH,
+            VerifyError::MalformedPackage => MALFORMED_PACKAGE,
+            VerifyError::InvalidRecoveryId => INVALID_RECOVERY_ID,
+            VerifyError::BatchTooLarge => BATCH_TOO_LARGE,
+            VerifyError::Unauthorized => UNAUTHORIZED,
+            VerifyError::RegistryFull => REGISTRY_FULL,
+            VerifyError::SignerAlreadyRegistered => SIGNER_ALREADY_REGISTERED,
+            VerifyError::SignerNotRegistered => SIGNER_NOT_REGISTERED,
+            VerifyError::EthAddressMismatch => ETH_ADDRESS_MISMATCH,
+            VerifyError::StaleNonce => STALE_NONCE,
+            VerifyError::SignatureExpired => SIGNATURE_EXPIRED,
+            VerifyError::ThresholdNotMet => THRESHOLD_NOT_MET,
+            VerifyError::DuplicateSigner => DUPLICATE_SIGNER,
+            VerifyError::DataMismatch => DATA_MISMATCH,
+            VerifyError::MalleableSignature => MALLEABLE_SIGNATURE,
+            VerifyError::ModeDisabled => MODE_DISABLED,
+            VerifyError::NotCommitteeMember => NOT_COMMITTEE_MEMBER,
+            VerifyError::NoPendingAdmin => NO_PENDING_ADMIN,
+            VerifyError::SlotOutOfRange => SLOT_OUT_OF_RANGE,
+            VerifyError::MessageTooLarge => MESSAGE_TOO_LARGE,
+            VerifyError::InvalidData => INVALID_DATA,
+        }
+    }
+}
+
+impl From<VerifyError> for ProgramError {
+    fn from(error: VerifyError) -> Self {
+        ProgramError::Custom(error.code())
+    }
+}
+
+/// Why bytes couldn't be parsed into a `SignaturePackage` with `TryFrom<&[u8]>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageParseError {
+    /// The input isn't `SignaturePackage::LEN` bytes long.
+    Length { expected: usize, actual: usize },
+    /// The public key doesn't start with the 0x04 uncompressed prefix.
+    PublicKeyPrefix(u8),
+    /// The recovery id is above `MAX_RECOVERY_ID`.
+    RecoveryId(u8),
+}
+
+impl fmt::Display for PackageParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageParseError::Length { expected, actual } => {
+                write!(f, "package must be {} bytes, got {}", expected, actual)
+            }
+            PackageParseError::PublicKeyPrefix(prefix) => {
+                write!(f, "public key must start with 0x04, got {:#04x}", prefix)
+            }
+            PackageParseError::RecoveryId(recovery_id) => {
+                write!(f, "recovery id must be at most {}, got {}", MAX_RECOVERY_ID, recovery_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PackageParseError {}
+
+/// The program's error for a package that fails to parse.
+impl From<PackageParseError> for VerifyError {
+    fn from(error: PackageParseError) -> Self {
+        match error {
+            PackageParseError::Length { .. } | PackageParseError::PublicKeyPrefix(_) => VerifyError::MalformedPackage,
+            PackageParseError::RecoveryId(_) => VerifyError::InvalidRecoveryId,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every variant with the code it is pinned to.
+    const PINNED_CODES: [(VerifyError, u32); 22] = [
+        (VerifyError::RecoveryFailed, 0),
+        (VerifyError::PublicKeyMismatch, 1),
+        (VerifyError::MalformedPackage, 2),
+        (VerifyError::InvalidRecoveryId, 3),
+        (VerifyError::BatchTooLarge, 4),
+        (VerifyError::Unauthorized, 5),
+        (VerifyError::RegistryFull, 6),
+        (VerifyError::SignerAlreadyRegistered, 7),
+        (VerifyError::SignerNotRegistered, 8),
+        (VerifyError::EthAddressMismatch, 9),
+        (VerifyError::StaleNonce, 10),
+        (VerifyError::SignatureExpired, 11),
+        (VerifyError::ThresholdNotMet, 12),
+        (VerifyError::DuplicateSigner, 13),
+        (VerifyError::DataMismatch, 14),
+        (VerifyError::MalleableSignature, 15),
+        (VerifyError::ModeDisabled, 16),
+        (VerifyError::NotCommitteeMember, 17),
+        (VerifyError::NoPendingAdmin, 18),
+        (VerifyError::SlotOutOfRange, 19),
+        (VerifyError::MessageTooLarge, 20),
+        (VerifyError::InvalidData, 21),
+    ];
+
+    #[test]
+    fn verify_errors_map_to_their_custom_codes() {
+        for (error, code) in PINNED_CODES {
+            assert_eq!(ProgramError::from(error), ProgramError::Custom(code), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn codes_round_trip_for_every_variant() {
+        for (error, _) in PINNED_CODES {
+            assert_eq!(VerifyError::from_code(error.code()), Some(error));
+        }
+    }
+
+    #[test]
+    fn unknown_codes_are_not_program_errors() {
+        for code in [22, 100, u32::MAX] {
+            assert_eq!(VerifyError::from_code(code), None);
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! The `ProgramError::Custom` code of each `VerifyError`, pinned so clients can match on the
+//! numbers. `VerifyError::code` and `VerifyError::from_code` map between the variants and these
+//! constants instead of the enum's declaration order, so reordering or inserting a variant can't
+//! renumber an error. A new variant takes the next unused code, and a code is never reused, even
+//! if its variant is removed.
+
+pub const RECOVERY_FAILED: u32 = 0;
+pub const PUBLIC_KEY_MISMATCH: u32 = 1;
+pub const MALFORMED_PACKAGE: u32 = 2;
+pub const INVALID_RECOVERY_ID: u32 = 3;
+pub const BATCH_TOO_LARGE: u32 = 4;
+pub const UNAUTHORIZED: u32 = 5;
+pub const REGISTRY_FULL: u32 = 6;
+pub const SIGNER_ALREADY_REGISTERED: u32 = 7;
+pub const SIGNER_NOT_REGISTERED: u32 = 8;
+pub const ETH_ADDRESS_MISMATCH: u32 = 9;
+pub const STALE_NONCE: u32 = 10;
+pub const SIGNATURE_EXPIRED: u32 = 11;
+pub const THRESHOLD_NOT_MET: u32 = 12;
+pub const DUPLICATE_SIGNER: u32 = 13;
+pub const DATA_MISMATCH: u32 = 14;
+pub const MALLEABLE_SIGNATURE: u32 = 15;
+pub const MODE_DISABLED: u32 = 16;
+pub const NOT_COMMITTEE_MEMBER: u32 = 17;
+pub const NO_PENDING_ADMIN: u32 = 18;
+pub const SLOT_OUT_OF_RANGE: u32 = 19;
+pub const MESSAGE_TOO_LARGE: u32 = 20;
+pub const INVALID_DATA: u32 = 21;
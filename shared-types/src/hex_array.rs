@@ -0,0 +1,16 @@
+//! Serde helpers writing fixed-size byte arrays as hex strings, so packages saved as JSON can be
+//! read and edited by hand.
+
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer, const N: usize>(bytes: &[u8; N], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(bytes))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error> {
+    let hex_bytes = String::deserialize(deserializer)?;
+    let bytes = hex::decode(&hex_bytes).map_err(de::Error::custom)?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| de::Error::custom(format!("expected {} bytes, got {}", N, bytes.len())))
+}
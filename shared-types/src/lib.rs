@@ -0,0 +1,620 @@
+//! Wire format and error codes shared by the signature verification program and its clients.
+//! Both sides depend on these definitions so the Borsh layout of the instructions, the signed
+//! message and the signature checks can't drift apart.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::pubkey::Pubkey;
+
+mod error;
+pub mod error_codes;
+pub mod state;
+pub mod verify;
+
+#[cfg(feature = "serde")]
+pub mod hex_array;
+
+pub use error::{PackageParseError, VerifyError};
+pub use state::{ProgramState, ProgramStateV1, ProgramStateV2, STATE_VERSION};
+pub use verify::{
+    chain_id_from_genesis_hash, check_not_degenerate, check_valid_point, ct_eq, hash_message, is_low_s, parse_pubkey, pubkey_to_eth_address,
+    nonce_message_hash, record_message_hash, signed_message_hash, slot_bound_message_hash, verify_batch_local, verify_package_locally, verify_prehashed_locally, MAX_RECOVERY_ID,
+};
+#[cfg(feature = "rayon")]
+pub use verify::verify_batch_local_parallel;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Prefix of every `SignaturePackage` signed message, followed by the program id.
+pub const DOMAIN_SEPARATOR: &[u8] = b"solana-secp-verify-v1";
+
+/// Prefix of every `VerifySlotBound` signed message, followed by the program id.
+pub const SLOT_DOMAIN_SEPARATOR: &[u8] = b"solana-secp-verify-slot-v1";
+
+/// Prefix of every `VerifyRecord` signed message, followed by the program id.
+pub const RECORD_DOMAIN_SEPARATOR: &[u8] = b"solana-secp-verify-record-v1";
+
+/// Prefix of every `VerifySigWithNonce` signed message, followed by the program id.
+pub const NONCE_DOMAIN_SEPARATOR: &[u8] = b"solana-secp-verify-nonce-v1";
+
+/// `SignaturePackage::hash_algo` value for Keccak-256, as used by Ethereum.
+pub const HASH_ALGO_KECCAK256: u8 = 0;
+/// `SignaturePackage::hash_algo` value for SHA-256.
+pub const HASH_ALGO_SHA256: u8 = 1;
+
+/// Maximum length of the `message` of a `SignaturePackageVar` or `VerifyPersonalSign`, rejected
+/// with `MessageTooLarge` before it is hashed. Hashing itself is cheap, the Keccak-256 syscall
+/// costs 85 compute units plus one per 2 bytes, about 600 units at this limit. A transaction is at
+/// most 1232 bytes, which caps a message sent directly at roughly 900 bytes, so the limit matters
+/// for programs invoking this one through CPI, which can pass up to 10 KiB of instruction data.
+pub const MAX_MESSAGE_LEN: usize = 1024;
+
+/// Maximum number of packages accepted by a single `VerifySigBatch` instruction, bounding the
+/// compute spent on recovery. Clients splitting a larger batch across transactions use it as the
+/// upper bound of a chunk.
+pub const MAX_BATCH_SIZE: usize = 16;
+
+/// Seed prefix of the state PDA, derived as `[STATE_SEED, payer]`.
+pub const STATE_SEED: &[u8] = b"state";
+
+/// Seed of the config PDA holding the program's admin authority.
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/// Seed of the registry PDA holding the authorized signers.
+pub const REGISTRY_SEED: &[u8] = b"registry";
+
+/// Seed prefix of a signer's nonce PDA, derived as `[NONCE_SEED, x, y]` from the signer's
+/// 64-byte public key.
+pub const NONCE_SEED: &[u8] = b"nonce";
+
+/// Seed of the audit PDA holding the log of recent verifications.
+pub const AUDIT_SEED: &[u8] = b"audit";
+
+/// `enabled_modes` value with every instruction enabled, the config's initial value.
+pub const ALL_MODES_ENABLED: u32 = u32::MAX;
+
+/// Number of `ProgramInstruction` variants, one more than the last discriminant.
+pub const INSTRUCTION_COUNT: u8 = 27;
+
+// Every instruction has a bit in the `u32` `enabled_modes`, so `mode_bit` can't shift past it
+const _: () = assert!(INSTRUCTION_COUNT as u32 <= u32::BITS, "enabled_modes has no bit left for another instruction");
+
+/// The signature covers the `hash_algo` hash of
+/// `DOMAIN_SEPARATOR || program_id || payer || data || expiry_unix || chain_id || tag` (expiry and
+/// chain id little-endian), so the expiry and tag can't be changed and the package can't be used
+/// with another program, submitted by another payer or replayed on another cluster. The chain id
+/// isn't part of the package, the program reads it from its config.
+///
+/// Borsh writes the fields in declaration order with no padding, so a package is always
+/// `SignaturePackage::LEN` bytes:
+///
+/// | Offset | Length | Field                                   |
+/// |--------|--------|-----------------------------------------|
+/// | 0      | 64     | `verifier_signature` (r \|\| s)          |
+/// | 64     | 1      | `recovery_id`                           |
+/// | 65     | 65     | `public_key` (0x04 \|\| x \|\| y)         |
+/// | 130    | 32     | `data`                                  |
+/// | 162    | 8      | `expiry_unix`, little-endian            |
+/// | 170    | 1      | `hash_algo`                             |
+/// | 171    | 8      | `tag`                                   |
+///
+/// Reordering or resizing a field changes this layout and breaks every client already deployed.
+///
+/// With the `serde` feature it can also be written as JSON, with the byte arrays hex-encoded.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SignaturePackage {
+    /// `r || s`, each a 32-byte big-endian integer, the order `libsecp256k1::Signature::serialize`
+    /// writes and the `secp256k1_recover` syscall reads.
+    #[cfg_attr(feature = "serde", serde(with = "hex_array"))]
+    pub verifier_signature: [u8; 64],
+    pub recovery_id: u8,
+    /// `0x04 || x || y`, each coordinate 32 bytes big-endian.
+    #[cfg_attr(feature = "serde", serde(with = "hex_array"))]
+    pub public_key: [u8; 65],
+    #[cfg_attr(feature = "serde", serde(with = "hex_array"))]
+    pub data: [u8; 32],
+    /// Unix timestamp after which the package is rejected.
+    pub expiry_unix: i64,
+    /// Hash applied to the signed message, `HASH_ALGO_KECCAK256` or `HASH_ALGO_SHA256`.
+    pub hash_algo: u8,
+    /// Application-defined context, such as the report or epoch the signature covers. Signed with
+    /// the data and stored next to it in the payer's state, all zeros when unused.
+    #[cfg_attr(feature = "serde", serde(with = "hex_array"))]
+    pub tag: [u8; 8],
+}
+
+impl SignaturePackage {
+    /// Size of the Borsh-serialized package in bytes.
+    pub const LEN: usize = 64 + 1 + 65 + 32 + 8 + 1 + 8;
+}
+
+/// Parses a Borsh-serialized package, also rejecting content Borsh accepts but no valid package
+/// has: a length other than `LEN`, a public key without the 0x04 prefix and a recovery id above
+/// `MAX_RECOVERY_ID`. The signature itself isn't checked, see `verify_package_locally`.
+impl TryFrom<&[u8]> for SignaturePackage {
+    type Error = PackageParseError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let length_error = PackageParseError::Length { expected: Self::LEN, actual: bytes.len() };
+        if bytes.len() != Self::LEN {
+            return Err(length_error);
+        }
+        // Every field has a fixed size, so decoding exactly LEN bytes can't fail
+        let package = Self::try_from_slice(bytes).map_err(|_| length_error)?;
+
+        if package.public_key[0] != 0x04 {
+            return Err(PackageParseError::PublicKeyPrefix(package.public_key[0]));
+        }
+        if package.recovery_id > MAX_RECOVERY_ID {
+            return Err(PackageParseError::RecoveryId(package.recovery_id));
+        }
+        Ok(package)
+    }
+}
+
+/// A `SignaturePackage` with a 33-byte compressed public key (0x02 or 0x03 || x) instead of the
+/// 65-byte uncompressed form. The signed message is the same as for `SignaturePackage`.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignaturePackageCompressed {
+    pub verifier_signature: [u8; 64],
+    pub recovery_id: u8,
+    pub public_key: [u8; 33],
+    pub data: [u8; 32],
+    pub expiry_unix: i64,
+    pub hash_algo: u8,
+    pub tag: [u8; 8],
+}
+
+/// A `SignaturePackage` carrying a nonce for replay protection. The signature covers
+/// `nonce_message_hash` of `data` and `nonce`, which binds them to the program, payer and
+/// cluster, and the nonce must be greater than the last one accepted for the signer.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignaturePackageV2 {
+    pub verifier_signature: [u8; 64],
+    pub recovery_id: u8,
+    pub public_key: [u8; 65],
+    pub data: [u8; 32],
+    pub nonce: u64,
+}
+
+/// A package signing an arbitrary-length `message` instead of 32 bytes of data. The signature
+/// covers the message a `SignaturePackage` signs, with the Keccak-256 hash of `message` as the
+/// data, an all-zero tag and Keccak-256 as the hash algorithm, so it is bound to the program,
+/// payer and cluster like any other package.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignaturePackageVar {
+    pub verifier_signature: [u8; 64],
+    pub recovery_id: u8,
+    pub public_key: [u8; 65],
+    pub message: Vec<u8>,
+    pub expiry_unix: i64,
+}
+
+/// Rule the verified `data` must satisfy before the program stores it, set by the admin with
+/// `SetDataRule` and kept in the config.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DataRule {
+    /// Any 32 bytes, the initial rule.
+    #[default]
+    Any,
+    /// Anything but 32 zero bytes.
+    NonZero,
+    /// The first byte, the most significant for a big-endian value, must be this marker.
+    LeadingByte(u8),
+}
+
+impl DataRule {
+    /// Whether `data` satisfies the rule.
+    pub fn accepts(&self, data: &[u8; 32]) -> bool {
+        match self {
+            DataRule::Any => true,
+            DataRule::NonZero => data.iter().any(|&byte| byte != 0),
+            DataRule::LeadingByte(marker) => data[0] == *marker,
+        }
+    }
+}
+
+/// A structured attestation, signed in its canonical Borsh encoding: `epoch` as 8 bytes
+/// little-endian followed by the 32 bytes of `root`. The signer and the program both go through
+/// Borsh, so they can't disagree on the layout, and the program only accepts bytes that decode to
+/// exactly this shape, `LEN` bytes with the fields in this order.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttestationRecord {
+    /// Epoch, or any other sequence number, the attestation is for.
+    pub epoch: u64,
+    /// The attested value, such as a Merkle root.
+    pub root: [u8; 32],
+}
+
+impl AttestationRecord {
+    /// Length of the Borsh encoding.
+    pub const LEN: usize = 8 + 32;
+}
+
+/// Instructions supported by the program. New variants must be appended, the Borsh discriminant
+/// is the variant's index. An instruction is serialized as that one-byte discriminant followed by
+/// the variant's fields, so `VerifySig` is `0x00` followed by the `SignaturePackage::LEN` bytes of
+/// the package, and a `Vec` field is written as a `u32` little-endian length followed by its
+/// elements. Every verification stores the verified data in the payer's state PDA,
+/// created by `InitializeState`, so each verification variant expects the accounts:
+///
+/// 0. `[signer, writable]` Payer, funds the state account if it has to grow.
+/// 1. `[writable]` State PDA derived from `[STATE_SEED, payer]`.
+/// 2. `[]` System program.
+/// 3. `[writable]` Config PDA derived from `[CONFIG_SEED]`, holding the global verification count.
+/// 4. `[writable]` Audit PDA derived from `[AUDIT_SEED]`, where the verification is recorded.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ProgramInstruction {
+    VerifySig(SignaturePackage),
+    VerifySigBatch(Vec<SignaturePackage>),
+    /// Verifies the package against the public key stored in an account, ignoring the
+    /// package's own `public_key`. The public key account comes before the state accounts.
+    VerifySigFromAccount(SignaturePackage),
+    /// Creates the config, registry and audit PDAs with the signer as admin, binding the program
+    /// to the cluster with `chain_id`, usually `chain_id_from_genesis_hash` of its genesis hash.
+    /// The signer must be the program's upgrade authority. See the program's
+    /// `admin::initialize_config`.
+    InitializeConfig { chain_id: u64 },
+    /// Adds a 64-byte public key (x || y) to the registry. Admin only, see the program's
+    /// `admin::register_signer`.
+    RegisterSigner { pubkey: [u8; 64] },
+    /// Removes a 64-byte public key from the registry. Admin only, see the program's
+    /// `admin::revoke_signer`.
+    RevokeSigner { pubkey: [u8; 64] },
+    /// Verifies that the recovered public key is in the registry, ignoring the package's own
+    /// `public_key`. The registry PDA comes before the state accounts.
+    VerifySigAgainstRegistry(SignaturePackage),
+    /// Verifies a signature over `data` against a 20-byte Ethereum address rather than a full
+    /// public key. The signed message is the one a `SignaturePackage` signs, hashed with
+    /// Keccak-256 and without a tag, so the signature is bound to the program, payer and cluster
+    /// and expires at `expiry_unix`.
+    VerifyEthSig {
+        signature: [u8; 64],
+        recovery_id: u8,
+        eth_address: [u8; 20],
+        data: [u8; 32],
+        expiry_unix: i64,
+    },
+    /// Verifies a nonce-carrying package and records the nonce so the package can't be replayed.
+    /// The signer's nonce PDA, derived from `[NONCE_SEED, x, y]`, comes before the state accounts.
+    VerifySigWithNonce(SignaturePackageV2),
+    /// Verifies that at least `threshold` distinct registered signers signed the same data. Takes
+    /// the same accounts as `VerifySigAgainstRegistry`.
+    VerifyThreshold {
+        packages: Vec<SignaturePackage>,
+        threshold: u8,
+    },
+    /// Verifies a signature over an arbitrary-length message. The message's Keccak-256 hash is
+    /// stored as the verified data.
+    VerifySigVar(SignaturePackageVar),
+    /// Verifies a package carrying a compressed public key. Takes the same accounts as `VerifySig`.
+    VerifySigCompressed(SignaturePackageCompressed),
+    /// Creates the payer's state PDA. Must be sent before the payer's first verification.
+    InitializeState,
+    /// Verifies the package like `VerifySig`, then invokes a target program with the verified
+    /// `data` as its instruction data. The target program follows the state accounts, and any
+    /// remaining accounts are passed through to it.
+    VerifyAndForward(SignaturePackage),
+    /// Verifies a signature made with Ethereum's `personal_sign` (EIP-191) over `message`, against
+    /// the signer's 20-byte Ethereum address. `recovery_id` is the wallet's `v` minus 27. The
+    /// EIP-191 hash of the message is stored as the verified data.
+    VerifyPersonalSign {
+        signature: [u8; 64],
+        recovery_id: u8,
+        eth_address: [u8; 20],
+        message: Vec<u8>,
+    },
+    /// Sets the config's `enabled_modes`. Admin only.
+    ///
+    /// Accounts:
+    /// 0. `[signer]` Admin.
+    /// 1. `[writable]` Config PDA.
+    SetEnabledModes { mask: u32 },
+    /// Verifies the package like `VerifySig`, and that the recovered key is a member of a
+    /// committee account. The committee account, owned by the program, comes before the state
+    /// accounts.
+    VerifyAgainstCommittee(SignaturePackage),
+    /// Verifies a package whose `data` is already the 32-byte message digest, recovering the
+    /// signer from it directly instead of hashing it with the domain separator, program id,
+    /// payer and expiry. For digests produced by other systems; `expiry_unix`, `hash_algo`
+    /// and `tag` are ignored since they aren't signed. Takes the same accounts as `VerifySig`.
+    VerifyPrehashed(SignaturePackage),
+    /// Proposes `new_admin` as the next admin, stored in the config as pending until it accepts
+    /// with `AcceptAdmin`. Admin only. Proposing again replaces the pending admin.
+    ///
+    /// Accounts:
+    /// 0. `[signer]` Admin.
+    /// 1. `[writable]` Config PDA.
+    ProposeAdmin { new_admin: Pubkey },
+    /// Makes the pending admin the admin. Must be signed by the pending admin, so control is
+    /// never handed to a key nobody holds.
+    ///
+    /// Accounts:
+    /// 0. `[signer]` Pending admin.
+    /// 1. `[writable]` Config PDA.
+    AcceptAdmin,
+    /// Verifies a signature made by a tool that doesn't report the recovery id, trying recovery
+    /// ids 0 and 1 until one recovers `public_key`. The signed message is the one a
+    /// `SignaturePackage` signs, hashed with Keccak-256 and without a tag, so the signature is
+    /// bound to the program, payer and cluster. Costs up to two recoveries. Takes the same accounts
+    /// as `VerifySig`.
+    VerifySigAnyRecovery {
+        signature: [u8; 64],
+        public_key: [u8; 65],
+        data: [u8; 32],
+        expiry_unix: i64,
+    },
+    /// Verifies a signature over an EIP-712 typed-data `digest`,
+    /// `keccak256(0x19 || 0x01 || domainSeparator || hashStruct(message))`, against the signer's
+    /// 20-byte Ethereum address. The digest is computed off-chain and only recovered from here,
+    /// so any domain and struct type can be verified. The digest is stored as the verified data.
+    /// Takes the same accounts as `VerifySig`.
+    VerifyTypedData {
+        digest: [u8; 32],
+        signature: [u8; 64],
+        recovery_id: u8,
+        eth_address: [u8; 20],
+    },
+    /// Verifies a signature over `data` bound to a recent `slot`, for attestations such as oracle
+    /// reports that go stale. The signature covers `slot_bound_message_hash` of the fields, so the
+    /// signer chooses `max_slot_age`, and the instruction fails with `SlotOutOfRange` unless
+    /// `slot` is at most the current slot and at most `max_slot_age` slots behind it. Takes the
+    /// same accounts as `VerifySig`.
+    VerifySlotBound {
+        signature: [u8; 64],
+        recovery_id: u8,
+        public_key: [u8; 65],
+        data: [u8; 32],
+        slot: u64,
+        max_slot_age: u64,
+    },
+    /// Sets the rule verified data must satisfy, checked after the signature verifies and before
+    /// the state is updated. Admin only.
+    ///
+    /// Accounts:
+    /// 0. `[signer]` Admin.
+    /// 1. `[writable]` Config PDA.
+    SetDataRule { rule: DataRule },
+    /// Verifies that two packages were signed by the same key, such as a commitment and its
+    /// reveal. Both are recovered and the recovered keys must be identical, failing with
+    /// `PublicKeyMismatch` otherwise; the packages' `public_key` fields are ignored. The two
+    /// signatures must differ. `pkg_b`'s data and tag are stored as the verified data and the
+    /// shared key is returned as the signer. Takes the same accounts as `VerifySig`.
+    VerifySameSigner {
+        pkg_a: SignaturePackage,
+        pkg_b: SignaturePackage,
+    },
+    /// Verifies the package like `VerifySig`, then closes a temporary account: its data is zeroed
+    /// and all its lamports are moved to the payer. The account follows the state accounts, has
+    /// to be owned by this program and has to sign, so only its holder can have it closed.
+    VerifyAndClose(SignaturePackage),
+    /// Verifies a signature over an `AttestationRecord`. `record` is the record's Borsh encoding,
+    /// and the signature covers `record_message_hash` of it. The program decodes it and fails with
+    /// `MalformedPackage` unless it is exactly one record, so a truncated, extended or reordered
+    /// encoding is rejected before recovery. The record's `root` is stored as the verified data
+    /// and its `epoch`, little-endian, as the tag. Takes the same accounts as `VerifySig`.
+    VerifyRecord {
+        signature: [u8; 64],
+        recovery_id: u8,
+        public_key: [u8; 65],
+        record: Vec<u8>,
+    },
+}
+
+impl ProgramInstruction {
+    /// The instruction's Borsh discriminant, the index of its variant.
+    pub fn discriminant(&self) -> u8 {
+        match self {
+            ProgramInstruction::VerifySig(_) => 0,
+            ProgramInstruction::VerifySigBatch(_) => 1,
+            ProgramInstruction::VerifySigFromAccount(_) => 2,
+            ProgramInstruction::InitializeConfig { .. } => 3,
+            ProgramInstruction::RegisterSigner { .. } => 4,
+            ProgramInstruction::RevokeSigner { .. } => 5,
+            ProgramInstruction::VerifySigAgainstRegistry(_) => 6,
+            ProgramInstruction::VerifyEthSig { .. } => 7,
+            ProgramInstruction::VerifySigWithNonce(_) => 8,
+            ProgramInstruction::VerifyThreshold { .. } => 9,
+            ProgramInstruction::VerifySigVar(_) => 10,
+            ProgramInstruction::VerifySigCompressed(_) => 11,
+            ProgramInstruction::InitializeState => 12,
+            ProgramInstruction::VerifyAndForward(_) => 13,
+            ProgramInstruction::VerifyPersonalSign { .. } => 14,
+            ProgramInstruction::SetEnabledModes { .. } => 15,
+            ProgramInstruction::VerifyAgainstCommittee(_) => 16,
+            ProgramInstruction::VerifyPrehashed(_) => 17,
+            ProgramInstruction::ProposeAdmin { .. } => 18,
+            ProgramInstruction::AcceptAdmin => 19,
+            ProgramInstruction::VerifySigAnyRecovery { .. } => 20,
+            ProgramInstruction::VerifyTypedData { .. } => 21,
+            ProgramInstruction::VerifySlotBound { .. } => 22,
+            ProgramInstruction::SetDataRule { .. } => 23,
+            ProgramInstruction::VerifySameSigner { .. } => 24,
+            ProgramInstruction::VerifyAndClose(_) => 25,
+            ProgramInstruction::VerifyRecord { .. } => 26,
+        }
+    }
+
+    /// The instruction's bit in the config's `enabled_modes` mask, `1 << discriminant`. There are
+    /// at most 32 variants, `INSTRUCTION_COUNT` is checked at compile time.
+    pub fn mode_bit(&self) -> u32 {
+        1 << self.discriminant()
+    }
+
+    /// Whether the instruction can be disabled with `SetEnabledModes`. The setup and admin
+    /// instructions can't, so the admin can always turn modes back on.
+    pub fn is_toggleable(&self) -> bool {
+        !matches!(
+            self,
+            ProgramInstruction::InitializeConfig { .. }
+                | ProgramInstruction::RegisterSigner { .. }
+                | ProgramInstruction::RevokeSigner { .. }
+                | ProgramInstruction::InitializeState
+                | ProgramInstruction::SetEnabledModes { .. }
+                | ProgramInstruction::ProposeAdmin { .. }
+                | ProgramInstruction::AcceptAdmin
+                | ProgramInstruction::SetDataRule { .. }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package() -> SignaturePackage {
+        SignaturePackage {
+            verifier_signature: [0x01; 64],
+            recovery_id: 1,
+            public_key: [0x04; 65],
+            data: [0x33; 32],
+            expiry_unix: 1_700_000_000,
+            hash_algo: HASH_ALGO_SHA256,
+            tag: [0x07; 8],
+        }
+    }
+
+    #[test]
+    fn signature_package_round_trips_through_borsh() {
+        let bytes = borsh::to_vec(&package()).unwrap();
+
+        assert_eq!(bytes.len(), 64 + 1 + 65 + 32 + 8 + 1 + 8);
+        assert_eq!(SignaturePackage::try_from_slice(&bytes).unwrap(), package());
+    }
+
+    #[test]
+    fn try_from_parses_exactly_one_valid_package() {
+        let bytes = borsh::to_vec(&package()).unwrap();
+        assert_eq!(SignaturePackage::try_from(&bytes[..]), Ok(package()));
+
+        let length = |actual| Err(PackageParseError::Length { expected: SignaturePackage::LEN, actual });
+        assert_eq!(SignaturePackage::try_from(&bytes[..bytes.len() - 1]), length(bytes.len() - 1));
+        assert_eq!(SignaturePackage::try_from(&[&bytes[..], &[0]].concat()[..]), length(bytes.len() + 1));
+        assert_eq!(SignaturePackage::try_from(&[][..]), length(0));
+    }
+
+    #[test]
+    fn try_from_rejects_a_bad_prefix_or_recovery_id() {
+        let mut prefix = package();
+        prefix.public_key[0] = 0x02;
+        let bytes = borsh::to_vec(&prefix).unwrap();
+        assert_eq!(SignaturePackage::try_from(&bytes[..]), Err(PackageParseError::PublicKeyPrefix(0x02)));
+
+        let mut recovery_id = package();
+        recovery_id.recovery_id = MAX_RECOVERY_ID + 1;
+        let bytes = borsh::to_vec(&recovery_id).unwrap();
+        assert_eq!(SignaturePackage::try_from(&bytes[..]), Err(PackageParseError::RecoveryId(MAX_RECOVERY_ID + 1)));
+        assert_eq!(VerifyError::from(PackageParseError::RecoveryId(4)), VerifyError::InvalidRecoveryId);
+    }
+
+    #[test]
+    fn data_rules_accept_only_data_of_their_shape() {
+        let mut marked = [0; 32];
+        marked[0] = 0x01;
+
+        assert!(DataRule::default().accepts(&[0; 32]));
+        assert!(!DataRule::NonZero.accepts(&[0; 32]));
+        assert!(DataRule::NonZero.accepts(&marked));
+        assert!(DataRule::LeadingByte(0x01).accepts(&marked));
+        assert!(!DataRule::LeadingByte(0x01).accepts(&[0x02; 32]));
+    }
+
+    #[test]
+    fn attestation_record_encodes_the_epoch_then_the_root() {
+        let record = AttestationRecord { epoch: 0x0102, root: [0x33; 32] };
+
+        let bytes = borsh::to_vec(&record).unwrap();
+
+        assert_eq!(bytes.len(), AttestationRecord::LEN);
+        assert_eq!(bytes[..8], 0x0102u64.to_le_bytes());
+        assert_eq!(bytes[8..], [0x33; 32]);
+    }
+
+    #[test]
+    fn instructions_round_trip_through_borsh() {
+        let instructions = [
+            ProgramInstruction::VerifySig(package()),
+            ProgramInstruction::VerifySigBatch(vec![package(), package()]),
+            ProgramInstruction::VerifyThreshold { packages: vec![package()], threshold: 1 },
+            ProgramInstruction::InitializeState,
+        ];
+        for instruction in instructions {
+            let bytes = borsh::to_vec(&instruction).unwrap();
+            assert_eq!(ProgramInstruction::try_from_slice(&bytes).unwrap(), instruction);
+        }
+    }
+
+    #[test]
+    fn instruction_count_matches_the_variants() {
+        let last = ProgramInstruction::VerifyRecord { signature: [0; 64], recovery_id: 0, public_key: [0; 65], record: Vec::new() };
+        assert_eq!(last.discriminant(), INSTRUCTION_COUNT - 1);
+        assert_eq!(borsh::to_vec(&last).unwrap()[0], INSTRUCTION_COUNT - 1);
+        assert_eq!(last.mode_bit(), 1 << 26);
+
+        // A variant appended without raising `INSTRUCTION_COUNT` would be known here
+        let err = ProgramInstruction::try_from_slice(&[INSTRUCTION_COUNT]).unwrap_err();
+        assert!(err.to_string().contains("Unexpected variant tag"), "{}", err);
+    }
+
+    /// The Borsh encoding of `VerifySig`, which the program and every client must agree on byte
+    /// for byte. Enums are a one-byte variant index followed by the variant's fields, fixed-size
+    /// arrays are their bytes with no length prefix, and integers are little-endian:
+    ///
+    /// | Bytes | Field |
+    /// |-------|-------|
+    /// | 0 | Variant index, 0 for `VerifySig` |
+    /// | 1..65 | `verifier_signature` |
+    /// | 65 | `recovery_id` |
+    /// | 66..131 | `public_key` |
+    /// | 131..163 | `data` |
+    /// | 163..171 | `expiry_unix` (i64) |
+    /// | 171 | `hash_algo` |
+    /// | 172..180 | `tag` |
+    #[test]
+    fn verify_sig_borsh_layout_is_pinned() {
+        let mut public_key = [0xcc; 65];
+        public_key[0] = 0x04;
+        public_key[33..].fill(0xdd);
+        let mut verifier_signature = [0xaa; 64];
+        verifier_signature[32..].fill(0xbb);
+        let instruction = ProgramInstruction::VerifySig(SignaturePackage {
+            verifier_signature,
+            recovery_id: 1,
+            public_key,
+            data: [0x33; 32],
+            expiry_unix: 0x0102_0304_0506_0708,
+            hash_algo: HASH_ALGO_SHA256,
+            tag: [1, 2, 3, 4, 5, 6, 7, 8],
+        });
+
+        #[rustfmt::skip]
+        let expected: [u8; 180] = [
+            // Discriminant of VerifySig
+            0x00,
+            // verifier_signature, r || s
+            0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa,
+            0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa,
+            0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb,
+            0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb,
+            // recovery_id
+            0x01,
+            // public_key, 0x04 || x || y
+            0x04, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+            0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+            0xcc, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd,
+            0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd,
+            0xdd,
+            // data
+            0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33,
+            0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33,
+            // expiry_unix, little-endian
+            0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01,
+            // hash_algo
+            0x01,
+            // tag
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+        ];
+        assert_eq!(borsh::to_vec(&instruction).unwrap(), expected);
+    }
+}
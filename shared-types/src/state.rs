@@ -0,0 +1,168 @@
+//! The per-payer state account written by the program, shared so clients can read it back with
+//! the same layout.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+/// Layout version written to the first byte of a state account.
+pub const STATE_VERSION: u8 = 3;
+
+/// Verified data persisted by the program for each payer. The account holds a version byte
+/// followed by the Borsh-serialized state, read and written with `unpack` and `pack`.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProgramState {
+    pub verification_count: u64,
+    pub last_data: [u8; 32],
+    /// The payer that initialized the state.
+    pub admin: Pubkey,
+    /// The `tag` signed with `last_data`, all zeros for instructions without one.
+    pub last_tag: [u8; 8],
+}
+
+/// Version 2 of the state, before the tag was stored.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProgramStateV2 {
+    pub verification_count: u64,
+    pub last_data: [u8; 32],
+    pub admin: Pubkey,
+}
+
+impl From<ProgramStateV2> for ProgramState {
+    fn from(state: ProgramStateV2) -> Self {
+        Self {
+            verification_count: state.verification_count,
+            last_data: state.last_data,
+            admin: state.admin,
+            last_tag: [0u8; 8],
+        }
+    }
+}
+
+impl ProgramStateV2 {
+    /// Size of a version 2 state account in bytes, including the version byte.
+    pub const LEN: usize = 1 + 8 + 32 + 32;
+
+    /// Size of a state account written with this layout before the version byte was introduced.
+    pub const UNVERSIONED_LEN: usize = 8 + 32 + 32;
+}
+
+/// The first layout of the state, before the admin was stored. It was written without a version
+/// byte and is recognized by its size.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProgramStateV1 {
+    pub verification_count: u64,
+    pub last_data: [u8; 32],
+}
+
+impl From<ProgramStateV1> for ProgramState {
+    fn from(state: ProgramStateV1) -> Self {
+        Self {
+            verification_count: state.verification_count,
+            last_data: state.last_data,
+            admin: Pubkey::default(),
+            last_tag: [0u8; 8],
+        }
+    }
+}
+
+impl ProgramStateV1 {
+    /// Size of a state account written with this layout, which has no version byte.
+    pub const LEN: usize = 8 + 32;
+}
+
+impl ProgramState {
+    /// Size of a state account in bytes, the version byte followed by the Borsh-serialized state.
+    pub const LEN: usize = 1 + 8 + 32 + 32 + 8;
+
+    /// Reads a state account of any known version, upgrading older versions to the current
+    /// layout. Accounts written before the version byte was introduced are recognized by their
+    /// size, which no versioned layout shares. Fails with `InvalidAccountData` for an unknown
+    /// version.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        match data.len() {
+            ProgramStateV1::LEN => return Ok(ProgramStateV1::try_from_slice(data)?.into()),
+            ProgramStateV2::UNVERSIONED_LEN => return Ok(ProgramStateV2::try_from_slice(data)?.into()),
+            _ => {}
+        }
+        let Some((&version, mut state)) = data.split_first() else {
+            return Err(ProgramError::InvalidAccountData);
+        };
+        match version {
+            2 => Ok(ProgramStateV2::deserialize(&mut state)?.into()),
+            STATE_VERSION => Ok(ProgramState::deserialize(&mut state)?),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Writes the state in the current layout, which needs `LEN` bytes.
+    pub fn pack(&self, data: &mut [u8]) -> Result<(), ProgramError> {
+        let Some((version, state)) = data.split_first_mut() else {
+            return Err(ProgramError::AccountDataTooSmall);
+        };
+        *version = STATE_VERSION;
+        self.serialize(&mut &mut state[..])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNT: u64 = 0x0102_0304_0506_0708;
+    const DATA: [u8; 32] = [0x33; 32];
+    const ADMIN: Pubkey = Pubkey::new_from_array([0x44; 32]);
+    const TAG: [u8; 8] = [0x55; 8];
+
+    /// The bytes each layout was written with, built by hand rather than with Borsh.
+    fn blob(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn unversioned_accounts_are_read_by_their_size() {
+        let v1 = blob(&[&COUNT.to_le_bytes(), &DATA]);
+        assert_eq!(v1.len(), ProgramStateV1::LEN);
+        let state = ProgramState::unpack(&v1).unwrap();
+        assert_eq!(state, ProgramState { verification_count: COUNT, last_data: DATA, admin: Pubkey::default(), last_tag: [0; 8] });
+
+        let v2 = blob(&[&COUNT.to_le_bytes(), &DATA, ADMIN.as_ref()]);
+        assert_eq!(v2.len(), ProgramStateV2::UNVERSIONED_LEN);
+        let state = ProgramState::unpack(&v2).unwrap();
+        assert_eq!(state, ProgramState { verification_count: COUNT, last_data: DATA, admin: ADMIN, last_tag: [0; 8] });
+    }
+
+    #[test]
+    fn versioned_accounts_are_read_by_their_version_byte() {
+        let v2 = blob(&[&[2], &COUNT.to_le_bytes(), &DATA, ADMIN.as_ref()]);
+        assert_eq!(v2.len(), ProgramStateV2::LEN);
+        let state = ProgramState::unpack(&v2).unwrap();
+        assert_eq!(state, ProgramState { verification_count: COUNT, last_data: DATA, admin: ADMIN, last_tag: [0; 8] });
+
+        let v3 = blob(&[&[STATE_VERSION], &COUNT.to_le_bytes(), &DATA, ADMIN.as_ref(), &TAG]);
+        assert_eq!(v3.len(), ProgramState::LEN);
+        let state = ProgramState::unpack(&v3).unwrap();
+        assert_eq!(state, ProgramState { verification_count: COUNT, last_data: DATA, admin: ADMIN, last_tag: TAG });
+    }
+
+    #[test]
+    fn unknown_versions_and_empty_accounts_are_rejected() {
+        for version in [0, 1, STATE_VERSION + 1] {
+            let mut data = vec![0u8; ProgramState::LEN];
+            data[0] = version;
+            assert_eq!(ProgramState::unpack(&data), Err(ProgramError::InvalidAccountData), "version {}", version);
+        }
+        assert_eq!(ProgramState::unpack(&[]), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn pack_writes_the_current_version() {
+        let state = ProgramState { verification_count: COUNT, last_data: DATA, admin: ADMIN, last_tag: TAG };
+        let mut data = vec![0u8; ProgramState::LEN];
+
+        state.pack(&mut data).unwrap();
+
+        assert_eq!(data, blob(&[&[STATE_VERSION], &COUNT.to_le_bytes(), &DATA, ADMIN.as_ref(), &TAG]));
+    }
+}
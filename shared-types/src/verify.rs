@@ -0,0 +1,491 @@
+//! Signature checks shared by the program and off-chain tools. The program runs the recovery
+//! through the `secp256k1_recover` syscall, `verify_package_locally` through `libsecp256k1`, and
+//! both hash the message and screen the signature with the functions below.
+
+use libsecp256k1::{Message, PublicKey, PublicKeyFormat, RecoveryId, Signature};
+use solana_program::pubkey::Pubkey;
+use solana_program::secp256k1_recover::Secp256k1Pubkey;
+use solana_program::{hash, keccak};
+use crate::{
+    SignaturePackage, VerifyError, DOMAIN_SEPARATOR, HASH_ALGO_KECCAK256, HASH_ALGO_SHA256, NONCE_DOMAIN_SEPARATOR, RECORD_DOMAIN_SEPARATOR, SLOT_DOMAIN_SEPARATOR,
+};
+
+/// Largest recovery id accepted by secp256k1 recovery.
+pub const MAX_RECOVERY_ID: u8 = 3;
+
+/// Half the secp256k1 curve order, `n / 2`, big-endian.
+const SECP256K1_HALF_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// Returns whether the `s` half of an `r || s` signature is at most `n / 2`. Both are big-endian,
+/// so comparing the byte arrays compares the numbers.
+pub fn is_low_s(signature: &[u8; 64]) -> bool {
+    signature[32..] <= SECP256K1_HALF_ORDER[..]
+}
+
+/// Rejects a package no real signer produces, before any recovery runs: a signature whose `r` or
+/// `s` is zero, which includes the all-zero signature, and an all-zero public key or one whose
+/// coordinates are all zero, the encoding some libraries use for the point at infinity. Recovery
+/// would fail on these too, but only after paying for the syscall, and not always with an error
+/// that says the package is malformed.
+pub fn check_not_degenerate(signature: &[u8; 64], public_key: &[u8; 65]) -> Result<(), VerifyError> {
+    let is_zero = |bytes: &[u8]| bytes.iter().all(|&byte| byte == 0);
+    if is_zero(&signature[..32]) || is_zero(&signature[32..]) || is_zero(&public_key[1..]) {
+        return Err(VerifyError::MalformedPackage);
+    }
+    Ok(())
+}
+
+/// Rejects a recovered key that isn't a point on the curve with `RecoveryFailed`. The point at
+/// infinity has no `x || y` encoding and comes back as all zeros, which isn't on the curve either,
+/// so this covers it too. `secp256k1_recover` only returns points it computed on the curve, so
+/// this should never fail on the syscall's output; it guards the comparisons that follow against
+/// a runtime that ever returned something else, for a few hundred compute units.
+pub fn check_valid_point(pubkey: &Secp256k1Pubkey) -> Result<(), VerifyError> {
+    PublicKey::parse_slice(&pubkey.to_bytes(), Some(PublicKeyFormat::Raw))
+        .map(|_| ())
+        .map_err(|_| VerifyError::RecoveryFailed)
+}
+
+/// Compares two byte strings in time that depends only on their length, not on where they first
+/// differ. Public keys aren't secret, but this keeps the comparison safe if it is ever used on
+/// values derived from secrets. Slices of different lengths are never equal.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0u8, |difference, (x, y)| difference | (x ^ y));
+    difference == 0
+}
+
+/// Derives the Ethereum address of a public key: the last 20 bytes of the Keccak-256 hash of the
+/// 64-byte uncompressed key, without the 0x04 prefix.
+pub fn pubkey_to_eth_address(pubkey: &Secp256k1Pubkey) -> [u8; 20] {
+    let hash = keccak::hash(&pubkey.to_bytes());
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash.0[12..]);
+    address
+}
+
+/// Hashes the concatenation of `values` with the algorithm selected by `hash_algo`, or returns
+/// `None` for an unknown algorithm.
+pub fn hash_message(hash_algo: u8, values: &[&[u8]]) -> Option<[u8; 32]> {
+    match hash_algo {
+        HASH_ALGO_KECCAK256 => Some(keccak::hashv(values).to_bytes()),
+        HASH_ALGO_SHA256 => Some(hash::hashv(values).to_bytes()),
+        _ => None,
+    }
+}
+
+/// Hash of the message signed for a `SignaturePackage`:
+/// `DOMAIN_SEPARATOR || program_id || payer || data || expiry_unix || chain_id || tag`, or `None`
+/// for an unknown `hash_algo`.
+pub fn signed_message_hash(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    data: &[u8; 32],
+    expiry_unix: i64,
+    tag: &[u8; 8],
+    hash_algo: u8,
+) -> Option<[u8; 32]> {
+    hash_message(
+        hash_algo,
+        &[DOMAIN_SEPARATOR, program_id.as_ref(), payer.as_ref(), data, &expiry_unix.to_le_bytes(), &chain_id.to_le_bytes(), tag],
+    )
+}
+
+/// Keccak-256 hash of the message signed for a `VerifyRecord`:
+/// `RECORD_DOMAIN_SEPARATOR || program_id || payer || chain_id || record` (chain id little-endian),
+/// `record` being the `AttestationRecord`'s Borsh encoding. The encoding is hashed as sent, so the
+/// signature covers the exact bytes the program decodes.
+pub fn record_message_hash(program_id: &Pubkey, payer: &Pubkey, chain_id: u64, record: &[u8]) -> [u8; 32] {
+    keccak::hashv(&[
+        RECORD_DOMAIN_SEPARATOR,
+        program_id.as_ref(),
+        payer.as_ref(),
+        &chain_id.to_le_bytes(),
+        record,
+    ])
+    .to_bytes()
+}
+
+/// Keccak-256 hash of the message signed for a `VerifySigWithNonce`:
+/// `NONCE_DOMAIN_SEPARATOR || program_id || payer || chain_id || nonce || data` (integers
+/// little-endian). Without the program id and payer, a package seen in one transaction could be
+/// submitted first by anyone else, burning the signer's nonce.
+pub fn nonce_message_hash(program_id: &Pubkey, payer: &Pubkey, chain_id: u64, data: &[u8; 32], nonce: u64) -> [u8; 32] {
+    keccak::hashv(&[
+        NONCE_DOMAIN_SEPARATOR,
+        program_id.as_ref(),
+        payer.as_ref(),
+        &chain_id.to_le_bytes(),
+        &nonce.to_le_bytes(),
+        data,
+    ])
+    .to_bytes()
+}
+
+/// Keccak-256 hash of the message signed for a `VerifySlotBound`:
+/// `SLOT_DOMAIN_SEPARATOR || program_id || payer || data || slot || max_slot_age || chain_id`
+/// (integers little-endian). The separator differs from `DOMAIN_SEPARATOR` so a slot-bound
+/// signature can never be read as a `SignaturePackage` signature, whose message has the same length.
+pub fn slot_bound_message_hash(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    data: &[u8; 32],
+    slot: u64,
+    max_slot_age: u64,
+) -> [u8; 32] {
+    keccak::hashv(&[
+        SLOT_DOMAIN_SEPARATOR,
+        program_id.as_ref(),
+        payer.as_ref(),
+        data,
+        &slot.to_le_bytes(),
+        &max_slot_age.to_le_bytes(),
+        &chain_id.to_le_bytes(),
+    ])
+    .to_bytes()
+}
+
+/// Chain id of a cluster, the first 8 bytes of its genesis hash read little-endian. Every cluster
+/// has its own genesis hash, so a package signed for one cluster doesn't verify on another.
+pub fn chain_id_from_genesis_hash(genesis_hash: &[u8; 32]) -> u64 {
+    let mut chain_id = [0u8; 8];
+    chain_id.copy_from_slice(&genesis_hash[..8]);
+    u64::from_le_bytes(chain_id)
+}
+
+/// Parses a secp256k1 public key given as 64 bytes (x || y) or 65 bytes uncompressed
+/// (0x04 || x || y) into the 64-byte form recovery returns. Any other length or prefix is
+/// `MalformedPackage`, so callers never slice a key of the wrong size.
+pub fn parse_pubkey(public_key: &[u8]) -> Result<Secp256k1Pubkey, VerifyError> {
+    match public_key {
+        coordinates if coordinates.len() == 64 => Ok(Secp256k1Pubkey::new(coordinates)),
+        [0x04, coordinates @ ..] if coordinates.len() == 64 => Ok(Secp256k1Pubkey::new(coordinates)),
+        _ => Err(VerifyError::MalformedPackage),
+    }
+}
+
+/// Runs the same checks as the program's `VerifySig` submitted by `payer` on the cluster with
+/// `chain_id` without sending a transaction: degenerate signatures and keys, the expiry against `now_unix`, the recovery id, the low-S form, recovery of the
+/// signer and the comparison with the package's `public_key`. Returns the recovered public key.
+///
+/// The program rejects an unknown `hash_algo` with `ProgramError::InvalidArgument`, which has no
+/// `VerifyError` code, so it is reported as `MalformedPackage` here.
+pub fn verify_package_locally(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    package: &SignaturePackage,
+    now_unix: i64,
+) -> Result<Secp256k1Pubkey, VerifyError> {
+    check_not_degenerate(&package.verifier_signature, &package.public_key)?;
+    let expected_pubkey = parse_pubkey(&package.public_key)?;
+
+    if now_unix > package.expiry_unix {
+        return Err(VerifyError::SignatureExpired);
+    }
+
+    let message_hash = signed_message_hash(program_id, payer, chain_id, &package.data, package.expiry_unix, &package.tag, package.hash_algo)
+        .ok_or(VerifyError::MalformedPackage)?;
+
+    recover_and_compare(&message_hash, package, &expected_pubkey)
+}
+
+/// Runs the same checks as the program's `VerifyPrehashed` without sending a transaction: the
+/// package's `data` is the signed digest itself, so it is recovered without hashing, and
+/// `expiry_unix`, `hash_algo` and `tag` are ignored. Returns the recovered public key.
+pub fn verify_prehashed_locally(package: &SignaturePackage) -> Result<Secp256k1Pubkey, VerifyError> {
+    let expected_pubkey = parse_pubkey(&package.public_key)?;
+    recover_and_compare(&package.data, package, &expected_pubkey)
+}
+
+/// Recovers the signer of `message_hash` from the package's signature with `libsecp256k1`,
+/// screening it like the program does, and checks it is `expected_pubkey`.
+fn recover_and_compare(
+    message_hash: &[u8; 32],
+    package: &SignaturePackage,
+    expected_pubkey: &Secp256k1Pubkey,
+) -> Result<Secp256k1Pubkey, VerifyError> {
+    if package.recovery_id > MAX_RECOVERY_ID {
+        return Err(VerifyError::InvalidRecoveryId);
+    }
+    if !is_low_s(&package.verifier_signature) {
+        return Err(VerifyError::MalleableSignature);
+    }
+
+    // Same parsing as the syscall: r and s must be below the curve order
+    let recovery_id = RecoveryId::parse(package.recovery_id).map_err(|_| VerifyError::InvalidRecoveryId)?;
+    let signature = Signature::parse_standard(&package.verifier_signature).map_err(|_| VerifyError::RecoveryFailed)?;
+    let recovered = libsecp256k1::recover(&Message::parse(message_hash), &signature, &recovery_id)
+        .map_err(|_| VerifyError::RecoveryFailed)?;
+
+    // Drop the 0x04 prefix to match the syscall's 64-byte x || y form
+    let recovered_pubkey = parse_pubkey(&recovered.serialize())?;
+    if !ct_eq(&recovered_pubkey.to_bytes(), &expected_pubkey.to_bytes()) {
+        return Err(VerifyError::PublicKeyMismatch);
+    }
+
+    Ok(recovered_pubkey)
+}
+
+/// Runs `verify_package_locally` on each package, returning one result per package in the same
+/// order. Unlike the program's `VerifySigBatch`, which fails on the first invalid package, this
+/// reports exactly which packages failed and why.
+pub fn verify_batch_local(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    packages: &[SignaturePackage],
+    now_unix: i64,
+) -> Vec<Result<Secp256k1Pubkey, VerifyError>> {
+    packages
+        .iter()
+        .map(|package| verify_package_locally(program_id, payer, chain_id, package, now_unix))
+        .collect()
+}
+
+/// `verify_batch_local` with the packages recovered in parallel on rayon's thread pool, for
+/// off-chain tools checking thousands of packages. The results are in the order of `packages`.
+#[cfg(feature = "rayon")]
+pub fn verify_batch_local_parallel(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    packages: &[SignaturePackage],
+    now_unix: i64,
+) -> Vec<Result<Secp256k1Pubkey, VerifyError>> {
+    use rayon::prelude::*;
+
+    packages
+        .par_iter()
+        .map(|package| verify_package_locally(program_id, payer, chain_id, package, now_unix))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HASH_ALGO_KECCAK256;
+    use libsecp256k1::{PublicKey, SecretKey};
+
+    const PROGRAM_ID: Pubkey = Pubkey::new_from_array([0x42; 32]);
+    const PAYER: Pubkey = Pubkey::new_from_array([0x22; 32]);
+    const CHAIN_ID: u64 = 0x4444_4444_4444_4444;
+    const NOW: i64 = 1_700_000_000;
+    const SECRET_KEY: [u8; 32] = [0x11; 32];
+
+    /// `data` signed with `SECRET_KEY` for `PAYER`, expiring a minute after `NOW`.
+    fn sign_package(data: [u8; 32]) -> SignaturePackage {
+        let expiry_unix = NOW + 60;
+        let tag = [0; 8];
+        let message_hash = signed_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &data, expiry_unix, &tag, HASH_ALGO_KECCAK256).unwrap();
+        let secret_key = SecretKey::parse(&SECRET_KEY).unwrap();
+        let (signature, recovery_id) = libsecp256k1::sign(&Message::parse(&message_hash), &secret_key);
+
+        SignaturePackage {
+            verifier_signature: signature.serialize(),
+            recovery_id: recovery_id.serialize(),
+            public_key: PublicKey::from_secret_key(&secret_key).serialize(),
+            data,
+            expiry_unix,
+            hash_algo: HASH_ALGO_KECCAK256,
+            tag,
+        }
+    }
+
+    /// The recovered key as bytes, `Secp256k1Pubkey` isn't `Debug`.
+    fn verify(package: &SignaturePackage) -> Result<[u8; 64], VerifyError> {
+        verify_package_locally(&PROGRAM_ID, &PAYER, CHAIN_ID, package, NOW).map(|pubkey| pubkey.to_bytes())
+    }
+
+    #[test]
+    fn signed_package_verifies_to_its_key() {
+        let package = sign_package([0x33; 32]);
+
+        assert_eq!(verify(&package), Ok(package.public_key[1..].try_into().unwrap()));
+    }
+
+    #[test]
+    fn corrupted_packages_fail() {
+        let mut data = sign_package([0x33; 32]);
+        data.data[0] ^= 1;
+        assert_eq!(verify(&data), Err(VerifyError::PublicKeyMismatch));
+
+        let mut signature = sign_package([0x33; 32]);
+        signature.verifier_signature[0] ^= 1;
+        assert!(verify(&signature).is_err());
+
+        let mut recovery_id = sign_package([0x33; 32]);
+        recovery_id.recovery_id = 4;
+        assert_eq!(verify(&recovery_id), Err(VerifyError::InvalidRecoveryId));
+
+        let mut expiry = sign_package([0x33; 32]);
+        expiry.expiry_unix = NOW - 1;
+        assert_eq!(verify(&expiry), Err(VerifyError::SignatureExpired));
+    }
+
+    #[test]
+    fn libsecp256k1_signatures_recover_through_secp256k1_recover() {
+        use solana_program::secp256k1_recover::secp256k1_recover;
+
+        let secret_key = SecretKey::parse(&SECRET_KEY).unwrap();
+        let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+        for byte in 0..8u8 {
+            let digest = keccak::hash(&[byte; 32]).0;
+            let (signature, recovery_id) = libsecp256k1::sign(&Message::parse(&digest), &secret_key);
+            let bytes = signature.serialize();
+
+            // r then s, each big-endian
+            assert_eq!(bytes[..32], signature.r.b32());
+            assert_eq!(bytes[32..], signature.s.b32());
+
+            // The syscall's host implementation reads the same 64 bytes back to the signer's key
+            let recovered = secp256k1_recover(&digest, recovery_id.serialize(), &bytes).unwrap();
+            assert_eq!(recovered.to_bytes()[..], public_key[1..]);
+
+            // s || r is a different signature, recovering some other key if any
+            let swapped = [&bytes[32..], &bytes[..32]].concat();
+            let recovered = secp256k1_recover(&digest, recovery_id.serialize(), &swapped);
+            assert!(recovered.map_or(true, |key| key.to_bytes()[..] != public_key[1..]));
+        }
+    }
+
+    #[test]
+    fn degenerate_signatures_and_keys_are_malformed() {
+        let package = sign_package([0x33; 32]);
+        assert_eq!(check_not_degenerate(&package.verifier_signature, &package.public_key), Ok(()));
+
+        let mut zero_r = package.verifier_signature;
+        zero_r[..32].fill(0);
+        let mut zero_s = package.verifier_signature;
+        zero_s[32..].fill(0);
+        for signature in [[0; 64], zero_r, zero_s] {
+            assert_eq!(check_not_degenerate(&signature, &package.public_key), Err(VerifyError::MalformedPackage));
+        }
+        let mut infinity = [0; 65];
+        infinity[0] = 0x04;
+        for public_key in [[0; 65], infinity] {
+            assert_eq!(check_not_degenerate(&package.verifier_signature, &public_key), Err(VerifyError::MalformedPackage));
+        }
+    }
+
+    #[test]
+    fn only_points_on_the_curve_are_valid() {
+        let public_key = PublicKey::from_secret_key(&SecretKey::parse(&SECRET_KEY).unwrap()).serialize();
+        let recovered = Secp256k1Pubkey::new(&public_key[1..]);
+        assert_eq!(check_valid_point(&recovered), Ok(()));
+
+        // The point at infinity as the syscall encodes it, and the key with its y coordinate changed
+        let mut off_curve = recovered.to_bytes();
+        off_curve[63] ^= 1;
+        for bytes in [[0; 64], off_curve] {
+            assert_eq!(check_valid_point(&Secp256k1Pubkey::new(&bytes)), Err(VerifyError::RecoveryFailed));
+        }
+    }
+
+    #[test]
+    fn ct_eq_agrees_with_the_naive_comparison() {
+        let key = parse_pubkey(&sign_package([0x33; 32]).public_key).unwrap().to_bytes();
+        let mut first_byte = key;
+        first_byte[0] ^= 1;
+        let mut last_byte = key;
+        last_byte[63] ^= 0x80;
+        let vectors: [&[u8]; 7] = [&key, &first_byte, &last_byte, &[0; 64], &key[..63], &[], &[0xff; 64]];
+
+        for a in vectors {
+            for b in vectors {
+                assert_eq!(ct_eq(a, b), a == b, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn chain_id_is_the_genesis_hash_prefix_little_endian() {
+        let mut genesis_hash = [0xff; 32];
+        genesis_hash[..8].copy_from_slice(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+
+        assert_eq!(chain_id_from_genesis_hash(&genesis_hash), 0x0807_0605_0403_0201);
+    }
+
+    #[test]
+    fn package_for_another_payer_or_chain_fails() {
+        let package = sign_package([0x33; 32]);
+
+        let other_payer = verify_package_locally(&PROGRAM_ID, &Pubkey::new_unique(), CHAIN_ID, &package, NOW);
+        assert_eq!(other_payer.err(), Some(VerifyError::PublicKeyMismatch));
+        let other_chain = verify_package_locally(&PROGRAM_ID, &PAYER, CHAIN_ID + 1, &package, NOW);
+        assert_eq!(other_chain.err(), Some(VerifyError::PublicKeyMismatch));
+    }
+
+    /// Valid packages at even indices, a tampered one at 1 and an expired one at 3.
+    fn mixed_batch() -> Vec<SignaturePackage> {
+        let mut packages: Vec<_> = (0..5).map(|byte| sign_package([byte; 32])).collect();
+        packages[1].data[0] ^= 1;
+        packages[3].expiry_unix = NOW - 1;
+        packages
+    }
+
+    #[test]
+    fn batch_results_line_up_with_the_packages() {
+        let packages = mixed_batch();
+        let results = verify_batch_local(&PROGRAM_ID, &PAYER, CHAIN_ID, &packages, NOW);
+
+        let key: [u8; 64] = packages[0].public_key[1..].try_into().unwrap();
+        let results: Vec<_> = results.into_iter().map(|result| result.map(|pubkey| pubkey.to_bytes())).collect();
+        assert_eq!(
+            results,
+            [Ok(key), Err(VerifyError::PublicKeyMismatch), Ok(key), Err(VerifyError::SignatureExpired), Ok(key)]
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_batch_matches_the_sequential_one() {
+        let packages: Vec<_> = mixed_batch().into_iter().cycle().take(64).collect();
+
+        let sequential = verify_batch_local(&PROGRAM_ID, &PAYER, CHAIN_ID, &packages, NOW);
+        let parallel = verify_batch_local_parallel(&PROGRAM_ID, &PAYER, CHAIN_ID, &packages, NOW);
+
+        let bytes = |results: Vec<Result<Secp256k1Pubkey, VerifyError>>| -> Vec<_> {
+            results.into_iter().map(|result| result.map(|pubkey| pubkey.to_bytes())).collect()
+        };
+        assert_eq!(bytes(parallel), bytes(sequential));
+    }
+
+    #[test]
+    fn eth_address_matches_a_known_pair() {
+        // The key and address from the web3.js `eth.accounts` documentation, 0x4c0883a6...3f362318
+        // and 0x2c7536E3605D9C16a7a3D7b1898e529396a65c23
+        let secret = [
+            0x4c, 0x08, 0x83, 0xa6, 0x91, 0x02, 0x93, 0x7d, 0x62, 0x31, 0x47, 0x1b, 0x5d, 0xbb, 0x62, 0x04,
+            0xfe, 0x51, 0x29, 0x61, 0x70, 0x82, 0x79, 0x2a, 0xe4, 0x68, 0xd0, 0x1a, 0x3f, 0x36, 0x23, 0x18,
+        ];
+        let address = [
+            0x2c, 0x75, 0x36, 0xe3, 0x60, 0x5d, 0x9c, 0x16, 0xa7, 0xa3, 0xd7, 0xb1, 0x89, 0x8e, 0x52, 0x93, 0x96, 0xa6, 0x5c, 0x23,
+        ];
+        let public_key = PublicKey::from_secret_key(&SecretKey::parse(&secret).unwrap()).serialize();
+
+        assert_eq!(pubkey_to_eth_address(&parse_pubkey(&public_key).unwrap()), address);
+    }
+
+    #[test]
+    fn parse_pubkey_rejects_keys_of_the_wrong_size_or_prefix() {
+        let public_key = PublicKey::from_secret_key(&SecretKey::parse(&SECRET_KEY).unwrap()).serialize();
+        assert_eq!(parse_pubkey(&public_key).map(|pubkey| pubkey.to_bytes()), Ok(public_key[1..].try_into().unwrap()));
+        assert_eq!(parse_pubkey(&public_key[1..]).map(|pubkey| pubkey.to_bytes()), Ok(public_key[1..].try_into().unwrap()));
+
+        let compressed = PublicKey::from_secret_key(&SecretKey::parse(&SECRET_KEY).unwrap()).serialize_compressed();
+        let mut wrong_prefix = public_key;
+        wrong_prefix[0] = 0x02;
+        let too_long = [&public_key[..], &[0]].concat();
+        for short_or_malformed in [&[][..], &public_key[..1], &compressed[..], &public_key[..63], &wrong_prefix[..], &too_long[..]] {
+            assert_eq!(parse_pubkey(short_or_malformed).err(), Some(VerifyError::MalformedPackage), "{} bytes", short_or_malformed.len());
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! A recent blockhash shared by the `--count` transactions, refreshed every few seconds so a long
+//! run doesn't keep signing with a blockhash that has expired, and the checks for which failed
+//! sends are worth signing again with a new blockhash.
+
+use log::warn;
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_sdk::hash::Hash;
+use solana_sdk::transaction::TransactionError;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use crate::rpc::SolanaRpc;
+
+/// How many times a transaction is sent before giving up on a retryable error, the first send
+/// included.
+pub const SEND_MAX_ATTEMPTS: usize = 3;
+
+/// How long a fetched blockhash is used before fetching a new one. Blockhashes expire after
+/// about a minute, so this leaves plenty of margin.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct BlockhashCache {
+    current: Mutex<(Hash, Instant)>,
+}
+
+impl BlockhashCache {
+    /// Fetches the first blockhash.
+    pub async fn new(client: &impl SolanaRpc) -> ClientResult<Self> {
+        let blockhash = fetch_blockhash(client).await?;
+        Ok(Self {
+            current: Mutex::new((blockhash, Instant::now())),
+        })
+    }
+
+    /// The cached blockhash, fetching a new one first if it is older than `REFRESH_INTERVAL`.
+    pub async fn get(&self, client: &impl SolanaRpc) -> ClientResult<Hash> {
+        let mut current = self.current.lock().await;
+        if current.1.elapsed() >= REFRESH_INTERVAL {
+            *current = (client.get_latest_blockhash().await?, Instant::now());
+        }
+        Ok(current.0)
+    }
+
+    /// Replaces `stale` with a newly fetched blockhash. When several transactions fail with the
+    /// same stale blockhash, only the first fetches, the others get the blockhash it fetched.
+    pub async fn refresh(&self, client: &impl SolanaRpc, stale: Hash) -> ClientResult<Hash> {
+        let mut current = self.current.lock().await;
+        if current.0 == stale {
+            *current = (client.get_latest_blockhash().await?, Instant::now());
+        }
+        Ok(current.0)
+    }
+}
+
+/// Fetches a recent blockhash, asking again after a transient failure (see `is_retryable`), up to
+/// `SEND_MAX_ATTEMPTS` requests in all.
+pub async fn fetch_blockhash(client: &impl SolanaRpc) -> ClientResult<Hash> {
+    let mut attempt = 1;
+    loop {
+        match client.get_latest_blockhash().await {
+            Err(err) if attempt < SEND_MAX_ATTEMPTS && is_retryable(&err) => {
+                warn!("Fetching a blockhash failed, retrying ({}/{}): {}", attempt, SEND_MAX_ATTEMPTS, err);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether a send failed because its blockhash expired, so signing again with a new blockhash gives
+/// a transaction the cluster accepts.
+pub fn is_blockhash_error(err: &ClientError) -> bool {
+    matches!(err.get_transaction_error(), Some(TransactionError::BlockhashNotFound))
+}
+
+/// Whether a send failed because the same transaction was already processed, typically a resend
+/// of one that landed while its confirmation was lost. The transaction succeeded, so this counts as
+/// a success; signing it again with a new blockhash would run the instruction a second time.
+pub fn is_already_processed(err: &ClientError) -> bool {
+    matches!(err.get_transaction_error(), Some(TransactionError::AlreadyProcessed))
+}
+
+/// Whether a failed send is worth retrying: the request timed out or couldn't connect, the node is
+/// behind the cluster, or the blockhash was rejected. Errors from the transaction itself, like a
+/// program error or a payer without enough lamports for the fee, fail the same way every time and
+/// are not retried.
+pub fn is_retryable(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(err) => err.is_timeout() || err.is_connect(),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::NodeUnhealthy { .. },
+            ..
+        }) => true,
+        _ => is_blockhash_error(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::MockRpc;
+    use solana_sdk::instruction::InstructionError;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn only_an_expired_blockhash_is_retryable() {
+        let expired = ClientError::from(TransactionError::BlockhashNotFound);
+        assert!(is_blockhash_error(&expired));
+        assert!(is_retryable(&expired));
+        assert!(!is_already_processed(&expired));
+
+        let duplicate = ClientError::from(TransactionError::AlreadyProcessed);
+        assert!(is_already_processed(&duplicate));
+        assert!(!is_blockhash_error(&duplicate));
+        assert!(!is_retryable(&duplicate));
+
+        assert!(!is_blockhash_error(&ClientError::from(TransactionError::InsufficientFundsForFee)));
+    }
+
+    #[test]
+    fn transient_failures_are_retryable_and_transaction_errors_are_not() {
+        let node_behind = RpcError::RpcResponseError {
+            code: -32005,
+            message: "Node is behind by 42 slots".to_string(),
+            data: RpcResponseErrorData::NodeUnhealthy { num_slots_behind: Some(42) },
+        };
+        let io_error = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "connection reset");
+        assert!(is_retryable(&ClientError::from(node_behind)));
+        assert!(is_retryable(&ClientError::from(io_error)));
+
+        let program_error = TransactionError::InstructionError(0, InstructionError::Custom(1));
+        for err in [program_error, TransactionError::InsufficientFundsForFee, TransactionError::AccountNotFound] {
+            assert!(!is_retryable(&ClientError::from(err.clone())), "{:?}", err);
+        }
+        assert!(!is_retryable(&ClientError::from(RpcError::ForUser("invalid request".to_string()))));
+    }
+
+    #[tokio::test]
+    async fn refresh_fetches_once_for_the_same_stale_blockhash() {
+        let client = MockRpc::default();
+        let cache = BlockhashCache::new(&client).await.unwrap();
+        let stale = cache.get(&client).await.unwrap();
+
+        let fresh = cache.refresh(&client, stale).await.unwrap();
+        // A second transaction that failed with the same blockhash gets the one already fetched
+        assert_eq!(cache.refresh(&client, stale).await.unwrap(), fresh);
+
+        assert_ne!(fresh, stale);
+        assert_eq!(cache.get(&client).await.unwrap(), fresh);
+        assert_eq!(client.blockhashes_fetched.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn fetch_blockhash_retries_a_transient_failure_only() {
+        let client = MockRpc::default();
+        let timed_out = || ClientError::from(std::io::Error::new(std::io::ErrorKind::TimedOut, "request timed out"));
+        client.blockhash_results.lock().unwrap().push_back(Err(timed_out()));
+        fetch_blockhash(&client).await.unwrap();
+        assert_eq!(client.blockhashes_fetched.load(Ordering::Relaxed), 2);
+
+        let client = MockRpc::default();
+        client.blockhash_results.lock().unwrap().push_back(Err(RpcError::ForUser("invalid request".to_string()).into()));
+        assert!(fetch_blockhash(&client).await.is_err());
+        assert_eq!(client.blockhashes_fetched.load(Ordering::Relaxed), 1);
+    }
+}
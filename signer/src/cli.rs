@@ -0,0 +1,638 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use solana_program::pubkey::Pubkey;
+use std::fs;
+use std::num::NonZeroUsize;
+use shared_types::{HASH_ALGO_KECCAK256, HASH_ALGO_SHA256};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use secp256k1_signer_example::keys::secret_key_from_seed;
+use crate::{DEFAULT_COMPUTE_UNITS, DEFAULT_TIMEOUT_SECS, DEFAULT_UNITS_PER_SIGNATURE, PROGRAM_ID, RPC_URL};
+
+/// Signs data with a secp256k1 key and sends it to the signature verification program.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Log more, -v for debug and -vv for trace. RUST_LOG overrides it.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Program id of the deployed signature verification program.
+    #[arg(long, default_value = PROGRAM_ID)]
+    pub program_id: Pubkey,
+
+    /// Cluster to connect to.
+    #[arg(long, value_enum, default_value_t = Cluster::Localhost)]
+    pub cluster: Cluster,
+
+    /// URL of the Solana RPC node, overriding the cluster's URL.
+    #[arg(long)]
+    pub rpc_url: Option<String>,
+
+    /// Confirm airdrops with a signatureSubscribe over WebSocket instead of polling, falling back
+    /// to polling if the WebSocket endpoint can't be reached.
+    #[arg(long)]
+    pub ws: bool,
+
+    /// WebSocket URL for --ws, overriding the one derived from the RPC URL.
+    #[arg(long, requires = "ws")]
+    pub ws_url: Option<String>,
+
+    /// Chain id the packages are signed for, which must match the one in the program's config.
+    /// Defaults to the one derived from the cluster's genesis hash. Required by `verify` and
+    /// --dry-run, which make no RPC calls.
+    #[arg(long, global = true)]
+    pub chain_id: Option<u64>,
+
+    /// Commitment level transactions are confirmed at.
+    #[arg(long, value_enum, default_value_t = Commitment::Confirmed)]
+    pub commitment: Commitment,
+
+    /// Seconds to wait for a transaction to be confirmed before giving up.
+    #[arg(long, default_value_t = DEFAULT_TIMEOUT_SECS)]
+    pub timeout: u64,
+
+    /// Solana keypair file paying for the transactions. Defaults to ~/.config/solana/id.json.
+    #[arg(long)]
+    pub keypair: Option<String>,
+
+    /// Generate a new payer keypair instead of loading one. It is funded with an airdrop on
+    /// localhost, other clusters have to be funded separately.
+    #[arg(long, conflicts_with = "keypair")]
+    pub generate: bool,
+
+    /// Generate this many keypairs, fund each with an airdrop and write them to --fund-dir, then
+    /// exit. For load testing the batch and threshold paths with many payers.
+    #[arg(long, conflicts_with_all = ["keypair", "generate", "dry_run"])]
+    pub fund: Option<usize>,
+
+    /// Submit this many VerifySig transactions, each signing distinct random data, and report the
+    /// total time, transactions per second and failures instead of running the default flow.
+    #[arg(long, conflicts_with_all = ["fund", "package", "prehashed", "dry_run"])]
+    pub count: Option<usize>,
+
+    /// Number of --count transactions in flight at once.
+    #[arg(long, default_value_t = NonZeroUsize::MIN, requires = "count")]
+    pub concurrency: NonZeroUsize,
+
+    /// Directory the --fund keypairs are written to, created if missing.
+    #[arg(long, default_value = "keypairs", requires = "fund")]
+    pub fund_dir: String,
+
+    /// secp256k1 secret key, as 64 hex characters or the path of a file containing them or the
+    /// raw 32 bytes. A random key is generated when omitted.
+    #[arg(long, value_parser = parse_secret)]
+    pub secret: Option<[u8; 32]>,
+
+    /// Comma-separated secp256k1 secret keys, each as hex or a file like --secret. The data is
+    /// signed with every key and the packages are sent together in a VerifySigBatch, or a
+    /// VerifyThreshold with --threshold.
+    #[arg(long, value_delimiter = ',', value_parser = parse_secret)]
+    pub secrets: Vec<[u8; 32]>,
+
+    /// Number of registered signers among --secrets that must have signed. The keys have to be
+    /// registered with RegisterSigner by the admin first.
+    #[arg(long, requires = "secrets")]
+    pub threshold: Option<u8>,
+
+    /// Estimated compute units each package of a VerifySigBatch costs. --secrets packages that
+    /// don't fit in --compute-units, or in one transaction, are split across several batches.
+    #[arg(long, default_value_t = DEFAULT_UNITS_PER_SIGNATURE, requires = "secrets", conflicts_with = "threshold")]
+    pub units_per_signature: u32,
+
+    /// File whose contents are signed, or - for stdin. The contents are hashed with Keccak-256
+    /// to 32 bytes. Random data is signed when omitted.
+    #[arg(long)]
+    pub data_file: Option<String>,
+
+    /// Sign the --data-file contents as is instead of hashing them. The file must be exactly
+    /// 32 bytes.
+    #[arg(long, requires = "data_file")]
+    pub raw: bool,
+
+    /// Hash algorithm the signed message is hashed with, recorded in the package's hash_algo.
+    #[arg(long, value_enum, default_value_t = HashAlgo::Keccak)]
+    pub hash: HashAlgo,
+
+    /// Application-defined 8-byte tag signed with the data and stored next to it in the payer's
+    /// state, as 16 hex characters. Defaults to all zeros.
+    #[arg(long, value_parser = parse_tag, conflicts_with = "prehashed")]
+    pub tag: Option<[u8; 8]>,
+
+    /// Sign the data as the final 32-byte message digest, without hashing it with the domain
+    /// separator, program id, payer and expiry, and send it in a VerifyPrehashed instead of a
+    /// VerifySig. A --data-file must be exactly 32 bytes, as with --raw, and a --package must
+    /// have been signed with --prehashed too.
+    #[arg(long, conflicts_with_all = ["secrets", "hash"])]
+    pub prehashed: bool,
+
+    /// Also sign the data without keeping the recovery id and send it in a VerifySigAnyRecovery,
+    /// which tries each recovery id on chain.
+    #[arg(long, conflicts_with = "prehashed")]
+    pub without_recovery_id: bool,
+
+    /// Also sign the data as an EIP-712 Attestation for the chain id and send it in a
+    /// VerifyTypedData, verified against the signer's Ethereum address.
+    #[arg(long, conflicts_with = "prehashed")]
+    pub typed_data: bool,
+
+    /// Also sign the data bound to the current slot and send it in a VerifySlotBound, which the
+    /// program rejects once the cluster is more than this many slots past it.
+    #[arg(long, value_name = "MAX_SLOT_AGE", conflicts_with = "prehashed")]
+    pub slot_bound: Option<u64>,
+
+    /// Also sign an AttestationRecord of this epoch with the data as its root and send it in a
+    /// VerifyRecord, which stores the root as the data and the epoch as the tag.
+    #[arg(long, value_name = "EPOCH", conflicts_with = "prehashed")]
+    pub record_epoch: Option<u64>,
+
+    /// Also sign the Keccak-256 hash of the data as a commitment and send it with a package
+    /// revealing the data in a VerifySameSigner, which checks both were signed by the same key.
+    #[arg(long, conflicts_with_all = ["prehashed", "package"])]
+    pub same_signer: bool,
+
+    /// Also print the package's signature in Ethereum's 65-byte r || s || v layout, v being the
+    /// recovery id plus 27, as 0x-prefixed hex. The package sent to the program is unchanged.
+    #[arg(long, conflicts_with_all = ["json_output", "offline"])]
+    pub eth_format: bool,
+
+    /// Write the signed package to this file as JSON, with the byte arrays hex-encoded.
+    #[arg(long)]
+    pub out: Option<String>,
+
+    /// Submit a package previously written with --out instead of signing a new one.
+    #[arg(long, conflicts_with_all = ["data_file", "secret"])]
+    pub package: Option<String>,
+
+    /// Compute unit limit requested for each transaction. Batch and threshold verification need
+    /// more than a single signature.
+    #[arg(long, default_value_t = DEFAULT_COMPUTE_UNITS)]
+    pub compute_units: u32,
+
+    /// Priority fee in micro-lamports per compute unit. No fee is added when 0.
+    #[arg(long, default_value_t = 0)]
+    pub priority_fee: u64,
+
+    /// Send only the VerifySig, or VerifyPrehashed, and print its result to stdout as a single
+    /// JSON object with the signature, slot, status and error code. Logs still go to stderr.
+    #[arg(long, conflicts_with_all = ["dry_run", "count", "fund", "secrets", "without_recovery_id", "typed_data", "slot_bound", "same_signer", "record_epoch"])]
+    pub json_output: bool,
+
+    /// Print the VerifySig transaction and exit without making any RPC call.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Check that the payer can pay for every transaction this run sends, including priority fees
+    /// and the state account's rent, and stop before sending any of them otherwise.
+    #[arg(long, conflicts_with_all = ["dry_run", "offline"])]
+    pub prefund_check: bool,
+
+    /// Build and sign the VerifySig transaction without any RPC call, using --blockhash, and print
+    /// it base64-encoded for `broadcast` on a networked machine. Needs --chain-id.
+    #[arg(long, requires = "blockhash", conflicts_with_all = [
+        "dry_run", "generate", "count", "fund", "secrets", "json_output", "without_recovery_id", "typed_data", "slot_bound",
+        "same_signer", "record_epoch",
+    ])]
+    pub offline: bool,
+
+    /// Recent blockhash the --offline transaction is signed with, fetched on a networked machine.
+    /// The transaction has to be broadcast before it expires, about a minute later.
+    #[arg(long, requires = "offline")]
+    pub blockhash: Option<Hash>,
+
+    /// Use the blocking RPC client instead of the nonblocking one. This only changes the client
+    /// type: the signer still runs on its async runtime, each call blocks that task until it
+    /// returns, so --count sends one transaction at a time and --timeout only bounds each request.
+    #[arg(long)]
+    pub blocking: bool,
+
+    /// Send transactions even when the package fails local verification or their simulation fails.
+    #[arg(long)]
+    pub force: bool,
+}
+
+impl Cli {
+    /// The RPC URL to connect to, `--rpc-url` if given or the cluster's URL otherwise.
+    pub fn rpc_url(&self) -> String {
+        self.rpc_url.clone().unwrap_or_else(|| self.cluster.url().to_string())
+    }
+
+    /// The WebSocket URL for --ws, `--ws-url` if given or the one derived from the RPC URL.
+    pub fn ws_url(&self) -> String {
+        self.ws_url.clone().unwrap_or_else(|| websocket_url(&self.rpc_url()))
+    }
+}
+
+/// Derives a node's WebSocket URL from its RPC URL: `http` becomes `ws`, `https` becomes `wss`,
+/// and the default RPC port 8899 becomes the default WebSocket port 8900.
+pub fn websocket_url(rpc_url: &str) -> String {
+    let url = if let Some(rest) = rpc_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        rpc_url.to_string()
+    };
+    url.replacen(":8899", ":8900", 1)
+}
+
+/// Subcommands run instead of the default sign and submit flow.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check a package written with --out locally and report the key that signed it, without
+    /// making any RPC call. Needs --chain-id.
+    Verify {
+        /// Package file to check.
+        #[arg(long)]
+        package: String,
+
+        /// Payer the package was signed for. Defaults to the --keypair public key.
+        #[arg(long)]
+        payer: Option<Pubkey>,
+    },
+    /// Check every package of a JSONL file, one package per line as JSON like --out writes it,
+    /// and print a report with each line's number. Needs --chain-id unless --submit is passed.
+    VerifyFile {
+        /// JSONL file of packages. Blank lines are ignored.
+        #[arg(long)]
+        file: String,
+
+        /// Payer the packages were signed for. Defaults to the --keypair public key, and is
+        /// always the --keypair public key with --submit.
+        #[arg(long)]
+        payer: Option<Pubkey>,
+
+        /// Send the packages that pass the check in VerifySigBatch transactions, split like a
+        /// --secrets batch. The payer's state account must exist already.
+        #[arg(long)]
+        submit: bool,
+
+        /// Stop at the first malformed line instead of reporting and skipping it.
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Generate a secp256k1 secret key, write it to a file as hex and print its public key and
+    /// Ethereum address. Not a Solana keypair, see solana-keygen for those. Nothing is sent.
+    Keygen {
+        /// File the hex secret key is written to. An existing file is never overwritten.
+        #[arg(long)]
+        out: String,
+
+        /// Use this 32-byte seed as the key instead of a random one, given like --secret, so the
+        /// same seed always gives the same key. For tests only.
+        #[arg(long, value_parser = parse_secret)]
+        seed: Option<[u8; 32]>,
+    },
+    /// Check that --program-id is a program deployed on the cluster, then exit.
+    CheckDeploy,
+    /// Create the program's config, making the --keypair payer its admin and binding it to
+    /// --chain-id, or the chain id derived from the cluster's genesis hash. Sent once after
+    /// deployment, and only the program's upgrade authority can send it.
+    InitConfig,
+    /// Submit a transaction built with --offline and wait for its confirmation.
+    Broadcast {
+        /// The base64-encoded transaction --offline printed.
+        #[arg(long)]
+        tx: String,
+    },
+    /// Recover the key that signed a message and print its public key and Ethereum address,
+    /// without making any RPC call. For finding out who produced a signature.
+    RecoverAddress {
+        /// Signature `r || s`, 64 bytes as hex.
+        #[arg(long, value_parser = parse_signature)]
+        sig: [u8; 64],
+
+        /// Recovery id of the signature, 0 or 1 (27 and 28 are accepted as Ethereum writes them).
+        #[arg(long, value_parser = parse_recovery_id)]
+        recid: u8,
+
+        /// Signed message, as hex or the path of a file with its raw contents. It is hashed with
+        /// Keccak-256 before recovery.
+        // The full path keeps clap from reading a `Vec` as a repeated argument
+        #[arg(long, value_parser = parse_message)]
+        message: std::vec::Vec<u8>,
+
+        /// Treat --message as the final 32-byte digest and recover from it without hashing.
+        #[arg(long)]
+        prehashed: bool,
+    },
+    /// Fetch and print a payer's state account: its verification count, last verified data and
+    /// admin. Nothing is sent.
+    QueryState {
+        /// Payer whose state to read. Defaults to the --keypair public key.
+        #[arg(long)]
+        payer: Option<Pubkey>,
+    },
+}
+
+/// Hash algorithms the program accepts for the signed message.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgo {
+    Keccak,
+    Sha256,
+}
+
+impl From<HashAlgo> for u8 {
+    fn from(hash: HashAlgo) -> Self {
+        match hash {
+            HashAlgo::Keccak => HASH_ALGO_KECCAK256,
+            HashAlgo::Sha256 => HASH_ALGO_SHA256,
+        }
+    }
+}
+
+/// Commitment levels a transaction can be confirmed at, from fastest to safest.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Commitment {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl From<Commitment> for CommitmentConfig {
+    fn from(commitment: Commitment) -> Self {
+        match commitment {
+            Commitment::Processed => CommitmentConfig::processed(),
+            Commitment::Confirmed => CommitmentConfig::confirmed(),
+            Commitment::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+}
+
+/// Solana clusters with a well-known public RPC endpoint.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cluster {
+    MainnetBeta,
+    Devnet,
+    Testnet,
+    Localhost,
+}
+
+impl Cluster {
+    /// The cluster's canonical RPC URL.
+    pub fn url(&self) -> &'static str {
+        match self {
+            Cluster::MainnetBeta => "https://api.mainnet-beta.solana.com",
+            Cluster::Devnet => "https://api.devnet.solana.com",
+            Cluster::Testnet => "https://api.testnet.solana.com",
+            Cluster::Localhost => RPC_URL,
+        }
+    }
+}
+
+/// Parses a `--tag`, exactly 8 bytes given as hex.
+pub fn parse_tag(value: &str) -> Result<[u8; 8], String> {
+    let bytes = hex::decode(value).map_err(|err| format!("tag is not valid hex: {}", err))?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("tag must be 8 bytes, got {}", bytes.len()))
+}
+
+/// Parses a signature `r || s`, exactly 64 bytes given as hex with or without a `0x` prefix.
+pub fn parse_signature(value: &str) -> Result<[u8; 64], String> {
+    let bytes = hex::decode(value.trim_start_matches("0x")).map_err(|err| format!("signature is not valid hex: {}", err))?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("signature must be 64 bytes, got {}", bytes.len()))
+}
+
+/// Parses a recovery id, 0 to 3 or Ethereum's 27 to 30, which are the same ids offset by 27.
+pub fn parse_recovery_id(value: &str) -> Result<u8, String> {
+    match value.parse::<u8>() {
+        Ok(id @ 0..=3) => Ok(id),
+        Ok(id @ 27..=30) => Ok(id - 27),
+        _ => Err(format!("recovery id must be 0 to 3 or 27 to 30, got {}", value)),
+    }
+}
+
+/// Parses a message given either as hex, with or without a `0x` prefix, or as the path of a file
+/// whose raw contents are the message.
+pub fn parse_message(value: &str) -> Result<Vec<u8>, String> {
+    let hex_message = value.strip_prefix("0x").unwrap_or(value);
+    if !hex_message.is_empty() && hex_message.len().is_multiple_of(2) && hex_message.chars().all(|c| c.is_ascii_hexdigit()) {
+        return hex::decode(hex_message).map_err(|err| format!("message is not valid hex: {}", err));
+    }
+    fs::read(value).map_err(|err| format!("message is neither hex nor a readable file {}: {}", value, err))
+}
+
+/// Parses a secp256k1 secret given either as hex or as the path of a file containing the raw 32
+/// bytes or their hex encoding. The secret has to be a valid secp256k1 scalar, non-zero and below
+/// the curve order.
+pub fn parse_secret(value: &str) -> Result<[u8; 32], String> {
+    let bytes = if value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        hex::decode(value).map_err(|err| format!("secret is not valid hex: {}", err))?
+    } else {
+        let contents = fs::read(value).map_err(|err| format!("unable to read secret file {}: {}", value, err))?;
+        if contents.len() == 32 {
+            contents
+        } else {
+            let hex_secret = String::from_utf8(contents).map_err(|_| "secret file is neither 32 raw bytes nor hex".to_string())?;
+            hex::decode(hex_secret.trim()).map_err(|err| format!("secret is not valid hex: {}", err))?
+        }
+    };
+
+    let secret: [u8; 32] = bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("secret must be 32 bytes, got {}", bytes.len()))?;
+    secret_key_from_seed(&secret).map_err(|_| "secret is not a valid secp256k1 key, it must be non-zero and below the curve order".to_string())?;
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use std::str::FromStr;
+
+    const SECRET: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(["secp256k1-signer-example"].iter().chain(args))
+    }
+
+    #[test]
+    fn help_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn defaults_to_the_constants() {
+        let cli = parse(&[]).unwrap();
+
+        assert_eq!(cli.program_id, Pubkey::from_str(PROGRAM_ID).unwrap());
+        assert_eq!(cli.rpc_url(), RPC_URL);
+        assert_eq!(cli.keypair, None);
+        assert_eq!(cli.secret, None);
+    }
+
+    #[test]
+    fn parses_the_flags() {
+        let program_id = Pubkey::new_unique();
+        let cli = parse(&[
+            "--program-id",
+            &program_id.to_string(),
+            "--rpc-url",
+            "http://validator:8899",
+            "--keypair",
+            "payer.json",
+            "--secret",
+            SECRET,
+        ])
+        .unwrap();
+
+        assert_eq!(cli.program_id, program_id);
+        assert_eq!(cli.rpc_url(), "http://validator:8899");
+        assert_eq!(cli.keypair.as_deref(), Some("payer.json"));
+        assert_eq!(cli.secret, Some([0x11; 32]));
+    }
+
+    #[test]
+    fn reads_the_secret_from_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let hex_path = dir.path().join("secret.hex");
+        fs::write(&hex_path, format!("{}\n", SECRET)).unwrap();
+        let raw_path = dir.path().join("secret.bin");
+        fs::write(&raw_path, [0x11; 32]).unwrap();
+
+        assert_eq!(parse_secret(hex_path.to_str().unwrap()), Ok([0x11; 32]));
+        assert_eq!(parse_secret(raw_path.to_str().unwrap()), Ok([0x11; 32]));
+    }
+
+    #[test]
+    fn rejects_an_invalid_program_id() {
+        assert!(parse(&["--program-id", "not-a-pubkey"]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_secrets() {
+        // Too short, not hex and not a file, zero, and the curve order
+        for secret in [
+            "1111",
+            "zz11111111111111111111111111111111111111111111111111111111111111",
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141",
+        ] {
+            assert!(parse_secret(secret).is_err(), "{}", secret);
+            assert!(parse(&["--secret", secret]).is_err(), "{}", secret);
+        }
+    }
+
+    #[test]
+    fn hash_selects_the_package_hash_algo() {
+        assert_eq!(u8::from(parse(&[]).unwrap().hash), HASH_ALGO_KECCAK256);
+        assert_eq!(u8::from(parse(&["--hash", "keccak"]).unwrap().hash), HASH_ALGO_KECCAK256);
+        assert_eq!(u8::from(parse(&["--hash", "sha256"]).unwrap().hash), HASH_ALGO_SHA256);
+        assert!(parse(&["--hash", "blake3"]).is_err());
+    }
+
+    #[test]
+    fn websocket_url_follows_the_rpc_url() {
+        for (rpc_url, ws_url) in [
+            ("http://localhost:8899", "ws://localhost:8900"),
+            ("http://127.0.0.1:8899/", "ws://127.0.0.1:8900/"),
+            ("https://api.devnet.solana.com", "wss://api.devnet.solana.com"),
+            ("https://rpc.example.com:8899/path", "wss://rpc.example.com:8900/path"),
+            ("http://validator:9000", "ws://validator:9000"),
+            ("ws://localhost:8900", "ws://localhost:8900"),
+        ] {
+            assert_eq!(websocket_url(rpc_url), ws_url, "{}", rpc_url);
+        }
+    }
+
+    #[test]
+    fn ws_url_overrides_the_derived_one() {
+        assert_eq!(parse(&["--ws", "--cluster", "localhost"]).unwrap().ws_url(), "ws://localhost:8900");
+        assert_eq!(parse(&["--ws", "--ws-url", "ws://proxy:9000"]).unwrap().ws_url(), "ws://proxy:9000");
+        assert!(parse(&["--ws-url", "ws://proxy:9000"]).is_err());
+    }
+
+    #[test]
+    fn tag_is_eight_bytes_of_hex() {
+        assert_eq!(parse(&["--tag", "6f726465722d3432"]).unwrap().tag, Some(*b"order-42"));
+        assert_eq!(parse(&[]).unwrap().tag, None);
+        for tag in ["6f72", "6f726465722d343200", "not hex!"] {
+            assert!(parse_tag(tag).is_err(), "{}", tag);
+        }
+        assert!(parse(&["--tag", "6f726465722d3432", "--prehashed"]).is_err());
+    }
+
+    #[test]
+    fn clusters_map_to_their_rpc_urls() {
+        for (cluster, url) in [
+            ("mainnet-beta", "https://api.mainnet-beta.solana.com"),
+            ("devnet", "https://api.devnet.solana.com"),
+            ("testnet", "https://api.testnet.solana.com"),
+            ("localhost", "http://localhost:8899"),
+        ] {
+            assert_eq!(parse(&["--cluster", cluster]).unwrap().rpc_url(), url);
+        }
+    }
+
+    #[test]
+    fn rpc_url_overrides_the_cluster() {
+        let cli = parse(&["--cluster", "devnet", "--rpc-url", "https://devnet.example.com"]).unwrap();
+
+        assert_eq!(cli.rpc_url(), "https://devnet.example.com");
+    }
+
+    #[test]
+    fn rejects_an_unknown_cluster() {
+        assert!(parse(&["--cluster", "mainnet"]).is_err());
+    }
+
+    #[test]
+    fn parse_recovery_id_accepts_raw_and_ethereum_ids() {
+        for (value, id) in [("0", 0), ("3", 3), ("27", 0), ("28", 1), ("30", 3)] {
+            assert_eq!(parse_recovery_id(value), Ok(id));
+        }
+    }
+
+    #[test]
+    fn parse_recovery_id_rejects_other_values() {
+        for value in ["4", "26", "31", "-1", "256", "0x1b", ""] {
+            assert!(parse_recovery_id(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn parse_signature_takes_64_bytes_of_hex() {
+        let hex_signature = "11".repeat(64);
+        assert_eq!(parse_signature(&hex_signature), Ok([0x11; 64]));
+        assert_eq!(parse_signature(&format!("0x{}", hex_signature)), Ok([0x11; 64]));
+        assert!(parse_signature(&"11".repeat(65)).is_err());
+        assert!(parse_signature("not hex").is_err());
+    }
+
+    #[test]
+    fn parse_message_reads_hex_or_a_file() {
+        assert_eq!(parse_message("0x0102"), Ok(vec![1, 2]));
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), b"Some data").unwrap();
+        assert_eq!(parse_message(file.path().to_str().unwrap()), Ok(b"Some data".to_vec()));
+        assert!(parse_message("no such file").is_err());
+    }
+
+    #[test]
+    fn parse_secret_accepts_a_hex_key() {
+        assert_eq!(parse_secret(SECRET), Ok([0x11; 32]));
+    }
+
+    #[test]
+    fn parse_secret_rejects_a_key_of_the_wrong_length() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret.hex");
+        fs::write(&path, hex::encode([0x11; 31])).unwrap();
+
+        assert_eq!(parse_secret(path.to_str().unwrap()), Err("secret must be 32 bytes, got 31".to_string()));
+    }
+
+    #[test]
+    fn parse_secret_rejects_an_out_of_range_scalar() {
+        let curve_order = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+
+        let err = parse_secret(curve_order).unwrap_err();
+        assert!(err.starts_with("secret is not a valid secp256k1 key"), "{}", err);
+    }
+}
@@ -0,0 +1,114 @@
+//! EIP-712 typed-data hashing for `VerifyTypedData`. The program only recovers the signer from
+//! the digest, so the domain and struct are hashed here, the way an Ethereum wallet's
+//! `eth_signTypedData_v4` hashes them.
+
+use solana_program::keccak;
+
+/// Type of the signer's EIP-712 domain. The optional `verifyingContract` and `salt` fields are
+/// left out, the program is identified by `name` and the cluster by `chainId`.
+pub const DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,uint256 chainId)";
+
+/// Type of the struct the signer signs, 32 bytes of data.
+pub const ATTESTATION_TYPE: &str = "Attestation(bytes32 data)";
+
+/// Domain name the signer's attestations are signed under.
+pub const DOMAIN_NAME: &str = "solana-secp-verify";
+
+/// Domain version the signer's attestations are signed under.
+pub const DOMAIN_VERSION: &str = "1";
+
+/// `hashStruct` of the domain: the Keccak-256 hash of its type hash followed by each field
+/// encoded to 32 bytes. Strings are encoded as their Keccak-256 hash and `chainId` as a
+/// big-endian `uint256`.
+pub fn domain_separator(name: &str, version: &str, chain_id: u64) -> [u8; 32] {
+    let mut chain_id_word = [0u8; 32];
+    chain_id_word[24..].copy_from_slice(&chain_id.to_be_bytes());
+    keccak::hashv(&[
+        &keccak::hash(DOMAIN_TYPE.as_bytes()).0,
+        &keccak::hash(name.as_bytes()).0,
+        &keccak::hash(version.as_bytes()).0,
+        &chain_id_word,
+    ])
+    .0
+}
+
+/// `hashStruct` of an `Attestation`. A `bytes32` field is encoded as is.
+pub fn attestation_hash(data: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[&keccak::hash(ATTESTATION_TYPE.as_bytes()).0, data]).0
+}
+
+/// The digest a wallet signs for typed data, `keccak256(0x19 || 0x01 || domain_separator ||
+/// struct_hash)`. The 0x19 0x01 prefix keeps it from colliding with a transaction or a
+/// `personal_sign` message.
+pub fn typed_data_digest(domain_separator: &[u8; 32], struct_hash: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[b"\x19\x01", domain_separator, struct_hash]).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sign_typed_data;
+
+    fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+        hex::decode(hex).unwrap().try_into().unwrap()
+    }
+
+    /// An `address` encoded to a 32-byte word, left-padded with zeros.
+    fn address_word(hex: &str) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        word[12..].copy_from_slice(&bytes::<20>(hex));
+        word
+    }
+
+    // The `Mail` example of the EIP-712 specification, signed by "Cow" with the key keccak256("cow")
+    const MAIL_DOMAIN_SEPARATOR: &str = "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f";
+    const MAIL_STRUCT_HASH: &str = "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e";
+    const MAIL_DIGEST: &str = "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2";
+    const MAIL_SIGNATURE: &str = "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d\
+                                  07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562";
+    const COW_ADDRESS: &str = "cd2a3d9f938e13cd947ec05abc7fe734df8dd826";
+    const BOB_ADDRESS: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    #[test]
+    fn typed_data_digest_matches_the_specification_example() {
+        // The example's domain has a verifyingContract, which the signer's own domain leaves out
+        let domain_type = "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)";
+        let mut chain_id = [0u8; 32];
+        chain_id[31] = 1;
+        let domain_separator = keccak::hashv(&[
+            &keccak::hash(domain_type.as_bytes()).0,
+            &keccak::hash(b"Ether Mail").0,
+            &keccak::hash(b"1").0,
+            &chain_id,
+            &address_word("cccccccccccccccccccccccccccccccccccccccc"),
+        ])
+        .0;
+        assert_eq!(domain_separator, bytes(MAIL_DOMAIN_SEPARATOR));
+
+        let person_type = "Person(string name,address wallet)";
+        let person = |name: &str, wallet: &str| {
+            keccak::hashv(&[&keccak::hash(person_type.as_bytes()).0, &keccak::hash(name.as_bytes()).0, &address_word(wallet)]).0
+        };
+        let mail_type = format!("Mail(Person from,Person to,string contents){}", person_type);
+        let struct_hash = keccak::hashv(&[
+            &keccak::hash(mail_type.as_bytes()).0,
+            &person("Cow", COW_ADDRESS),
+            &person("Bob", BOB_ADDRESS),
+            &keccak::hash(b"Hello, Bob!").0,
+        ])
+        .0;
+        assert_eq!(struct_hash, bytes(MAIL_STRUCT_HASH));
+
+        assert_eq!(typed_data_digest(&domain_separator, &struct_hash), bytes(MAIL_DIGEST));
+    }
+
+    #[test]
+    fn signing_the_specification_example_gives_its_signature_and_address() {
+        let secret = keccak::hash(b"cow").0;
+
+        let (signature, recovery_id, eth_address) = sign_typed_data(&bytes(MAIL_DIGEST), &secret).unwrap();
+
+        // The example's v is 28
+        assert_eq!((signature, recovery_id + 27, eth_address), (bytes(MAIL_SIGNATURE), 28, bytes(COW_ADDRESS)));
+    }
+}
@@ -0,0 +1,59 @@
+//! The two kinds of keys the signer uses, which have nothing to do with each other:
+//!
+//! - The payer is a Solana `Keypair`, an ed25519 key loaded from `--keypair`. It signs the
+//!   transactions and pays their fees, and it is the `payer` every package is bound to.
+//! - The signer is a secp256k1 `SecretKey`, from `--secret` or random. It signs the data the
+//!   program verifies, and the program only ever sees its public key.
+//!
+//! The curves are different, so one key can't be converted into the other, and deriving the
+//! secp256k1 key from the ed25519 one would tie the identity the program verifies to whoever pays
+//! for the transaction. For reproducible runs, derive the secp256k1 key from a fixed seed with
+//! `secret_key_from_seed` instead.
+
+use libsecp256k1::SecretKey;
+use crate::SignerError;
+
+/// The secp256k1 secret key for a 32-byte `seed`, always the same key for the same seed. The seed
+/// is the key's scalar itself, so it has to be non-zero and below the curve order; other seeds
+/// fail with `SignerError::KeyParse`. Nearly every random 32 bytes is valid, and the seeds that
+/// aren't are rejected rather than silently mapped onto some other key.
+///
+/// For tests and demonstrations only: a key derived from a guessable seed is not secret.
+pub fn secret_key_from_seed(seed: &[u8; 32]) -> Result<SecretKey, SignerError> {
+    Ok(SecretKey::parse(seed)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The secp256k1 curve order n, the first scalar that isn't a valid secret key.
+    const CURVE_ORDER: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+        0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+    ];
+
+    #[test]
+    fn the_same_seed_gives_the_same_key() {
+        let first = secret_key_from_seed(&[0x11; 32]).unwrap();
+        let second = secret_key_from_seed(&[0x11; 32]).unwrap();
+
+        assert_eq!(first.serialize(), second.serialize());
+        assert_eq!(first.serialize(), [0x11; 32]);
+        assert_ne!(secret_key_from_seed(&[0x12; 32]).unwrap().serialize(), first.serialize());
+    }
+
+    #[test]
+    fn seeds_that_are_not_valid_scalars_are_rejected() {
+        let mut above_order = CURVE_ORDER;
+        above_order[31] += 1;
+
+        for seed in [[0; 32], CURVE_ORDER, above_order, [0xff; 32]] {
+            assert!(matches!(secret_key_from_seed(&seed), Err(SignerError::KeyParse(_))));
+        }
+
+        let mut below_order = CURVE_ORDER;
+        below_order[31] -= 1;
+        assert!(secret_key_from_seed(&below_order).is_ok());
+    }
+}
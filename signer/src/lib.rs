@@ -0,0 +1,540 @@
+//! Off-chain signing of the packages verified by the signature verification program, usable by
+//! other tools without going through the signer's command line.
+
+pub mod eip712;
+pub mod keys;
+pub mod vectors;
+
+use libsecp256k1::{Message, PublicKey, SecretKey};
+use shared_types::{
+    nonce_message_hash, parse_pubkey, pubkey_to_eth_address, record_message_hash, signed_message_hash, slot_bound_message_hash, AttestationRecord, ProgramInstruction, SignaturePackage,
+    SignaturePackageV2, SignaturePackageVar, VerifyError,
+    HASH_ALGO_KECCAK256, MAX_BATCH_SIZE,
+};
+use solana_program::pubkey::Pubkey;
+use solana_client::client_error::ClientError;
+use solana_program::instruction::InstructionError;
+use solana_program::keccak;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::TransactionError;
+
+/// Errors returned by the signer.
+#[derive(Debug, thiserror::Error)]
+pub enum SignerError {
+    /// An RPC request to the Solana node failed.
+    #[error("RPC request failed: {0}")]
+    Rpc(Box<ClientError>),
+    /// The package can't be signed with this hash algorithm, the program doesn't accept it.
+    #[error("unable to sign with unknown hash algorithm {0}")]
+    Sign(u8),
+    /// The secret key isn't a valid secp256k1 scalar.
+    #[error("invalid secp256k1 secret key: {0}")]
+    KeyParse(#[from] libsecp256k1::Error),
+    /// No public key could be recovered from the signature, recovery id and digest.
+    #[error("unable to recover the signer: {0}")]
+    Recover(libsecp256k1::Error),
+    /// Reading or writing a file failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// An airdrop wasn't confirmed within the given number of attempts.
+    #[error("airdrop {0} not confirmed after {1} attempts")]
+    AirdropTimeout(Signature, u32),
+}
+
+impl From<ClientError> for SignerError {
+    fn from(err: ClientError) -> Self {
+        SignerError::Rpc(Box::new(err))
+    }
+}
+
+/// The program error a failed transaction carries, if it failed with one of the program's
+/// `ProgramError::Custom` codes.
+pub fn verify_error(error: &TransactionError) -> Option<VerifyError> {
+    match error {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => VerifyError::from_code(*code),
+        _ => None,
+    }
+}
+
+/// The line the signer logs for a transaction that failed with one of the program's errors,
+/// naming the `VerifyError` variant with its code.
+pub fn describe_verify_error(error: &TransactionError) -> Option<String> {
+    verify_error(error).map(|verify_error| format!("Program error: {:?} (code {})", verify_error, verify_error.code()))
+}
+
+/// Signs `digest` as is, without hashing it, for the program's `VerifyPrehashed`. The digest
+/// isn't bound to a program, payer or expiry, so `expiry_unix` is 0, `hash_algo` Keccak-256 and
+/// `tag` zeroed, all ignored by `VerifyPrehashed`.
+pub fn create_prehashed_package(digest: [u8; 32], signer_secret_key: &[u8; 32]) -> Result<SignaturePackage, SignerError> {
+    let secret_key = SecretKey::parse(signer_secret_key)?;
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+    let (signature, recovery_id) = libsecp256k1::sign(&Message::parse(&digest), &secret_key);
+
+    Ok(SignaturePackage {
+        verifier_signature: signature.serialize(),
+        recovery_id: recovery_id.serialize(),
+        public_key,
+        data: digest,
+        expiry_unix: 0,
+        hash_algo: HASH_ALGO_KECCAK256,
+        tag: [0u8; 8],
+    })
+}
+
+/// Signs `message_data` with its `tag` for `program_id` on the cluster with `chain_id`, to be
+/// submitted by `payer` and valid until `expiry_unix`, hashing the signed message with `hash_algo`.
+///
+/// Signing is deterministic: the nonce is derived from the key and message hash with RFC 6979, so
+/// the same inputs always give a byte-identical `verifier_signature` and `recovery_id`. The test
+/// vector in `vectors` depends on this, and so does anything keyed on the signature, such as
+/// replay tracking. The other signing functions use the same `libsecp256k1::sign` and are just as
+/// deterministic.
+#[allow(clippy::too_many_arguments)]
+pub fn create_and_sign_package(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    message_data: [u8; 32],
+    expiry_unix: i64,
+    tag: [u8; 8],
+    hash_algo: u8,
+    signer_secret_key: &[u8; 32],
+) -> Result<SignaturePackage, SignerError> {
+
+    // The domain separator and program id bind the signature to our program, the payer to the
+    // account allowed to submit it and the chain id to one cluster, and the expiry is signed
+    // together with the data so it can't be extended by whoever submits the package. The hash is
+    // computed by the same function the program uses, so the two can't disagree.
+    let message_hash = signed_message_hash(program_id, payer, chain_id, &message_data, expiry_unix, &tag, hash_algo)
+        .ok_or(SignerError::Sign(hash_algo))?;
+
+    let message = Message::parse(&message_hash);
+
+    // Create secret key from input bytes
+    let secret_key = SecretKey::parse(signer_secret_key)?;
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+
+    // Sign the message and get the signature and recovery ID. serialize() writes r || s, both
+    // big-endian, the order the program's secp256k1_recover syscall reads. libsecp256k1::sign
+    // takes no RNG, its nonce is RFC 6979's; a signer with random nonces would break `vectors`
+    let (signature, recovery_id) = libsecp256k1::sign(&message, &secret_key);
+
+    Ok(SignaturePackage {
+        verifier_signature: signature.serialize(),
+        recovery_id: recovery_id.serialize(),
+        public_key,
+        data: message_data,
+        expiry_unix,
+        hash_algo,
+        tag,
+    })
+}
+
+/// Signs the same `message_data` and `tag` with each of `signer_secret_keys`, for a
+/// `VerifySigBatch` or `VerifyThreshold` instruction. The packages are in the order of the keys and
+/// hashed with `hash_algo`.
+#[allow(clippy::too_many_arguments)]
+pub fn sign_with_each(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    message_data: [u8; 32],
+    expiry_unix: i64,
+    tag: [u8; 8],
+    hash_algo: u8,
+    signer_secret_keys: &[[u8; 32]],
+) -> Result<Vec<SignaturePackage>, SignerError> {
+    signer_secret_keys
+        .iter()
+        .map(|secret| create_and_sign_package(program_id, payer, chain_id, message_data, expiry_unix, tag, hash_algo, secret))
+        .collect()
+}
+
+/// Ethereum's 65-byte signature layout, `r || s || v` with `v = recovery_id + 27`, as returned by
+/// `eth_sign` and accepted by `ecrecover` tooling. Only the layout changes: the signature still
+/// covers whatever message it was made over.
+pub fn to_eth_signature(signature: &[u8; 64], recovery_id: u8) -> [u8; 65] {
+    let mut eth_signature = [0u8; 65];
+    eth_signature[..64].copy_from_slice(signature);
+    eth_signature[64] = recovery_id + 27;
+    eth_signature
+}
+
+/// Number of packages a `VerifySigBatch` can verify within `compute_units`, when each package
+/// costs about `units_per_signature`. At least 1, so a single package is still sent if the budget
+/// is too small for it, and at most `MAX_BATCH_SIZE`, which the program rejects beyond.
+pub fn batch_chunk_size(compute_units: u32, units_per_signature: u32) -> usize {
+    let fitting = compute_units / units_per_signature.max(1);
+    (fitting as usize).clamp(1, MAX_BATCH_SIZE)
+}
+
+/// Splits `packages` into `VerifySigBatch` instructions of at most `chunk_size` packages each,
+/// in order, to be sent in separate transactions. Only the last one can be smaller.
+pub fn split_batch(packages: Vec<SignaturePackage>, chunk_size: usize) -> Vec<ProgramInstruction> {
+    packages
+        .chunks(chunk_size.max(1))
+        .map(|chunk| ProgramInstruction::VerifySigBatch(chunk.to_vec()))
+        .collect()
+}
+
+/// Signs `record` for a `VerifyRecord` on the cluster with `chain_id`, submitted by `payer`.
+/// Returns the signature, its recovery id, the signer's public key and the record's Borsh
+/// encoding, the exact bytes that were signed and have to be sent.
+#[allow(clippy::type_complexity)]
+pub fn sign_record(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    record: &AttestationRecord,
+    signer_secret_key: &[u8; 32],
+) -> Result<([u8; 64], u8, [u8; 65], Vec<u8>), SignerError> {
+    let encoded = borsh::to_vec(record)?;
+    let message_hash = record_message_hash(program_id, payer, chain_id, &encoded);
+
+    let secret_key = SecretKey::parse(signer_secret_key)?;
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+
+    let (signature, recovery_id) = libsecp256k1::sign(&Message::parse(&message_hash), &secret_key);
+    Ok((signature.serialize(), recovery_id.serialize(), public_key, encoded))
+}
+
+/// Recovers the public key that signed `digest`, the message hash as is, with `signature`
+/// (`r || s`) and `recovery_id`. Returns the uncompressed public key, `0x04 || x || y`, and its
+/// Ethereum address. Nothing is checked beyond the recovery itself, so any signature recovers
+/// some key: compare it with the expected one.
+pub fn recover_signer_key(digest: &[u8; 32], signature: &[u8; 64], recovery_id: u8) -> Result<([u8; 65], [u8; 20]), SignerError> {
+    let message = Message::parse(digest);
+    let signature = libsecp256k1::Signature::parse_standard(signature).map_err(SignerError::Recover)?;
+    let recovery_id = libsecp256k1::RecoveryId::parse(recovery_id).map_err(SignerError::Recover)?;
+    let public_key = libsecp256k1::recover(&message, &signature, &recovery_id).map_err(SignerError::Recover)?.serialize();
+
+    let eth_address = pubkey_to_eth_address(&parse_pubkey(&public_key).expect("uncompressed keys are 65 bytes"));
+    Ok((public_key, eth_address))
+}
+
+/// Signs `message_data` bound to `slot` for a `VerifySlotBound` on the cluster with `chain_id`,
+/// submitted by `payer`. The program rejects it once the cluster is more than `max_slot_age`
+/// slots past `slot`. Returns the signature, its recovery id and the signer's public key.
+pub fn sign_slot_bound(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    message_data: &[u8; 32],
+    slot: u64,
+    max_slot_age: u64,
+    signer_secret_key: &[u8; 32],
+) -> Result<([u8; 64], u8, [u8; 65]), SignerError> {
+    let message_hash = slot_bound_message_hash(program_id, payer, chain_id, message_data, slot, max_slot_age);
+
+    let secret_key = SecretKey::parse(signer_secret_key)?;
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+
+    let (signature, recovery_id) = libsecp256k1::sign(&Message::parse(&message_hash), &secret_key);
+    Ok((signature.serialize(), recovery_id.serialize(), public_key))
+}
+
+/// Signs `message_data` with `nonce` for a `VerifySigWithNonce` on the cluster with `chain_id`,
+/// submitted by `payer`. The program only accepts it from `payer`, and only while `nonce` is
+/// greater than the last one it accepted for the signer.
+pub fn sign_with_nonce(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    message_data: [u8; 32],
+    nonce: u64,
+    signer_secret_key: &[u8; 32],
+) -> Result<SignaturePackageV2, SignerError> {
+    let message_hash = nonce_message_hash(program_id, payer, chain_id, &message_data, nonce);
+
+    let secret_key = SecretKey::parse(signer_secret_key)?;
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+
+    let (signature, recovery_id) = libsecp256k1::sign(&Message::parse(&message_hash), &secret_key);
+    Ok(SignaturePackageV2 {
+        verifier_signature: signature.serialize(),
+        recovery_id: recovery_id.serialize(),
+        public_key,
+        data: message_data,
+        nonce,
+    })
+}
+
+/// Signs `message_data` for a `VerifySigAnyRecovery` on the cluster with `chain_id`, submitted by
+/// `payer` and valid until `expiry_unix`, and drops the recovery id, returning the signature and
+/// public key as a signing tool that doesn't report the recovery id would. The signed message is
+/// the one `create_and_sign_package` signs, with an all-zero tag and Keccak-256.
+pub fn sign_without_recovery_id(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    message_data: &[u8; 32],
+    expiry_unix: i64,
+    signer_secret_key: &[u8; 32],
+) -> Result<([u8; 64], [u8; 65]), SignerError> {
+    let message_hash = signed_message_hash(program_id, payer, chain_id, message_data, expiry_unix, &[0; 8], HASH_ALGO_KECCAK256)
+        .ok_or(SignerError::Sign(HASH_ALGO_KECCAK256))?;
+    let message = Message::parse(&message_hash);
+
+    let secret_key = SecretKey::parse(signer_secret_key)?;
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+
+    let (signature, _recovery_id) = libsecp256k1::sign(&message, &secret_key);
+    Ok((signature.serialize(), public_key))
+}
+
+/// Signs an EIP-712 typed-data `digest` from `eip712::typed_data_digest` as is, returning the
+/// signature, its recovery id and the signer's Ethereum address for a `VerifyTypedData`.
+pub fn sign_typed_data(
+    digest: &[u8; 32],
+    signer_secret_key: &[u8; 32],
+) -> Result<([u8; 64], u8, [u8; 20]), SignerError> {
+    let secret_key = SecretKey::parse(signer_secret_key)?;
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+
+    let (signature, recovery_id) = libsecp256k1::sign(&Message::parse(digest), &secret_key);
+
+    let eth_address = pubkey_to_eth_address(&parse_pubkey(&public_key).expect("uncompressed keys are 65 bytes"));
+    Ok((signature.serialize(), recovery_id.serialize(), eth_address))
+}
+
+/// Signs an arbitrary-length `message` for a `VerifySigVar` on the cluster with `chain_id`,
+/// submitted by `payer`, valid until `expiry_unix`.
+pub fn create_and_sign_message(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    message: &[u8],
+    expiry_unix: i64,
+    signer_secret_key: &[u8; 32],
+) -> Result<SignaturePackageVar, SignerError> {
+
+    // The whole message is hashed, so it can be any length the program accepts, and its hash is
+    // signed like a package's data, bound to the program, payer and cluster
+    let message_hash = keccak::hash(message);
+    let signed_hash = signed_message_hash(program_id, payer, chain_id, &message_hash.0, expiry_unix, &[0; 8], HASH_ALGO_KECCAK256)
+        .ok_or(SignerError::Sign(HASH_ALGO_KECCAK256))?;
+    let hashed_message = Message::parse(&signed_hash);
+
+    let secret_key = SecretKey::parse(signer_secret_key)?;
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+
+    let (signature, recovery_id) = libsecp256k1::sign(&hashed_message, &secret_key);
+
+    Ok(SignaturePackageVar {
+        verifier_signature: signature.serialize(),
+        recovery_id: recovery_id.serialize(),
+        public_key,
+        message: message.to_vec(),
+        expiry_unix,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_types::{verify_package_locally, HASH_ALGO_SHA256};
+
+    const PROGRAM_ID: Pubkey = Pubkey::new_from_array([0x42; 32]);
+    const PAYER: Pubkey = Pubkey::new_from_array([0x22; 32]);
+    const CHAIN_ID: u64 = 0x4444_4444_4444_4444;
+    const EXPIRY_UNIX: i64 = 1_700_000_000;
+    const SECRET_KEY: [u8; 32] = [0x11; 32];
+
+    fn sign(hash_algo: u8, secret: &[u8; 32]) -> Result<SignaturePackage, SignerError> {
+        create_and_sign_package(&PROGRAM_ID, &PAYER, CHAIN_ID, [0x33; 32], EXPIRY_UNIX, [0; 8], hash_algo, secret)
+    }
+
+    #[test]
+    fn signed_package_recovers_to_its_public_key() {
+        for hash_algo in [HASH_ALGO_KECCAK256, HASH_ALGO_SHA256] {
+            let package = sign(hash_algo, &SECRET_KEY).unwrap();
+            let message_hash = signed_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &package.data, EXPIRY_UNIX, &package.tag, hash_algo).unwrap();
+
+            let (public_key, _) = recover_signer_key(&message_hash, &package.verifier_signature, package.recovery_id).unwrap();
+            assert_eq!(public_key, package.public_key);
+            assert_eq!(public_key, PublicKey::from_secret_key(&SecretKey::parse(&SECRET_KEY).unwrap()).serialize());
+        }
+    }
+
+    #[test]
+    fn signed_package_verifies_locally() {
+        for hash_algo in [HASH_ALGO_KECCAK256, HASH_ALGO_SHA256] {
+            let package = sign(hash_algo, &SECRET_KEY).unwrap();
+            assert_eq!(package.hash_algo, hash_algo);
+
+            let public_key = verify_package_locally(&PROGRAM_ID, &PAYER, CHAIN_ID, &package, EXPIRY_UNIX).unwrap();
+            assert_eq!(public_key.to_bytes()[..], package.public_key[1..]);
+        }
+    }
+
+    #[test]
+    fn package_fails_when_checked_with_the_other_hash_algo() {
+        let mut package = sign(HASH_ALGO_SHA256, &SECRET_KEY).unwrap();
+        package.hash_algo = HASH_ALGO_KECCAK256;
+
+        assert_eq!(verify_package_locally(&PROGRAM_ID, &PAYER, CHAIN_ID, &package, EXPIRY_UNIX).err(), Some(VerifyError::PublicKeyMismatch));
+    }
+
+    #[test]
+    fn signing_the_same_input_gives_the_same_signature() {
+        for hash_algo in [HASH_ALGO_KECCAK256, HASH_ALGO_SHA256] {
+            let first = sign(hash_algo, &SECRET_KEY).unwrap();
+            let second = sign(hash_algo, &SECRET_KEY).unwrap();
+
+            assert_eq!(first.verifier_signature, second.verifier_signature);
+            assert_eq!(first.recovery_id, second.recovery_id);
+        }
+
+        let prehashed = create_prehashed_package([0x33; 32], &SECRET_KEY).unwrap();
+        assert_eq!(create_prehashed_package([0x33; 32], &SECRET_KEY).unwrap(), prehashed);
+        // Only the inputs pick the nonce, another digest gets another signature
+        assert_ne!(create_prehashed_package([0x34; 32], &SECRET_KEY).unwrap().verifier_signature, prehashed.verifier_signature);
+    }
+
+    #[test]
+    fn signing_rejects_an_invalid_secret_key() {
+        assert!(matches!(sign(HASH_ALGO_KECCAK256, &[0; 32]), Err(SignerError::KeyParse(_))));
+        assert!(matches!(create_prehashed_package([0x33; 32], &[0xff; 32]), Err(SignerError::KeyParse(_))));
+    }
+
+    #[test]
+    fn signing_rejects_an_unknown_hash_algo() {
+        assert!(matches!(sign(2, &SECRET_KEY), Err(SignerError::Sign(2))));
+    }
+
+    #[test]
+    fn rejected_signature_is_described_by_its_program_error() {
+        let mut package = sign(HASH_ALGO_KECCAK256, &SECRET_KEY).unwrap();
+        package.verifier_signature[0] ^= 1;
+        let err = verify_package_locally(&PROGRAM_ID, &PAYER, CHAIN_ID, &package, EXPIRY_UNIX).err().unwrap();
+
+        // The error the program fails the transaction with for the same package
+        let error = TransactionError::InstructionError(0, InstructionError::Custom(err.code()));
+        assert_eq!(verify_error(&error), Some(err));
+        assert_eq!(describe_verify_error(&error), Some(format!("Program error: {:?} (code {})", err, err.code())));
+        assert_eq!(describe_verify_error(&TransactionError::InstructionError(0, InstructionError::Custom(1))).unwrap(), "Program error: PublicKeyMismatch (code 1)");
+    }
+
+    #[test]
+    fn other_errors_are_not_described() {
+        assert_eq!(describe_verify_error(&TransactionError::InstructionError(0, InstructionError::Custom(u32::MAX))), None);
+        assert_eq!(describe_verify_error(&TransactionError::InstructionError(0, InstructionError::InvalidArgument)), None);
+        assert_eq!(describe_verify_error(&TransactionError::AccountNotFound), None);
+    }
+
+    #[test]
+    fn sign_with_each_signs_the_same_data_with_every_key() {
+        let secrets = [[0x11; 32], [0x12; 32], [0x13; 32]];
+        let packages = sign_with_each(&PROGRAM_ID, &PAYER, CHAIN_ID, [0x33; 32], EXPIRY_UNIX, [0; 8], HASH_ALGO_KECCAK256, &secrets).unwrap();
+
+        assert_eq!(packages.len(), secrets.len());
+        for (package, secret) in packages.iter().zip(&secrets) {
+            assert_eq!(package.data, [0x33; 32]);
+            let message_hash = signed_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &package.data, EXPIRY_UNIX, &package.tag, HASH_ALGO_KECCAK256).unwrap();
+            let (public_key, _) = recover_signer_key(&message_hash, &package.verifier_signature, package.recovery_id).unwrap();
+            assert_eq!(public_key, PublicKey::from_secret_key(&SecretKey::parse(secret).unwrap()).serialize());
+            assert!(verify_package_locally(&PROGRAM_ID, &PAYER, CHAIN_ID, package, EXPIRY_UNIX).is_ok());
+        }
+    }
+
+    #[test]
+    fn eth_signature_appends_v_as_27_or_28() {
+        // `web3.eth.accounts.sign("Some data", …)` from the web3.js documentation, whose v is 0x1c
+        let eth_signature = "b91467e570a6466aa9e9876cbcd013baba02900b8979d43fe208a4a4f339f5fd6007e74cd82e037b800186422fc2da167c747ef045e5d18a5f5d4300f8e1a0291c";
+        let signature: [u8; 64] = hex::decode(&eth_signature[..128]).unwrap().try_into().unwrap();
+
+        assert_eq!(hex::encode(to_eth_signature(&signature, 1)), eth_signature);
+        let with_v_27 = to_eth_signature(&signature, 0);
+        assert_eq!((&with_v_27[..64], with_v_27[64]), (&signature[..], 27));
+    }
+
+    #[test]
+    fn split_batch_chunks_the_packages_in_order() {
+        let packages: Vec<_> = (0..40u8).map(|data| create_prehashed_package([data; 32], &SECRET_KEY).unwrap()).collect();
+
+        let instructions = split_batch(packages.clone(), 16);
+
+        let chunks: Vec<_> = instructions
+            .into_iter()
+            .map(|instruction| match instruction {
+                ProgramInstruction::VerifySigBatch(chunk) => chunk,
+                other => panic!("expected a VerifySigBatch, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(chunks.iter().map(Vec::len).collect::<Vec<_>>(), [16, 16, 8]);
+        assert_eq!(chunks.concat(), packages);
+    }
+
+    #[test]
+    fn batch_chunk_size_fits_the_budget_within_bounds() {
+        assert_eq!(batch_chunk_size(400_000, 25_000), 16);
+        assert_eq!(batch_chunk_size(10_000, 25_000), 1);
+        assert_eq!(batch_chunk_size(u32::MAX, 0), MAX_BATCH_SIZE);
+    }
+
+    #[test]
+    fn signed_record_recovers_to_its_public_key() {
+        let record = AttestationRecord { epoch: 7, root: [0x33; 32] };
+
+        let (signature, recovery_id, public_key, encoded) = sign_record(&PROGRAM_ID, &PAYER, CHAIN_ID, &record, &SECRET_KEY).unwrap();
+
+        assert_eq!(encoded, borsh::to_vec(&record).unwrap());
+        let message_hash = record_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &encoded);
+        assert_eq!(recover_signer_key(&message_hash, &signature, recovery_id).unwrap().0, public_key);
+    }
+
+    #[test]
+    fn message_package_is_signed_over_its_hash_as_package_data() {
+        let message = b"Hello from secp256k1!";
+        let package = create_and_sign_message(&PROGRAM_ID, &PAYER, CHAIN_ID, message, EXPIRY_UNIX, &SECRET_KEY).unwrap();
+
+        let message_hash =
+            signed_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &keccak::hash(message).0, EXPIRY_UNIX, &[0; 8], HASH_ALGO_KECCAK256).unwrap();
+        assert_eq!(recover_signer_key(&message_hash, &package.verifier_signature, package.recovery_id).unwrap().0, package.public_key);
+        assert_ne!(recover_signer_key(&keccak::hash(message).0, &package.verifier_signature, package.recovery_id).unwrap().0, package.public_key);
+    }
+
+    #[test]
+    fn signature_without_recovery_id_recovers_with_id_0_or_1() {
+        let (signature, public_key) = sign_without_recovery_id(&PROGRAM_ID, &PAYER, CHAIN_ID, &[0x33; 32], EXPIRY_UNIX, &SECRET_KEY).unwrap();
+
+        let message_hash = signed_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &[0x33; 32], EXPIRY_UNIX, &[0; 8], HASH_ALGO_KECCAK256).unwrap();
+        assert!([0, 1].iter().any(|&recovery_id| recover_signer_key(&message_hash, &signature, recovery_id).unwrap().0 == public_key));
+    }
+
+    #[test]
+    fn nonce_package_recovers_only_for_its_payer() {
+        let package = sign_with_nonce(&PROGRAM_ID, &PAYER, CHAIN_ID, [0x33; 32], 7, &SECRET_KEY).unwrap();
+
+        let message_hash = nonce_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &package.data, package.nonce);
+        assert_eq!(recover_signer_key(&message_hash, &package.verifier_signature, package.recovery_id).unwrap().0, package.public_key);
+        let other_payer = nonce_message_hash(&PROGRAM_ID, &Pubkey::new_unique(), CHAIN_ID, &package.data, package.nonce);
+        assert_ne!(recover_signer_key(&other_payer, &package.verifier_signature, package.recovery_id).unwrap().0, package.public_key);
+    }
+
+    #[test]
+    fn underlying_errors_map_to_their_variant() {
+        let rpc_error: ClientError = solana_client::rpc_request::RpcError::ForUser("airdrop request failed".to_string()).into();
+        assert!(matches!(SignerError::from(rpc_error), SignerError::Rpc(_)));
+        let io_error = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        assert!(matches!(SignerError::from(io_error), SignerError::Io(_)));
+        assert!(matches!(SignerError::from(libsecp256k1::Error::InvalidSecretKey), SignerError::KeyParse(_)));
+
+        // r = 0 is out of range, so nothing is recovered
+        assert!(matches!(recover_signer_key(&[0x33; 32], &[0; 64], 0), Err(SignerError::Recover(_))));
+        assert!(matches!(recover_signer_key(&[0x33; 32], &[0x11; 64], 4), Err(SignerError::Recover(_))));
+    }
+
+    #[test]
+    fn prehashed_package_verifies_only_as_prehashed() {
+        let package = create_prehashed_package([0x33; 32], &SECRET_KEY).unwrap();
+
+        let recovered = shared_types::verify_prehashed_locally(&package).map(|pubkey| pubkey.to_bytes());
+        assert_eq!(recovered, Ok(package.public_key[1..].try_into().unwrap()));
+        assert!(verify_package_locally(&PROGRAM_ID, &PAYER, CHAIN_ID, &package, 0).is_err());
+
+        // And a hashed package isn't a signature over its data
+        let hashed = sign(HASH_ALGO_KECCAK256, &SECRET_KEY).unwrap();
+        assert_eq!(shared_types::verify_prehashed_locally(&hashed).err(), Some(VerifyError::PublicKeyMismatch));
+    }
+}
@@ -1,136 +1,2424 @@
-use borsh::{to_vec, BorshDeserialize, BorshSerialize};
-use libsecp256k1::{Message, PublicKey, SecretKey};
-use log::info;
+mod blockhash;
+mod cli;
+mod output;
+mod rpc;
+
+use borsh::to_vec;
+use clap::Parser;
+use libsecp256k1::{PublicKey, SecretKey};
+use log::{debug, error, info, warn};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use futures::{future, stream, StreamExt};
+use solana_client::nonblocking::pubsub_client::{PubsubClient, PubsubClientError};
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSignatureSubscribeConfig;
+use solana_client::rpc_response::{ProcessedSignatureResult, Response, RpcSignatureResult};
+use solana_client::rpc_client::RpcClient as BlockingRpcClient;
 use solana_program::instruction::{AccountMeta, Instruction};
 use solana_program::pubkey::Pubkey;
-use solana_program::{keccak};
+use solana_program::{bpf_loader, bpf_loader_deprecated, bpf_loader_upgradeable, keccak, system_program};
 use solana_sdk::commitment_config::CommitmentConfig;
-use solana_sdk::signature::{Keypair, Signer};
-use solana_sdk::transaction::Transaction;
-use std::str::FromStr;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::hash::Hash;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::signature::{read_keypair_file, write_keypair_file, Keypair, Signature, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+use std::io::{self, Read, Write};
+use std::{env, fs};
+use std::path::{Path, PathBuf};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use rand::thread_rng;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use crate::blockhash::{fetch_blockhash, is_already_processed, is_retryable, BlockhashCache, SEND_MAX_ATTEMPTS};
+use crate::cli::{Cli, Cluster, Command};
+use crate::output::{Status, Submission};
+use crate::rpc::SolanaRpc;
+use secp256k1_signer_example::keys::secret_key_from_seed;
+use secp256k1_signer_example::{batch_chunk_size, create_and_sign_message, create_and_sign_package, create_prehashed_package, describe_verify_error, eip712, recover_signer_key, sign_record, sign_slot_bound, sign_typed_data, sign_with_each, sign_without_recovery_id, split_batch, to_eth_signature, SignerError};
+use shared_types::{
+    chain_id_from_genesis_hash, parse_pubkey, pubkey_to_eth_address, verify_package_locally, verify_prehashed_locally, AttestationRecord, ProgramInstruction,
+    ProgramState, SignaturePackage, VerifyError, AUDIT_SEED, CONFIG_SEED, REGISTRY_SEED, STATE_SEED,
+};
+
+/// Reasons `check_deploy` rejects the program id.
+#[derive(Debug, thiserror::Error)]
+enum DeployError {
+    #[error("unable to load program account {0}, check --program-id and the cluster: {1}")]
+    Unavailable(Pubkey, Box<ClientError>),
+    #[error("account {0} is not executable, it isn't a deployed program")]
+    NotExecutable(Pubkey),
+    #[error("account {0} is owned by {1}, not a BPF loader")]
+    NotBpfProgram(Pubkey, Pubkey),
+}
+
+/// Reasons a run stops, printed once by `main` before it exits with status 1. Failures the
+/// commands already reported in detail, such as a rejected transaction, are summed up by `Failed`.
+#[derive(Debug, thiserror::Error)]
+enum RunError {
+    #[error("{0}, pass the cluster's --chain-id")]
+    MissingChainId(&'static str),
+    #[error("unable to load payer keypair: {0}")]
+    Payer(Box<dyn std::error::Error>),
+    #[error("unable to load package: {0}")]
+    Package(Box<dyn std::error::Error>),
+    #[error("unable to load data: {0}")]
+    Data(Box<dyn std::error::Error>),
+    #[error("unable to write package: {0}")]
+    WritePackage(Box<dyn std::error::Error>),
+    #[error("unable to write keypairs: {0}")]
+    WriteKeypairs(Box<dyn std::error::Error>),
+    #[error("unable to fetch {0}: {1}")]
+    Fetch(String, Box<ClientError>),
+    #[error("signature package failed local verification: {0:?}")]
+    LocalVerification(VerifyError),
+    #[error("the program's config {0} doesn't exist, its upgrade authority has to run init-config first")]
+    ConfigMissing(Pubkey),
+    #[error("{0}")]
+    Prefund(Box<dyn std::error::Error>),
+    #[error("unable to encode the instruction: {0}")]
+    EncodeInstruction(#[from] io::Error),
+    #[error("unable to encode the transaction: {0}")]
+    EncodeTransaction(#[from] bincode::Error),
+    #[error("unable to encode the package: {0}")]
+    EncodePackage(#[from] serde_json::Error),
+    #[error("unable to write the report: {0}")]
+    Report(io::Error),
+    #[error(transparent)]
+    Sign(#[from] SignerError),
+    #[error(transparent)]
+    Deploy(#[from] DeployError),
+    #[error(transparent)]
+    Key(Box<dyn std::error::Error>),
+    #[error("{0} failed")]
+    Failed(&'static str),
+}
 
+// Defaults for the command line arguments
 const PROGRAM_ID: &str = "4muvyr2m6AFioKUjuyMXyLTYztykfXTTUemg4ZnD38bi";
 const RPC_URL: &str = "http://localhost:8899";
+// Compute unit limit requested for each transaction, the runtime's default for a single instruction
+const DEFAULT_COMPUTE_UNITS: u32 = 200_000;
+// Estimated compute units per package of a VerifySigBatch, mostly the secp256k1 recovery
+const DEFAULT_UNITS_PER_SIGNATURE: u32 = 50_000;
+// Seconds to wait for a transaction to be confirmed
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+// Solana CLI keypair used as the payer when --keypair isn't given, relative to the home directory
+const DEFAULT_KEYPAIR_PATH: &str = ".config/solana/id.json";
+// Airdrop confirmation polling, the delay doubles after each attempt up to the maximum
+const AIRDROP_MAX_ATTEMPTS: u32 = 10;
+const AIRDROP_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const AIRDROP_MAX_BACKOFF: Duration = Duration::from_secs(8);
+// Base fee of a transaction with a single signature
+const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+// Lamports airdropped to each generated payer, 1 SOL
+const AIRDROP_LAMPORTS: u64 = 1_000_000_000;
+// How long a signed package stays valid
+const SIGNATURE_TTL_SECS: i64 = 300;
+// How long --count waits for in-flight transactions after Ctrl-C before giving up on them
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    init_logger(cli.verbose);
+
+    if let Err(err) = run_cli(&cli).await {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+/// Runs the subcommands that make no RPC calls, or `run` with the client `--blocking` picks.
+async fn run_cli(cli: &Cli) -> Result<(), RunError> {
+    if let Some(Command::Verify { package, payer }) = &cli.command {
+        let chain_id = cli.chain_id.ok_or(RunError::MissingChainId("verify makes no RPC calls"))?;
+        let payer = payer_pubkey(cli, *payer)?;
+        if !verify_package_file(&cli.program_id, &payer, chain_id, package)? {
+            return Err(RunError::Failed("verify"));
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::VerifyFile { file, payer, submit: false, strict }) = &cli.command {
+        let chain_id = cli.chain_id.ok_or(RunError::MissingChainId("verify-file makes no RPC calls without --submit"))?;
+        let payer = payer_pubkey(cli, *payer)?;
+        if !verify_package_lines(&cli.program_id, &payer, chain_id, file, *strict)? {
+            return Err(RunError::Failed("verify-file"));
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::RecoverAddress { sig, recid, message, prehashed }) = &cli.command {
+        recover_address(sig, *recid, message, *prehashed).map_err(RunError::Key)?;
+        return Ok(());
+    }
+
+    if let Some(Command::Keygen { out, seed }) = &cli.command {
+        keygen(out, seed.as_ref()).map_err(RunError::Key)?;
+        return Ok(());
+    }
 
-#[derive(BorshDeserialize, BorshSerialize)]
-pub struct SignaturePackage {
-    pub verifier_signature: [u8; 64],
-    pub recovery_id: u8,
-    pub public_key: [u8; 65],
-    pub data: [u8; 32],
+    // The timeout also bounds each RPC request, which is all that limits the blocking client
+    let commitment = CommitmentConfig::from(cli.commitment);
+    let timeout = Duration::from_secs(cli.timeout);
+    if cli.blocking {
+        let client = BlockingRpcClient::new_with_timeout_and_commitment(cli.rpc_url(), timeout, commitment);
+        run(&client, cli).await
+    } else {
+        let client = RpcClient::new_with_timeout_and_commitment(cli.rpc_url(), timeout, commitment);
+        run(&client, cli).await
+    }
 }
 
-#[derive(BorshSerialize, BorshDeserialize)]
-pub enum ProgramInstruction {
-    VerifySig(SignaturePackage)
+/// `payer`, or the public key of the `--keypair` payer when it isn't given.
+fn payer_pubkey(cli: &Cli, payer: Option<Pubkey>) -> Result<Pubkey, RunError> {
+    match payer {
+        Some(payer) => Ok(payer),
+        None => Ok(load_payer(cli.keypair.as_deref()).map_err(RunError::Payer)?.pubkey()),
+    }
 }
 
-#[tokio::main]
-async fn main() {
+/// Seconds since the Unix epoch, 0 for a clock set before it.
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
+
+/// Logs to stderr with `log_filter(verbose)`. `RUST_LOG` replaces the filter when set.
+fn init_logger(verbose: u8) {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_filter(verbose))).init();
+}
+
+/// `info`, or `debug`/`trace` with -v/-vv, for this crate and `warn` for its dependencies, in
+/// `RUST_LOG` syntax.
+fn log_filter(verbose: u8) -> String {
+    let level = match verbose {
+        0 => "info",
+        1 => "debug",
+        _ => "trace",
+    };
+    format!("warn,secp256k1_signer_example={}", level)
+}
+
+/// Signs and submits the packages, using either RPC client.
+async fn run(client: &impl SolanaRpc, cli: &Cli) -> Result<(), RunError> {
+    if let Some(Command::CheckDeploy) = cli.command {
+        check_deploy(client, &cli.program_id).await?;
+        info!("Program {} is deployed", cli.program_id);
+        return Ok(());
+    }
+
+    if let Some(Command::QueryState { payer }) = &cli.command {
+        let payer = payer_pubkey(cli, *payer)?;
+        if !query_state(client, &cli.program_id, &payer).await {
+            return Err(RunError::Failed("query-state"));
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Broadcast { tx }) = &cli.command {
+        if !broadcast(client, cli, tx).await {
+            return Err(RunError::Failed("broadcast"));
+        }
+        return Ok(());
+    }
+
+    if let Some(count) = cli.fund {
+        fund_keypairs(client, cli, count, Path::new(&cli.fund_dir)).await?;
+        return Ok(());
+    }
+
+    // Packages are bound to one cluster, so they are signed for the chain id its config stores
+    let chain_id = match (cli.chain_id, cli.dry_run || cli.offline) {
+        (Some(chain_id), _) => chain_id,
+        (None, true) => return Err(RunError::MissingChainId("--dry-run and --offline make no RPC calls")),
+        (None, false) => {
+            let genesis_hash = client
+                .get_genesis_hash()
+                .await
+                .map_err(|err| RunError::Fetch("the genesis hash for the chain id".to_string(), Box::new(err)))?;
+            chain_id_from_genesis_hash(&genesis_hash.to_bytes())
+        }
+    };
+    debug!("Signing for chain id {}", chain_id);
+
+    // Load your Solana wallet keypair, or create a throwaway one funded by an airdrop
+    let payer = if cli.generate {
+        let payer = Keypair::new();
+        if cli.dry_run {
+            // Nothing is sent, so there is nothing to fund
+        } else if cli.cluster == Cluster::Localhost {
+            match request_airdrop(client, cli, &payer.pubkey(), AIRDROP_LAMPORTS).await {
+                Ok(_) => info!("Airdrop successful!"),
+                Err(err) => error!("Airdrop failed: {}", err),
+            }
+        } else {
+            // Airdrops fail on mainnet, the generated payer has to be funded some other way
+            warn!("Skipping airdrop on {:?}, fund {} before sending transactions", cli.cluster, payer.pubkey());
+        }
+        payer
+    } else {
+        load_payer(cli.keypair.as_deref()).map_err(RunError::Payer)?
+    };
+
+    let program_id = cli.program_id;
+
+    if let Some(Command::InitConfig) = cli.command {
+        let submission = send_instruction(client, cli, &payer, initialize_config_instruction(&program_id, &payer.pubkey(), chain_id)?).await;
+        if submission.status == Status::Failed {
+            return Err(RunError::Failed("init-config"));
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::VerifyFile { file, submit: true, strict, .. }) = &cli.command {
+        if !submit_package_lines(client, cli, &payer, chain_id, file, *strict).await? {
+            return Err(RunError::Failed("verify-file --submit"));
+        }
+        return Ok(());
+    }
+
+    // Use the provided secp256k1 secret, or create a random one
+    let secret = match cli.secret {
+        Some(secret) => secret,
+        None => {
+            let rng = &mut thread_rng();
+            SecretKey::random(rng).serialize()
+        }
+    };
+
+    // Submit a package signed earlier with --out, or sign a new one
+    let commitment = match &cli.package {
+        Some(path) => read_package(path).map_err(RunError::Package)?,
+        None => {
+            // Create some data we want to store on-chain, from --data-file or random. We'll use this to create our signature.
+            let data = match &cli.data_file {
+                Some(path) => load_data(path, cli.raw || cli.prehashed).map_err(RunError::Data)?,
+                None => Pubkey::new_unique().to_bytes(),
+            };
+
+            if cli.prehashed {
+                // The data is the digest, signed as is
+                create_prehashed_package(data, &secret)?
+            } else {
+                // The program rejects the package once this time has passed.
+                let expiry_unix = unix_now() + SIGNATURE_TTL_SECS;
+
+                // Use our data and secret to create a signed package to send to the Solana program.
+                create_and_sign_package(
+                    &program_id,
+                    &payer.pubkey(),
+                    chain_id,
+                    data,
+                    expiry_unix,
+                    cli.tag.unwrap_or_default(),
+                    cli.hash.into(),
+                    &secret)?
+            }
+        }
+    };
+    // Any --secrets sign the same data with the same expiry, tag and hash
+    let (data, expiry_unix, tag, hash_algo) = (commitment.data, commitment.expiry_unix, commitment.tag, commitment.hash_algo);
+
+    // Catch an expired or badly signed package before paying for a transaction
+    let local_result = if cli.prehashed {
+        verify_prehashed_locally(&commitment)
+    } else {
+        verify_package_locally(&program_id, &payer.pubkey(), chain_id, &commitment, unix_now())
+    };
+    if let Err(err) = local_result {
+        if !cli.force {
+            return Err(RunError::LocalVerification(err));
+        }
+        warn!("Signature package failed local verification, sending it anyway: {:?}", err);
+    }
+
+    if cli.eth_format {
+        println!("0x{}", hex::encode(to_eth_signature(&commitment.verifier_signature, commitment.recovery_id)));
+    }
 
-    let client = RpcClient::new_with_commitment(RPC_URL.to_string(), CommitmentConfig::confirmed());
+    if let Some(path) = &cli.out {
+        write_package(path, &commitment).map_err(RunError::WritePackage)?;
+        info!("Wrote signature package to {}", path);
+    }
+
+    // The program stores the verified data in a PDA derived from the payer, counts all
+    // verifications in its config PDA and records them in its audit PDA
+    let (state_pda, _bump) = Pubkey::find_program_address(&[STATE_SEED, payer.pubkey().as_ref()], &program_id);
+    let (config_pda, _bump) = Pubkey::find_program_address(&[CONFIG_SEED], &program_id);
+    let (audit_pda, _bump) = Pubkey::find_program_address(&[AUDIT_SEED], &program_id);
+    let accounts = vec![
+        AccountMeta::new(payer.pubkey(), true),
+        AccountMeta::new(state_pda, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new(config_pda, false),
+        AccountMeta::new(audit_pda, false),
+    ];
+
+    // Show what would be sent and stop before the first RPC call
+    if cli.dry_run {
+        let instruction_data = to_vec(&verify_instruction(cli, commitment.clone()))?;
+        let instruction = Instruction::new_with_bytes(program_id, instruction_data.as_slice(), accounts);
+        print_dry_run(cli, &payer.pubkey(), &commitment, instruction)?;
+        return Ok(());
+    }
 
-    // Load your Solana wallet keypair
-    let payer = Keypair::new();
-    let airdrop_amount = 1_000_000_000; // 1 SOL in lamports
-    match request_airdrop(&client, &payer.pubkey(), airdrop_amount).await {
-        Ok(_) => info!("Airdrop successful!"),
-        Err(err) => info!("Airdrop failed: {}", err),
+    // Sign with the given blockhash and leave sending to `broadcast` on a networked machine
+    if let Some(blockhash) = cli.blockhash {
+        let instruction_data = to_vec(&verify_instruction(cli, commitment))?;
+        let instruction = Instruction::new_with_bytes(program_id, instruction_data.as_slice(), accounts);
+        let transaction = offline_transaction(cli, &payer, instruction, blockhash);
+        info!("Signed transaction {} offline", transaction.signatures[0]);
+        println!("{}", encode_transaction(&transaction)?);
+        return Ok(());
     }
 
-    // Your program ID (replace with your actual program ID)
-    let program_id = Pubkey::from_str(PROGRAM_ID).unwrap();
+    // Sending to a program id that isn't deployed only fails with a vague error later
+    check_deploy(client, &program_id).await?;
 
-    // Create our secp256k1 secret. Normally, the secret is created and loaded elsewhere
-    let rng = &mut thread_rng();
-    let secret = SecretKey::random(rng).serialize();
+    // The config is created once per deployment by the upgrade authority with `init-config`. Only
+    // an account that doesn't exist counts as missing, a failed lookup stops the run
+    let config_exists = account_exists(client, &config_pda)
+        .await
+        .map_err(|err| RunError::Fetch(format!("the config {}", config_pda), Box::new(err)))?;
+    if !config_exists {
+        return Err(RunError::ConfigMissing(config_pda));
+    }
+    let state_missing = !account_exists(client, &state_pda)
+        .await
+        .map_err(|err| RunError::Fetch(format!("the state account {}", state_pda), Box::new(err)))?;
+
+    // Running out of lamports halfway fails with an error that doesn't say so
+    if cli.prefund_check {
+        prefund_check(client, cli, &payer.pubkey(), state_missing).await.map_err(RunError::Prefund)?;
+    }
 
-    // Create some data we want to store on-chain. We'll use this to create our signature.
-    let data = Pubkey::new_unique().to_bytes();
+    // The state PDA has to exist before the program can store verified data in it
+    if state_missing {
+        let instruction_data = to_vec(&ProgramInstruction::InitializeState)?;
+        let instruction = Instruction::new_with_bytes(
+            program_id,
+            instruction_data.as_slice(),
+            accounts.clone(),
+        );
+        send_instruction(client, cli, &payer, instruction).await;
+    }
 
-    // Use our data and secret to create a signed package to send to the Solana program.
-    let commitment = create_and_sign_package(
-        data,
-        &secret).unwrap();
+    // Measure throughput with many packages over the same accounts instead of the single
+    // verification below
+    if let Some(count) = cli.count {
+        submit_many(client, cli, &payer, &secret, chain_id, &accounts, count, tokio::signal::ctrl_c).await;
+        return Ok(());
+    }
 
     // Create the instruction to call our program
-    let instruction_data = to_vec(&ProgramInstruction::VerifySig(commitment)).unwrap();
+    let instruction_data = to_vec(&verify_instruction(cli, commitment))?;
     let instruction = Instruction::new_with_bytes(
         program_id,
         instruction_data.as_slice(),
-        vec![
-            AccountMeta::new(payer.pubkey(), true),
-        ],
+        accounts.clone(),
+    );
+    let submission = send_instruction(client, cli, &payer, instruction).await;
+
+    // The verification is the result, the demonstrations below would add more output
+    if cli.json_output {
+        submission.print_json()?;
+        if submission.status == Status::Failed {
+            return Err(RunError::Failed("verification"));
+        }
+        return Ok(());
+    }
+
+    // Messages don't have to be 32 bytes, the program can also verify a signature over arbitrary content
+    let message_package = create_and_sign_message(
+        &program_id,
+        &payer.pubkey(),
+        chain_id,
+        b"Hello from secp256k1!",
+        expiry_unix,
+        &secret)?;
+    let instruction_data = to_vec(&ProgramInstruction::VerifySigVar(message_package))?;
+    let instruction = Instruction::new_with_bytes(
+        program_id,
+        instruction_data.as_slice(),
+        accounts.clone(),
     );
+    send_instruction(client, cli, &payer, instruction).await;
+
+    // A signature without its recovery id, the program tries each id until one recovers the key
+    if cli.without_recovery_id {
+        let (signature, public_key) = sign_without_recovery_id(&program_id, &payer.pubkey(), chain_id, &data, expiry_unix, &secret)?;
+        let instruction_data = to_vec(&ProgramInstruction::VerifySigAnyRecovery { signature, public_key, data, expiry_unix })?;
+        let instruction = Instruction::new_with_bytes(
+            program_id,
+            instruction_data.as_slice(),
+            accounts.clone(),
+        );
+        send_instruction(client, cli, &payer, instruction).await;
+    }
+
+    // The data as EIP-712 typed data, hashed here so the program only recovers the signer
+    if cli.typed_data {
+        let domain = eip712::domain_separator(eip712::DOMAIN_NAME, eip712::DOMAIN_VERSION, chain_id);
+        let digest = eip712::typed_data_digest(&domain, &eip712::attestation_hash(&data));
+        let (signature, recovery_id, eth_address) = sign_typed_data(&digest, &secret)?;
+        let instruction_data = to_vec(&ProgramInstruction::VerifyTypedData { digest, signature, recovery_id, eth_address })?;
+        let instruction = Instruction::new_with_bytes(
+            program_id,
+            instruction_data.as_slice(),
+            accounts.clone(),
+        );
+        send_instruction(client, cli, &payer, instruction).await;
+    }
+
+    // The data bound to the current slot, rejected once it is too old
+    if let Some(max_slot_age) = cli.slot_bound {
+        let slot = client
+            .get_slot()
+            .await
+            .map_err(|err| RunError::Fetch("the current slot".to_string(), Box::new(err)))?;
+        let (signature, recovery_id, public_key) =
+            sign_slot_bound(&program_id, &payer.pubkey(), chain_id, &data, slot, max_slot_age, &secret)?;
+        let instruction_data = to_vec(&ProgramInstruction::VerifySlotBound { signature, recovery_id, public_key, data, slot, max_slot_age })?;
+        let instruction = Instruction::new_with_bytes(
+            program_id,
+            instruction_data.as_slice(),
+            accounts.clone(),
+        );
+        send_instruction(client, cli, &payer, instruction).await;
+    }
+
+    // The data as the root of a structured record, decoded and checked by the program
+    if let Some(epoch) = cli.record_epoch {
+        let record = AttestationRecord { epoch, root: data };
+        let (signature, recovery_id, public_key, record) = sign_record(&program_id, &payer.pubkey(), chain_id, &record, &secret)?;
+        let instruction_data = to_vec(&ProgramInstruction::VerifyRecord { signature, recovery_id, public_key, record })?;
+        let instruction = Instruction::new_with_bytes(
+            program_id,
+            instruction_data.as_slice(),
+            accounts.clone(),
+        );
+        send_instruction(client, cli, &payer, instruction).await;
+    }
+
+    // A commitment to the data and its reveal, proven to come from the same key
+    if cli.same_signer {
+        let pkg_a = create_and_sign_package(&program_id, &payer.pubkey(), chain_id, keccak::hash(&data).0, expiry_unix, tag, hash_algo, &secret)?;
+        let pkg_b = create_and_sign_package(&program_id, &payer.pubkey(), chain_id, data, expiry_unix, tag, hash_algo, &secret)?;
+        let instruction_data = to_vec(&ProgramInstruction::VerifySameSigner { pkg_a, pkg_b })?;
+        let instruction = Instruction::new_with_bytes(
+            program_id,
+            instruction_data.as_slice(),
+            accounts.clone(),
+        );
+        send_instruction(client, cli, &payer, instruction).await;
+    }
+
+    // Several keys signing the same data, verified together
+    if !cli.secrets.is_empty() {
+        let packages = sign_with_each(&program_id, &payer.pubkey(), chain_id, data, expiry_unix, tag, hash_algo, &cli.secrets)?;
+        match cli.threshold {
+            Some(threshold) => {
+                // Threshold verification counts only registered signers, so it reads the registry first
+                let (registry_pda, _bump) = Pubkey::find_program_address(&[REGISTRY_SEED], &program_id);
+                let mut threshold_accounts = vec![AccountMeta::new_readonly(registry_pda, false)];
+                threshold_accounts.extend(accounts);
+                let instruction_data = to_vec(&ProgramInstruction::VerifyThreshold { packages, threshold })?;
+                let instruction = Instruction::new_with_bytes(
+                    program_id,
+                    instruction_data.as_slice(),
+                    threshold_accounts,
+                );
+                send_instruction(client, cli, &payer, instruction).await;
+            }
+            None => send_batch(client, cli, &payer, &accounts, packages).await?,
+        }
+    }
+    Ok(())
+}
+
+/// Sends `packages` in as many `VerifySigBatch` transactions as needed, one after the other. A
+/// chunk holds as many packages as `--units-per-signature` allows within `--compute-units`, fewer
+/// if the transaction would exceed the packet size. Logs how many of the transactions succeeded.
+async fn send_batch(
+    client: &impl SolanaRpc,
+    cli: &Cli,
+    payer: &Keypair,
+    accounts: &[AccountMeta],
+    packages: Vec<SignaturePackage>,
+) -> io::Result<()> {
+    let batch_instruction = |instruction: &ProgramInstruction| {
+        Ok::<_, io::Error>(Instruction::new_with_bytes(cli.program_id, &to_vec(instruction)?, accounts.to_vec()))
+    };
+
+    // Shrink the chunk until a full one fits in a transaction, the packages all have the same size
+    let mut chunk_size = batch_chunk_size(cli.compute_units, cli.units_per_signature);
+    while chunk_size > 1 {
+        let first = ProgramInstruction::VerifySigBatch(packages.iter().take(chunk_size).cloned().collect());
+        if fits_in_transaction(cli, &payer.pubkey(), batch_instruction(&first)?) {
+            break;
+        }
+        chunk_size -= 1;
+    }
+
+    let instructions = split_batch(packages, chunk_size);
+    if instructions.len() > 1 {
+        info!("Splitting the batch into {} transactions of up to {} packages", instructions.len(), chunk_size);
+    }
+    let mut succeeded = 0;
+    for instruction in &instructions {
+        let submission = send_instruction(client, cli, payer, batch_instruction(instruction)?).await;
+        if submission.status == Status::Success {
+            succeeded += 1;
+        }
+    }
+    if succeeded == instructions.len() {
+        info!("All {} batch transactions succeeded", instructions.len());
+    } else {
+        error!("{} of {} batch transactions failed", instructions.len() - succeeded, instructions.len());
+    }
+    Ok(())
+}
+
+/// Whether the transaction sending `instruction`, with its compute budget instructions, fits in a
+/// packet once signed by `payer`.
+fn fits_in_transaction(cli: &Cli, payer: &Pubkey, instruction: Instruction) -> bool {
+    let transaction = Transaction::new_with_payer(&with_compute_budget(cli, instruction), Some(payer));
+    bincode::serialized_size(&transaction).is_ok_and(|size| size <= PACKET_DATA_SIZE as u64)
+}
+
+/// Number of transactions this run sends after creating the accounts, at most: one per `--count`
+/// verification, or the main verification followed by the extra ones the flags ask for. Each
+/// `--secrets` package is counted as its own transaction, although a batch usually holds several.
+fn planned_transactions(cli: &Cli) -> u64 {
+    if let Some(count) = cli.count {
+        return count as u64;
+    }
+    if cli.json_output {
+        return 1;
+    }
+    let secrets = match (cli.secrets.len(), cli.threshold) {
+        (0, _) => 0,
+        (_, Some(_)) => 1,
+        (len, None) => len,
+    };
+    let extras = [cli.without_recovery_id, cli.typed_data, cli.slot_bound.is_some(), cli.record_epoch.is_some(), cli.same_signer];
+    2 + extras.into_iter().filter(|&extra| extra).count() as u64 + secrets as u64
+}
+
+/// Fails unless `payer` holds enough lamports for the run: the base fee and priority fee of every
+/// transaction, and the rent of the state PDA when `InitializeState` has to create it.
+async fn prefund_check(client: &impl SolanaRpc, cli: &Cli, payer: &Pubkey, state_missing: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let transactions = planned_transactions(cli) + state_missing as u64;
+    // The priority fee is in micro-lamports per requested compute unit, rounded up per transaction
+    let priority_fee = (cli.compute_units as u64 * cli.priority_fee).div_ceil(1_000_000);
+    let rent = if state_missing {
+        client.get_minimum_balance_for_rent_exemption(ProgramState::LEN).await?
+    } else {
+        0
+    };
+    let need = transactions * (LAMPORTS_PER_SIGNATURE + priority_fee) + rent;
+    let have = client.get_balance(payer).await?;
+    if have <= need {
+        return Err(format!("insufficient funds: have {} lamports, need more than {} for {} transactions", have, need, transactions).into());
+    }
+    info!("Payer balance {} lamports covers the estimated {} lamports", have, need);
+    Ok(())
+}
+
+/// The instruction verifying the main package, `VerifyPrehashed` with --prehashed and
+/// `VerifySig` otherwise.
+fn verify_instruction(cli: &Cli, package: SignaturePackage) -> ProgramInstruction {
+    if cli.prehashed {
+        ProgramInstruction::VerifyPrehashed(package)
+    } else {
+        ProgramInstruction::VerifySig(package)
+    }
+}
 
+/// Simulates and sends `instruction`, logging the result, and returns the outcome for
+/// `--json-output`. A send failing for a transient reason (see `is_retryable`) is signed again with
+/// a new blockhash and retried, up to `SEND_MAX_ATTEMPTS` sends in all. A send rejected as already
+/// processed landed earlier and counts as a success. A send that times out waiting for confirmation
+/// isn't retried, the transaction may still land.
+async fn send_instruction(client: &impl SolanaRpc, cli: &Cli, payer: &Keypair, instruction: Instruction) -> Submission {
     // Create the transaction
-    let recent_blockhash = client.get_latest_blockhash().await.unwrap();
-    let transaction = Transaction::new_signed_with_payer(
-        &[instruction],
+    let recent_blockhash = match fetch_blockhash(client).await {
+        Ok(blockhash) => blockhash,
+        Err(err) => {
+            error!("Unable to fetch a blockhash: {}", err);
+            return Submission::unsigned();
+        }
+    };
+    let mut transaction = Transaction::new_signed_with_payer(
+        &with_compute_budget(cli, instruction),
         Some(&payer.pubkey()),
-        &[&payer],
+        &[payer],
         recent_blockhash,
     );
 
-    // Send and confirm transaction
-    match client.send_and_confirm_transaction(&transaction).await {
-        Ok(signature) => {
-            println!("Transaction succeeded: {:?}", &signature);
+    // Simulate first, a rejected signature shows up here without paying any fees
+    let signature = transaction.signatures[0];
+    if let Err(err) = simulate(client, &transaction).await {
+        if !cli.force {
+            error!("Simulation failed, not sending the transaction. Pass --force to send it anyway.");
+            return Submission::failed(&signature, err.as_ref());
+        }
+    }
+
+    // Send and confirm transaction, a stalled cluster would otherwise keep us waiting until the
+    // blockhash expires
+    let timeout = Duration::from_secs(cli.timeout);
+    let mut attempt = 1;
+    let result = loop {
+        let result = tokio::time::timeout(timeout, client.send_and_confirm_transaction(&transaction)).await;
+        match result {
+            // A transient failure, sign again with a new blockhash so the retry can't be dropped as
+            // a duplicate or for an expired blockhash
+            Ok(Err(err)) if attempt < SEND_MAX_ATTEMPTS && is_retryable(&err) => {
+                warn!("Sending {} failed, retrying ({}/{}): {}", transaction.signatures[0], attempt, SEND_MAX_ATTEMPTS, err);
+                let recent_blockhash = match client.get_latest_blockhash().await {
+                    Ok(blockhash) => blockhash,
+                    Err(_) => break Ok(Err(err)),
+                };
+                transaction.sign(&[payer], recent_blockhash);
+                attempt += 1;
+            }
+            // The transaction landed, only its confirmation was lost
+            Ok(Err(err)) if is_already_processed(&err) => break Ok(Ok(transaction.signatures[0])),
+            result => break result,
+        }
+    };
+    let signature = transaction.signatures[0];
+    match result {
+        Ok(Ok(signature)) => {
+            info!("Transaction succeeded: {:?}", &signature);
+            // The slot is only reported, so failing to fetch it doesn't fail the submission
+            let slot = match client.get_signature_slot(&signature).await {
+                Ok(slot) => slot,
+                Err(err) => {
+                    warn!("Unable to fetch the slot of {}: {}", signature, err);
+                    None
+                }
+            };
+            Submission::success(&signature, slot)
+        }
+        Ok(Err(err)) => {
+            error!("Error sending transaction: {}", err);
+            print_client_error(&err);
+            Submission::failed(&signature, err.get_transaction_error().as_ref())
+        }
+        Err(_) => {
+            error!(
+                "Timed out after {} seconds waiting for {} to be confirmed",
+                cli.timeout, signature
+            );
+            Submission::failed(&signature, None)
+        }
+    }
+}
+
+/// The `--offline` transaction carrying `instruction`, signed by `payer` with `blockhash`.
+fn offline_transaction(cli: &Cli, payer: &Keypair, instruction: Instruction, blockhash: Hash) -> Transaction {
+    Transaction::new_signed_with_payer(&with_compute_budget(cli, instruction), Some(&payer.pubkey()), &[payer], blockhash)
+}
+
+/// A signed transaction in its wire format, base64-encoded, as `--offline` prints it.
+fn encode_transaction(transaction: &Transaction) -> bincode::Result<String> {
+    Ok(BASE64_STANDARD.encode(bincode::serialize(transaction)?))
+}
+
+/// Decodes a transaction printed by `--offline`.
+fn decode_transaction(encoded: &str) -> Result<Transaction, Box<dyn std::error::Error>> {
+    let bytes = BASE64_STANDARD.decode(encoded.trim())?;
+    Ok(bincode::deserialize(&bytes)?)
+}
+
+/// Runs the `broadcast` subcommand, sending a transaction signed with `--offline` and waiting for
+/// its confirmation. It was simulated nowhere, so it is sent as is. Returns whether it succeeded.
+async fn broadcast(client: &impl SolanaRpc, cli: &Cli, encoded: &str) -> bool {
+    let transaction = match decode_transaction(encoded) {
+        Ok(transaction) => transaction,
+        Err(err) => {
+            error!("Unable to decode the transaction, pass the base64 --offline printed: {}", err);
+            return false;
+        }
+    };
+    if !transaction.is_signed() {
+        error!("Transaction {} is not fully signed", transaction.signatures[0]);
+        return false;
+    }
+
+    let timeout = Duration::from_secs(cli.timeout);
+    match tokio::time::timeout(timeout, client.send_and_confirm_transaction(&transaction)).await {
+        Ok(Ok(signature)) => {
+            info!("Transaction succeeded: {:?}", &signature);
+            true
+        }
+        Ok(Err(err)) => {
+            error!("Error sending transaction: {}", err);
+            print_client_error(&err);
+            false
         }
+        Err(_) => {
+            error!(
+                "Timed out after {} seconds waiting for {} to be confirmed",
+                cli.timeout, transaction.signatures[0]
+            );
+            false
+        }
+    }
+}
+
+/// Signs and sends `count` `VerifySig` transactions, each over distinct random data so none are
+/// dropped as duplicates, with up to `--concurrency` in flight. Transactions aren't simulated
+/// first. The blockhash is refreshed every few seconds, and a transaction failing for a transient
+/// reason is signed again with a new one and retried, up to `SEND_MAX_ATTEMPTS` sends in all.
+/// Logs the total time, the confirmed transactions per second and the number of failures.
+///
+/// An `interrupt`, `tokio::signal::ctrl_c` from the command line, stops new submissions and waits
+/// up to `SHUTDOWN_GRACE_PERIOD` for the ones in flight, or until a second interrupt, then logs
+/// the same summary for the transactions submitted so far. Returns the summary.
+#[allow(clippy::too_many_arguments)]
+async fn submit_many<F: Future<Output = io::Result<()>>>(
+    client: &impl SolanaRpc,
+    cli: &Cli,
+    payer: &Keypair,
+    secret: &[u8; 32],
+    chain_id: u64,
+    accounts: &[AccountMeta],
+    count: usize,
+    interrupt: impl Fn() -> F,
+) -> CountSummary {
+    let blockhashes = match BlockhashCache::new(client).await {
+        Ok(blockhashes) => blockhashes,
         Err(err) => {
-            println!("Error sending transaction: {}", err);
+            error!("Unable to fetch a blockhash: {}", err);
+            return CountSummary::default();
+        }
+    };
+    let expiry_unix = unix_now() + SIGNATURE_TTL_SECS;
+
+    info!("Submitting {} verifications, {} at a time", count, cli.concurrency);
+    let start = Instant::now();
+    let interrupted = AtomicBool::new(false);
+    let submitted = AtomicUsize::new(0);
+    let mut results = pin!(stream::iter(0..count)
+        // Checked as each submission is about to start, so none start after an interrupt
+        .take_while(|_| future::ready(!interrupted.load(Ordering::Relaxed)))
+        .map(|_| {
+            submitted.fetch_add(1, Ordering::Relaxed);
+            async {
+                let package = create_and_sign_package(
+                    &cli.program_id,
+                    &payer.pubkey(),
+                    chain_id,
+                    Pubkey::new_unique().to_bytes(),
+                    expiry_unix,
+                    cli.tag.unwrap_or_default(),
+                    cli.hash.into(),
+                    secret,
+                )?;
+                let instruction_data = to_vec(&ProgramInstruction::VerifySig(package))?;
+                let instruction = Instruction::new_with_bytes(cli.program_id, instruction_data.as_slice(), accounts.to_vec());
+                let instructions = with_compute_budget(cli, instruction);
+
+                let mut recent_blockhash = blockhashes.get(client).await?;
+                let mut attempt = 1;
+                loop {
+                    let transaction = Transaction::new_signed_with_payer(&instructions, Some(&payer.pubkey()), &[payer], recent_blockhash);
+                    match client.send_and_confirm_transaction(&transaction).await {
+                        Err(err) if attempt < SEND_MAX_ATTEMPTS && is_retryable(&err) => {
+                            debug!("Retrying {} with a new blockhash: {}", transaction.signatures[0], err);
+                            recent_blockhash = blockhashes.refresh(client, recent_blockhash).await?;
+                            attempt += 1;
+                        }
+                        Err(err) if is_already_processed(&err) => break Ok(transaction.signatures[0]),
+                        result => break Ok::<_, SignerError>(result?),
+                    }
+                }
+            }
+        })
+        .buffer_unordered(cli.concurrency.get()));
+
+    let mut ctrl_c = pin!(interrupt());
+    let mut grace_period = pin!(tokio::time::sleep(SHUTDOWN_GRACE_PERIOD));
+    let (mut confirmed, mut failures) = (0, 0);
+    let mut listening = true;
+    loop {
+        let interrupted_now = interrupted.load(Ordering::Relaxed);
+        tokio::select! {
+            result = results.next() => match result {
+                Some(Ok(signature)) => {
+                    debug!("Verification confirmed: {}", signature);
+                    confirmed += 1;
+                }
+                Some(Err(err)) => {
+                    warn!("Verification failed: {}", err);
+                    failures += 1;
+                }
+                None => break,
+            },
+            signal = &mut ctrl_c, if listening => {
+                if let Err(err) = signal {
+                    warn!("Unable to listen for Ctrl-C, it will abort without a summary: {}", err);
+                    listening = false;
+                    continue;
+                }
+                if interrupted_now {
+                    warn!("Interrupted again, not waiting for the remaining transactions");
+                    break;
+                }
+                interrupted.store(true, Ordering::Relaxed);
+                let in_flight = submitted.load(Ordering::Relaxed) - confirmed - failures;
+                warn!("Interrupted, waiting up to {:?} for {} in-flight transactions", SHUTDOWN_GRACE_PERIOD, in_flight);
+                grace_period.as_mut().reset(tokio::time::Instant::now() + SHUTDOWN_GRACE_PERIOD);
+                ctrl_c.set(interrupt());
+            }
+            _ = &mut grace_period, if interrupted_now => {
+                warn!("Grace period over, not waiting for the remaining transactions");
+                break;
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let submitted = submitted.load(Ordering::Relaxed);
+    let unsettled = submitted - confirmed - failures;
+    info!(
+        "Sent {} of {} verifications in {:.2?}: {} confirmed, {} failed, {} unsettled, {:.1} TPS",
+        submitted,
+        count,
+        elapsed,
+        confirmed,
+        failures,
+        unsettled,
+        confirmed as f64 / elapsed.as_secs_f64()
+    );
+    CountSummary { submitted, confirmed, failed: failures }
+}
+
+/// How the transactions `submit_many` started ended up. The rest of them are still unsettled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct CountSummary {
+    submitted: usize,
+    confirmed: usize,
+    failed: usize,
+}
+
+/// Prints the transaction that would be sent for `instruction`: each instruction's program id,
+/// accounts and hex data, followed by the decoded package as JSON. The transaction isn't signed.
+fn print_dry_run(cli: &Cli, payer: &Pubkey, package: &SignaturePackage, instruction: Instruction) -> serde_json::Result<()> {
+    let instructions = with_compute_budget(cli, instruction);
+    let transaction = Transaction::new_with_payer(&instructions, Some(payer));
+
+    println!("Dry run, nothing is sent");
+    println!("Fee payer: {}", payer);
+    println!("Message size: {} bytes", transaction.message_data().len());
+    for (index, instruction) in instructions.iter().enumerate() {
+        println!("Instruction {}: program {}", index, instruction.program_id);
+        for account in &instruction.accounts {
+            println!(
+                "  {} signer: {} writable: {}",
+                account.pubkey, account.is_signer, account.is_writable
+            );
+        }
+        println!("  data: {}", hex::encode(&instruction.data));
+    }
+    println!("Package: {}", serde_json::to_string_pretty(package)?);
+    Ok(())
+}
+
+/// Prints the program logs and the decoded program error carried by a failed send. The logs are
+/// only available when the node's preflight simulation rejected the transaction.
+fn print_client_error(err: &ClientError) {
+    if let ClientErrorKind::RpcError(RpcError::RpcResponseError {
+        data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+        ..
+    }) = err.kind()
+    {
+        for log in result.logs.iter().flatten() {
+            info!("  {}", log);
         }
     }
 
+    if let Some(description) = err.get_transaction_error().as_ref().and_then(describe_verify_error) {
+        error!("{}", description);
+    }
 }
 
-fn create_and_sign_package(
-    message_data: [u8; 32],
-    signer_secret_key: &[u8; 32],
-) -> Result<SignaturePackage, Box<dyn std::error::Error>> {
+/// Prepends the compute budget instructions to `instruction`: the compute unit limit, then the
+/// compute unit price when a priority fee is set.
+fn with_compute_budget(cli: &Cli, instruction: Instruction) -> Vec<Instruction> {
+    let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(cli.compute_units)];
+    if cli.priority_fee > 0 {
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_price(cli.priority_fee));
+    }
+    instructions.push(instruction);
+    instructions
+}
 
-    let message_hash = {
-        let mut hasher = keccak::Hasher::default();
-        hasher.hash(&message_data);
-        hasher.result()
+/// Simulates the transaction and prints its logs and compute units. Fails with the transaction
+/// error if the simulation failed, or `None` if the simulation couldn't be run.
+async fn simulate(client: &impl SolanaRpc, transaction: &Transaction) -> Result<(), Option<TransactionError>> {
+    let result = match client.simulate_transaction(transaction).await {
+        Ok(response) => response.value,
+        Err(err) => {
+            error!("Error simulating transaction: {}", err);
+            return Err(None);
+        }
     };
 
-    let message = Message::parse_slice(&message_hash.0)?;
+    for log in result.logs.unwrap_or_default() {
+        info!("  {}", log);
+    }
+    if let Some(units_consumed) = result.units_consumed {
+        info!("Simulation consumed {} compute units", units_consumed);
+    }
 
-    // Create secret key from input bytes
-    let secret_key = SecretKey::parse(signer_secret_key)?;
-    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+    match result.err {
+        Some(err) => {
+            error!("Simulation failed: {}", err);
+            if let Some(description) = describe_verify_error(&err) {
+                error!("{}", description);
+            }
+            Err(Some(err))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Checks the package in `path` the way the program would on the cluster with `chain_id` and
+/// prints the recovered signer with the verdict. Returns whether the package is valid, failing if
+/// it can't be read.
+fn verify_package_file(program_id: &Pubkey, payer: &Pubkey, chain_id: u64, path: &str) -> Result<bool, RunError> {
+    let package = read_package(path).map_err(RunError::Package)?;
+    match verify_package_locally(program_id, payer, chain_id, &package, unix_now()) {
+        Ok(recovered) => {
+            println!("Recovered public key: {}", hex::encode(recovered.to_bytes()));
+            println!("Ethereum address: 0x{}", hex::encode(pubkey_to_eth_address(&recovered)));
+            println!("Valid");
+            Ok(true)
+        }
+        Err(err) => {
+            println!("Invalid: {:?}", err);
+            Ok(false)
+        }
+    }
+}
 
-    // Sign the message and get the signature and recovery ID
-    let (signature, recovery_id) = libsecp256k1::sign(&message, &secret_key);
+/// Runs `verify-file`: checks each package of the JSONL file in `path` the way the program would
+/// on the cluster with `chain_id` and prints a line of the report for it, then the totals. Returns
+/// whether every line held a valid package, failing if the file can't be read.
+fn verify_package_lines(program_id: &Pubkey, payer: &Pubkey, chain_id: u64, path: &str, strict: bool) -> Result<bool, RunError> {
+    let lines = read_package_lines(path, strict).map_err(RunError::Package)?;
+    let total = lines.len();
+    let (packages, malformed) = check_package_lines(program_id, payer, chain_id, lines, &mut io::stdout()).map_err(RunError::Report)?;
+    let valid = packages.len();
+    println!("{} valid, {} invalid, {} malformed", valid, total - valid - malformed, malformed);
+    Ok(valid == total)
+}
 
-    // Combine signature and recovery ID into 64 bytes
-    let mut signature_bytes = [0u8; 64];
-    signature_bytes[..64].copy_from_slice(&signature.serialize());
+/// Runs `verify-file --submit`: checks the packages of the JSONL file in `path` like
+/// `verify_package_lines`, then sends the valid ones with `send_batch`. Returns whether there was
+/// anything to send, failing if the file can't be read or the program isn't deployed.
+async fn submit_package_lines(
+    client: &impl SolanaRpc,
+    cli: &Cli,
+    payer: &Keypair,
+    chain_id: u64,
+    path: &str,
+    strict: bool,
+) -> Result<bool, RunError> {
+    let lines = read_package_lines(path, strict).map_err(RunError::Package)?;
+    let total = lines.len();
+    let (packages, _malformed) =
+        check_package_lines(&cli.program_id, &payer.pubkey(), chain_id, lines, &mut io::stdout()).map_err(RunError::Report)?;
+    if packages.is_empty() {
+        error!("None of the {} packages in {} passed the check, nothing to send", total, path);
+        return Ok(false);
+    }
+    info!("Submitting {} of the {} packages in {}", packages.len(), total, path);
 
-    Ok(SignaturePackage {
-        verifier_signature: signature_bytes,
-        recovery_id: recovery_id.serialize(),
-        public_key,
-        data: message_data,
-    })
+    check_deploy(client, &cli.program_id).await?;
+    // The batch only verifies, the accounts are created by a run without a subcommand
+    let (state_pda, _bump) = Pubkey::find_program_address(&[STATE_SEED, payer.pubkey().as_ref()], &cli.program_id);
+    if client.get_account(&state_pda).await.is_err() {
+        error!("State account {} for {} is not initialized yet, run once without a subcommand to create it", state_pda, payer.pubkey());
+        return Ok(false);
+    }
+    let (config_pda, _bump) = Pubkey::find_program_address(&[CONFIG_SEED], &cli.program_id);
+    let (audit_pda, _bump) = Pubkey::find_program_address(&[AUDIT_SEED], &cli.program_id);
+    let accounts = [
+        AccountMeta::new(payer.pubkey(), true),
+        AccountMeta::new(state_pda, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new(config_pda, false),
+        AccountMeta::new(audit_pda, false),
+    ];
+    send_batch(client, cli, payer, &accounts, packages).await?;
+    Ok(true)
 }
 
-async fn request_airdrop(
-    client: &RpcClient,
-    pubkey: &Pubkey,
-    amount: u64,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let signature = client.request_airdrop(pubkey, amount).await?;
+/// Checks each line's package the way the program would and prints, in line order, whether it
+/// passed, with the signer's Ethereum address or the error. A malformed line is reported as
+/// skipped. The report is written to `report`, stdout outside of tests. Returns the packages that
+/// passed and the number of malformed lines, failing if the report can't be written.
+fn check_package_lines(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    lines: Vec<(usize, Result<SignaturePackage, serde_json::Error>)>,
+    report: &mut impl Write,
+) -> io::Result<(Vec<SignaturePackage>, usize)> {
+    let now = unix_now();
+    let mut valid = Vec::new();
+    let mut malformed = 0;
+    for (line, package) in lines {
+        let package = match package {
+            Ok(package) => package,
+            Err(err) => {
+                writeln!(report, "line {}: malformed, skipped: {}", line, err)?;
+                malformed += 1;
+                continue;
+            }
+        };
+        match verify_package_locally(program_id, payer, chain_id, &package, now) {
+            Ok(recovered) => {
+                let eth_address = pubkey_to_eth_address(&recovered);
+                writeln!(report, "line {}: valid, signed by 0x{}", line, hex::encode(eth_address))?;
+                valid.push(package);
+            }
+            Err(err) => writeln!(report, "line {}: invalid: {:?}", line, err)?,
+        }
+    }
+    Ok((valid, malformed))
+}
 
-    // Wait for the transaction to be confirmed
-    loop {
-        let confirmation = client.confirm_transaction(&signature).await.unwrap();
-        if confirmation {
-            break;
+/// Reads a JSONL file of packages, returning each line's parsed package with its line number,
+/// counted from 1. Blank lines are left out. With `strict`, the first malformed line fails the
+/// read instead of being returned.
+#[allow(clippy::type_complexity)]
+fn read_package_lines(path: &str, strict: bool) -> Result<Vec<(usize, Result<SignaturePackage, serde_json::Error>)>, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("unable to read package file {}: {}", path, err))?;
+    let mut lines = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Err(err) if strict => return Err(format!("line {}: malformed package: {}", index + 1, err).into()),
+            package => lines.push((index + 1, package)),
+        }
+    }
+    Ok(lines)
+}
+
+/// Fetches `payer`'s state PDA and prints its decoded `ProgramState`. Returns whether the state
+/// could be read.
+async fn query_state(client: &impl SolanaRpc, program_id: &Pubkey, payer: &Pubkey) -> bool {
+    let (state_pda, _bump) = Pubkey::find_program_address(&[STATE_SEED, payer.as_ref()], program_id);
+    let account = match client.get_account(&state_pda).await {
+        Ok(account) if account.owner == *program_id => account,
+        Ok(_) => {
+            error!("State account {} for {} is not initialized yet", state_pda, payer);
+            return false;
+        }
+        Err(err) => {
+            debug!("Unable to fetch {}: {}", state_pda, err);
+            error!("State account {} for {} is not initialized yet, or the cluster can't be reached", state_pda, payer);
+            return false;
+        }
+    };
+
+    let state = match ProgramState::unpack(&account.data) {
+        Ok(state) => state,
+        Err(err) => {
+            error!("Unable to decode state account {}: {}", state_pda, err);
+            return false;
+        }
+    };
+    println!("State account: {}", state_pda);
+    println!("Verification count: {}", state.verification_count);
+    println!("Last data: {}", hex::encode(state.last_data));
+    println!("Last tag: {}", hex::encode(state.last_tag));
+    println!("Admin: {}", state.admin);
+    true
+}
+
+/// Reads the data to sign from `path`, or stdin when `path` is `-`. The contents are hashed with
+/// Keccak-256 to 32 bytes, unless `raw` is set, in which case they must be exactly 32 bytes and
+/// are used as is.
+fn load_data(path: &str, raw: bool) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    let contents = if path == "-" {
+        let mut contents = Vec::new();
+        io::stdin().read_to_end(&mut contents).map_err(|err| format!("unable to read stdin: {}", err))?;
+        contents
+    } else {
+        fs::read(path).map_err(|err| format!("unable to read data file {}: {}", path, err))?
+    };
+
+    if raw {
+        let len = contents.len();
+        return contents
+            .try_into()
+            .map_err(|_| format!("--raw data must be exactly 32 bytes, got {}", len).into());
+    }
+
+    Ok(keccak::hash(&contents).to_bytes())
+}
+
+/// Writes the package to `path` as pretty-printed JSON.
+fn write_package(path: &str, package: &SignaturePackage) -> Result<(), Box<dyn std::error::Error>> {
+    let json = serde_json::to_string_pretty(package)?;
+    fs::write(path, json).map_err(|err| format!("unable to write package {}: {}", path, err).into())
+}
+
+/// Reads a package written by `write_package`.
+fn read_package(path: &str) -> Result<SignaturePackage, Box<dyn std::error::Error>> {
+    let json = fs::read_to_string(path).map_err(|err| format!("unable to read package {}: {}", path, err))?;
+    serde_json::from_str(&json).map_err(|err| format!("invalid package {}: {}", path, err).into())
+}
+
+/// Runs the `keygen` subcommand: creates a secp256k1 secret key from `seed`, or a random one,
+/// writes it to `path` as hex, readable by `--secret`, and prints its public key and Ethereum
+/// address, which it returns.
+fn keygen(path: &str, seed: Option<&[u8; 32]>) -> Result<([u8; 65], [u8; 20]), Box<dyn std::error::Error>> {
+    let secret_key = match seed {
+        Some(seed) => secret_key_from_seed(seed)?,
+        None => SecretKey::random(&mut thread_rng()),
+    };
+    let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+
+    // A key that replaced another one by accident would lose that identity for good
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|err| format!("unable to create key file {}: {}", path, err))?;
+    writeln!(file, "{}", hex::encode(secret_key.serialize())).map_err(|err| format!("unable to write key file {}: {}", path, err))?;
+    info!("Wrote secp256k1 secret key to {}", path);
+
+    let eth_address = pubkey_to_eth_address(&parse_pubkey(&public_key).map_err(|err| format!("invalid public key: {:?}", err))?);
+    println!("Public key: {}", hex::encode(public_key));
+    println!("Ethereum address: 0x{}", hex::encode(eth_address));
+    Ok((public_key, eth_address))
+}
+
+/// Runs the `recover-address` subcommand: recovers the key that signed `message`, hashed with
+/// Keccak-256 unless `prehashed`, and prints it with its Ethereum address, which it returns.
+fn recover_address(
+    signature: &[u8; 64],
+    recovery_id: u8,
+    message: &[u8],
+    prehashed: bool,
+) -> Result<([u8; 65], [u8; 20]), Box<dyn std::error::Error>> {
+    let digest: [u8; 32] = if prehashed {
+        message
+            .try_into()
+            .map_err(|_| format!("--prehashed needs a 32-byte digest, got {} bytes", message.len()))?
+    } else {
+        keccak::hash(message).0
+    };
+    let (public_key, eth_address) = recover_signer_key(&digest, signature, recovery_id)?;
+    println!("Digest: {}", hex::encode(digest));
+    println!("Public key: {}", hex::encode(public_key));
+    println!("Ethereum address: 0x{}", hex::encode(eth_address));
+    Ok((public_key, eth_address))
+}
+
+/// Loads a Solana keypair file in the standard JSON byte array format, defaulting to the Solana
+/// CLI's keypair at `~/.config/solana/id.json`.
+fn load_payer(path: Option<&str>) -> Result<Keypair, Box<dyn std::error::Error>> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let home = env::var("HOME").map_err(|_| "HOME is not set, pass --keypair")?;
+            Path::new(&home).join(DEFAULT_KEYPAIR_PATH)
         }
+    };
+
+    read_keypair_file(&path).map_err(|err| format!("unable to read keypair {}: {}", path.display(), err).into())
+}
+
+/// Checks that `program_id` is an executable account owned by one of the BPF loaders, catching a
+/// `PROGRAM_ID` or `--program-id` that doesn't match the deployed program before anything is sent.
+async fn check_deploy(client: &impl SolanaRpc, program_id: &Pubkey) -> Result<(), DeployError> {
+    let account = client
+        .get_account(program_id)
+        .await
+        .map_err(|err| DeployError::Unavailable(*program_id, Box::new(err)))?;
+    if !account.executable {
+        return Err(DeployError::NotExecutable(*program_id));
     }
+    if ![bpf_loader_upgradeable::ID, bpf_loader::ID, bpf_loader_deprecated::ID].contains(&account.owner) {
+        return Err(DeployError::NotBpfProgram(*program_id, account.owner));
+    }
+
     Ok(())
 }
+
+/// Whether the account at `pubkey` exists. Only the node reporting it as not found means it
+/// doesn't, any other failure, such as a timeout, is returned as an error.
+async fn account_exists(client: &impl SolanaRpc, pubkey: &Pubkey) -> Result<bool, ClientError> {
+    match client.get_account(pubkey).await {
+        Ok(_) => Ok(true),
+        Err(err) if is_account_not_found(&err) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Whether `err` is the node reporting an account as not found, the error `get_account` returns
+/// for an account that doesn't exist.
+fn is_account_not_found(err: &ClientError) -> bool {
+    matches!(err.kind(), ClientErrorKind::RpcError(RpcError::ForUser(message)) if message.starts_with("AccountNotFound"))
+}
+
+/// The `InitializeConfig` instruction for `init-config`, making `admin` the admin and binding the
+/// program to `chain_id`. The program checks `admin` against the upgrade authority stored in its
+/// ProgramData account.
+fn initialize_config_instruction(program_id: &Pubkey, admin: &Pubkey, chain_id: u64) -> io::Result<Instruction> {
+    let (config_pda, _bump) = Pubkey::find_program_address(&[CONFIG_SEED], program_id);
+    let (registry_pda, _bump) = Pubkey::find_program_address(&[REGISTRY_SEED], program_id);
+    let (audit_pda, _bump) = Pubkey::find_program_address(&[AUDIT_SEED], program_id);
+    let instruction_data = to_vec(&ProgramInstruction::InitializeConfig { chain_id })?;
+    Ok(Instruction::new_with_bytes(
+        *program_id,
+        instruction_data.as_slice(),
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(config_pda, false),
+            AccountMeta::new(registry_pda, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new(audit_pda, false),
+            AccountMeta::new_readonly(bpf_loader_upgradeable::get_program_data_address(program_id), false),
+        ],
+    ))
+}
+
+/// Generates `count` keypairs, writes them to `dir` as `<pubkey>.json` and funds them with
+/// concurrent airdrops. A failed airdrop is reported and doesn't stop the others. Returns whether
+/// each keypair was funded, in the order they were generated, failing if a keypair can't be written.
+async fn fund_keypairs(client: &impl SolanaRpc, cli: &Cli, count: usize, dir: &Path) -> Result<Vec<(Pubkey, bool)>, RunError> {
+    fs::create_dir_all(dir).map_err(|err| RunError::WriteKeypairs(format!("unable to create {}: {}", dir.display(), err).into()))?;
+    let keypairs: Vec<Keypair> = (0..count).map(|_| Keypair::new()).collect();
+    // Write the keypairs first so none are lost if funding fails
+    for keypair in &keypairs {
+        let path = dir.join(format!("{}.json", keypair.pubkey()));
+        write_keypair_file(keypair, &path).map_err(|err| RunError::WriteKeypairs(format!("{}: {}", path.display(), err).into()))?;
+    }
+
+    let pubkeys: Vec<Pubkey> = keypairs.iter().map(Keypair::pubkey).collect();
+    let airdrops = pubkeys.iter().map(|pubkey| request_airdrop(client, cli, pubkey, AIRDROP_LAMPORTS));
+    let results = futures::future::join_all(airdrops).await;
+
+    let mut funded = Vec::with_capacity(count);
+    for (pubkey, result) in pubkeys.into_iter().zip(results) {
+        match &result {
+            Ok(()) => info!("Funded {}", pubkey),
+            Err(err) => error!("Failed to fund {}: {}", pubkey, err),
+        }
+        funded.push((pubkey, result.is_ok()));
+    }
+    info!("Funded {} of {} keypairs in {}", funded.iter().filter(|(_, ok)| *ok).count(), count, dir.display());
+    Ok(funded)
+}
+
+/// Waits up to `timeout` for a `signatureSubscribe` notification that `signature` reached
+/// `commitment`, returning whether it did so without an error. Fails if the WebSocket endpoint
+/// can't be reached or the subscription is refused.
+async fn confirm_with_subscription(
+    ws_url: &str,
+    signature: &Signature,
+    commitment: CommitmentConfig,
+    timeout: Duration,
+) -> Result<bool, PubsubClientError> {
+    let pubsub = PubsubClient::new(ws_url).await?;
+    let config = RpcSignatureSubscribeConfig {
+        commitment: Some(commitment),
+        enable_received_notification: Some(false),
+    };
+    let (mut notifications, unsubscribe) = pubsub.signature_subscribe(signature, Some(config)).await?;
+    let notification = tokio::time::timeout(timeout, notifications.next()).await;
+    unsubscribe().await;
+
+    Ok(matches!(
+        notification,
+        Ok(Some(Response { value: RpcSignatureResult::ProcessedSignature(ProcessedSignatureResult { err: None }), .. }))
+    ))
+}
+
+/// Requests an airdrop and waits for it to be confirmed. With --ws the confirmation is pushed
+/// through a WebSocket subscription, otherwise, or if the WebSocket endpoint can't be reached,
+/// it is polled with exponential backoff so a flaky RPC node isn't hammered. RPC errors while
+/// polling are retried like unconfirmed polls.
+async fn request_airdrop(
+    client: &impl SolanaRpc,
+    cli: &Cli,
+    pubkey: &Pubkey,
+    amount: u64,
+) -> Result<(), SignerError> {
+    let signature = client.request_airdrop(pubkey, amount).await?;
+
+    if cli.ws {
+        let ws_url = cli.ws_url();
+        let timeout = Duration::from_secs(cli.timeout);
+        match confirm_with_subscription(&ws_url, &signature, cli.commitment.into(), timeout).await {
+            Ok(true) => return Ok(()),
+            Ok(false) => return Err(SignerError::AirdropTimeout(signature, 1)),
+            Err(err) => warn!("Unable to subscribe at {}, polling instead: {}", ws_url, err),
+        }
+    }
+
+    // Wait for the transaction to be confirmed
+    let mut backoff = AIRDROP_INITIAL_BACKOFF;
+    for attempt in 1..=AIRDROP_MAX_ATTEMPTS {
+        match client.confirm_transaction(&signature).await {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(err) => warn!("Error confirming airdrop (attempt {}): {}", attempt, err),
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(AIRDROP_MAX_BACKOFF);
+    }
+
+    Err(SignerError::AirdropTimeout(signature, AIRDROP_MAX_ATTEMPTS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshDeserialize;
+    use crate::rpc::{MockRpc, OfflineRpc};
+    use shared_types::HASH_ALGO_KECCAK256;
+    use solana_sdk::account::Account;
+    use std::collections::{HashSet, VecDeque};
+
+    const SECRET: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+    const CHAIN_ID: u64 = 0x4444_4444_4444_4444;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(["secp256k1-signer-example"].iter().chain(args)).unwrap()
+    }
+
+    /// A cluster with the program deployed and its config initialized, and nothing else.
+    fn cluster(program_id: &Pubkey) -> MockRpc {
+        let mut client = MockRpc::default();
+        let program = Account {
+            executable: true,
+            owner: bpf_loader_upgradeable::ID,
+            ..Account::default()
+        };
+        client.accounts.insert(*program_id, program);
+        let config = Account {
+            owner: *program_id,
+            ..Account::default()
+        };
+        client.accounts.insert(config_pda(program_id), config);
+        client
+    }
+
+    fn config_pda(program_id: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[CONFIG_SEED], program_id).0
+    }
+
+    /// The program instruction a sent transaction carries, after the compute budget.
+    fn sent_instruction(transaction: &Transaction) -> ProgramInstruction {
+        let instruction = transaction.message.instructions.last().unwrap();
+        ProgramInstruction::try_from_slice(&instruction.data).unwrap()
+    }
+
+    #[tokio::test]
+    async fn run_sends_the_whole_flow() {
+        let cli = cli(&["--generate", "--secret", SECRET, "--chain-id", &CHAIN_ID.to_string()]);
+        let client = cluster(&cli.program_id);
+        run(&client, &cli).await.unwrap();
+
+        let sent = client.sent();
+        let instructions: Vec<_> = sent.iter().map(sent_instruction).collect();
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(instructions[0], ProgramInstruction::InitializeState);
+        assert!(matches!(instructions[2], ProgramInstruction::VerifySigVar(_)));
+
+        // The package is signed for the payer of the transaction carrying it
+        let ProgramInstruction::VerifySig(package) = &instructions[1] else {
+            panic!("expected a VerifySig, got {:?}", instructions[1]);
+        };
+        let payer = sent[1].message.account_keys[0];
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        assert!(verify_package_locally(&cli.program_id, &payer, CHAIN_ID, package, now).is_ok());
+        let state_account = sent[1].message.instructions.last().unwrap().accounts[1];
+        let state_pda = Pubkey::find_program_address(&[STATE_SEED, payer.as_ref()], &cli.program_id).0;
+        assert_eq!(sent[1].message.account_keys[state_account as usize], state_pda);
+    }
+
+    #[tokio::test]
+    async fn run_sends_nothing_without_a_config() {
+        let cli = cli(&["--generate", "--secret", SECRET, "--chain-id", &CHAIN_ID.to_string()]);
+        let mut client = cluster(&cli.program_id);
+        client.accounts.remove(&config_pda(&cli.program_id));
+
+        let result = run(&client, &cli).await;
+
+        assert!(matches!(result, Err(RunError::ConfigMissing(config)) if config == config_pda(&cli.program_id)));
+        assert!(client.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn run_fails_on_an_unreadable_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.json");
+        let cli = cli(&["--generate", "--chain-id", &CHAIN_ID.to_string(), "--package", missing.to_str().unwrap()]);
+        let client = cluster(&cli.program_id);
+
+        let result = run(&client, &cli).await;
+
+        assert!(matches!(result, Err(RunError::Package(_))));
+        assert!(client.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn run_cli_fails_on_verify_without_a_chain_id_or_payer_keypair() {
+        let dir = tempfile::tempdir().unwrap();
+        let package = dir.path().join("package.json").to_str().unwrap().to_string();
+
+        let result = run_cli(&cli(&["verify", "--package", &package])).await;
+        assert!(matches!(result, Err(RunError::MissingChainId(_))));
+
+        let keypair = dir.path().join("missing-keypair.json").to_str().unwrap().to_string();
+        let result = run_cli(&cli(&["--chain-id", "1", "--keypair", &keypair, "verify", "--package", &package])).await;
+        assert!(matches!(result, Err(RunError::Payer(_))));
+    }
+
+    #[tokio::test]
+    async fn run_does_not_take_a_failed_lookup_for_a_missing_config() {
+        let cli = cli(&["--generate", "--secret", SECRET, "--chain-id", &CHAIN_ID.to_string()]);
+        let mut client = cluster(&cli.program_id);
+        client.unreachable.insert(config_pda(&cli.program_id));
+
+        let result = run(&client, &cli).await;
+
+        assert!(matches!(result, Err(RunError::Fetch(..))));
+
+        assert!(client.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn init_config_sends_initialize_config_with_the_program_data_account() {
+        let cli = cli(&["--generate", "--chain-id", &CHAIN_ID.to_string(), "init-config"]);
+        let mut client = cluster(&cli.program_id);
+        client.accounts.remove(&config_pda(&cli.program_id));
+
+        run(&client, &cli).await.unwrap();
+
+        let sent = client.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent_instruction(&sent[0]), ProgramInstruction::InitializeConfig { chain_id: CHAIN_ID });
+        let instruction = sent[0].message.instructions.last().unwrap();
+        let program_data = sent[0].message.account_keys[instruction.accounts[5] as usize];
+        assert_eq!(program_data, bpf_loader_upgradeable::get_program_data_address(&cli.program_id));
+    }
+
+    #[tokio::test]
+    async fn account_exists_only_reports_a_missing_account_as_absent() {
+        let program_id = Pubkey::new_unique();
+        let mut client = cluster(&program_id);
+        let unreachable = Pubkey::new_unique();
+        client.unreachable.insert(unreachable);
+
+        assert!(account_exists(&client, &program_id).await.unwrap());
+        assert!(!account_exists(&client, &Pubkey::new_unique()).await.unwrap());
+        assert!(account_exists(&client, &unreachable).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn run_sends_a_commitment_and_reveal_with_same_signer() {
+        let cli = cli(&["--generate", "--same-signer", "--secret", SECRET, "--chain-id", &CHAIN_ID.to_string()]);
+        let client = cluster(&cli.program_id);
+        run(&client, &cli).await.unwrap();
+
+        let sent = client.sent();
+        let instructions: Vec<_> = sent.iter().map(sent_instruction).collect();
+        let Some(ProgramInstruction::VerifySameSigner { pkg_a, pkg_b }) =
+            instructions.iter().find(|instruction| matches!(instruction, ProgramInstruction::VerifySameSigner { .. }))
+        else {
+            panic!("expected a VerifySameSigner, got {:?}", instructions);
+        };
+        assert_eq!(pkg_a.data, keccak::hash(&pkg_b.data).0);
+        assert_eq!(pkg_a.public_key, pkg_b.public_key);
+        assert_ne!(pkg_a.verifier_signature, pkg_b.verifier_signature);
+    }
+
+    #[tokio::test]
+    async fn dry_run_makes_no_rpc_calls() {
+        // A generated payer on localhost would otherwise be airdropped to
+        let cli = cli(&["--dry-run", "--generate", "--secret", SECRET, "--chain-id", &CHAIN_ID.to_string()]);
+        run(&OfflineRpc, &cli).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_instruction_sends_the_instruction_data() {
+        let cli = cli(&["--chain-id", "1"]);
+        let client = cluster(&cli.program_id);
+        let payer = Keypair::new();
+        let data = to_vec(&ProgramInstruction::InitializeState).unwrap();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &data, vec![AccountMeta::new(payer.pubkey(), true)]);
+
+        let submission = send_instruction(&client, &cli, &payer, instruction).await;
+
+        let sent = client.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(submission, Submission::success(&sent[0].signatures[0], Some(0)));
+        assert_eq!(sent[0].message.instructions.last().unwrap().data, data);
+        assert!(sent[0].is_signed());
+    }
+
+    // The blocking client needs the multi-threaded runtime for `block_in_place`, as in `main`
+    #[tokio::test(flavor = "multi_thread")]
+    async fn both_clients_build_and_send_the_same_transaction() {
+        let cli = cli(&["--blocking", "--chain-id", "1"]);
+        assert!(cli.blocking);
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_borsh(cli.program_id, &ProgramInstruction::InitializeState, vec![AccountMeta::new(payer.pubkey(), true)]);
+
+        // The mock senders answer every request, with the same blockhash for both clients
+        let blocking = BlockingRpcClient::new_mock("succeeds".to_string());
+        let blocking_submission = send_instruction(&blocking, &cli, &payer, instruction.clone()).await;
+        let nonblocking = RpcClient::new_mock("succeeds".to_string());
+        let nonblocking_submission = send_instruction(&nonblocking, &cli, &payer, instruction).await;
+
+        assert_eq!(blocking_submission.status, Status::Success);
+        assert_eq!(blocking_submission, nonblocking_submission);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn send_instruction_times_out_when_never_confirmed() {
+        let cli = cli(&["--chain-id", "1", "--timeout", "30"]);
+        let mut client = cluster(&cli.program_id);
+        client.stall_sends = true;
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[], vec![]);
+
+        let start = tokio::time::Instant::now();
+        let submission = send_instruction(&client, &cli, &payer, instruction).await;
+
+        // Sent once, then given up on after the timeout rather than waiting forever
+        assert_eq!(client.sent().len(), 1);
+        assert_eq!(start.elapsed(), Duration::from_secs(30));
+        assert_eq!(submission, Submission::failed(&client.sent()[0].signatures[0], None));
+    }
+
+    #[tokio::test]
+    async fn send_instruction_stops_when_simulation_fails() {
+        let cli = cli(&["--chain-id", "1"]);
+        let mut client = cluster(&cli.program_id);
+        client.simulation_error = Some(TransactionError::InstructionError(0, solana_program::instruction::InstructionError::Custom(1)));
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[], vec![]);
+
+        let submission = send_instruction(&client, &cli, &payer, instruction).await;
+
+        assert!(client.sent().is_empty());
+        assert_eq!(submission.status, Status::Failed);
+        assert_eq!(submission.error_code, Some(1));
+    }
+
+    #[tokio::test]
+    async fn send_instruction_reports_a_failed_send_with_its_error_code() {
+        let cli = cli(&["--chain-id", "1", "--json-output"]);
+        let client = cluster(&cli.program_id);
+        let program_error = TransactionError::InstructionError(0, solana_program::instruction::InstructionError::Custom(4));
+        client.send_results.lock().unwrap().push_back(Err(program_error.into()));
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[], vec![]);
+
+        let submission = send_instruction(&client, &cli, &payer, instruction).await;
+
+        let json: serde_json::Value = serde_json::from_str(&submission.to_json().unwrap()).unwrap();
+        assert_eq!(json["signature"], client.sent()[0].signatures[0].to_string());
+        assert_eq!((&json["status"], &json["error_code"], &json["slot"]), (&"failed".into(), &4.into(), &serde_json::Value::Null));
+    }
+
+    #[tokio::test]
+    async fn offline_makes_no_rpc_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("payer.json");
+        write_keypair_file(&Keypair::new(), &path).unwrap();
+        let blockhash = Hash::new_unique().to_string();
+        let chain_id = CHAIN_ID.to_string();
+        let cli = cli(&["--offline", "--blockhash", &blockhash, "--keypair", path.to_str().unwrap(), "--secret", SECRET, "--chain-id", &chain_id]);
+        run(&OfflineRpc, &cli).await.unwrap();
+    }
+
+    #[test]
+    fn offline_transaction_round_trips_through_base64() {
+        let cli = cli(&["--offline", "--blockhash", &Hash::new_unique().to_string(), "--chain-id", "1"]);
+        let payer = Keypair::new();
+        let blockhash = cli.blockhash.unwrap();
+        let package = ProgramInstruction::VerifySig(create_prehashed_package([0x33; 32], &[0x11; 32]).unwrap());
+        let instruction = Instruction::new_with_bytes(cli.program_id, &to_vec(&package).unwrap(), vec![AccountMeta::new(payer.pubkey(), true)]);
+
+        let transaction = offline_transaction(&cli, &payer, instruction, blockhash);
+        let decoded = decode_transaction(&format!("{}\n", encode_transaction(&transaction).unwrap())).unwrap();
+
+        assert_eq!(decoded, transaction);
+        assert!(decoded.is_signed());
+        assert_eq!(decoded.message.recent_blockhash, blockhash);
+        assert_eq!(decoded.message.account_keys[0], payer.pubkey());
+        assert_eq!(sent_instruction(&decoded), package);
+        let program_index = decoded.message.instructions.last().unwrap().program_id_index;
+        assert_eq!(decoded.message.account_keys[program_index as usize], cli.program_id);
+    }
+
+    #[tokio::test]
+    async fn broadcast_sends_an_offline_transaction_as_is() {
+        let cli = cli(&["--chain-id", "1"]);
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[0], vec![AccountMeta::new(payer.pubkey(), true)]);
+        let transaction = offline_transaction(&cli, &payer, instruction, Hash::new_unique());
+        let client = MockRpc::default();
+
+        assert!(broadcast(&client, &cli, &encode_transaction(&transaction).unwrap()).await);
+
+        assert_eq!(client.sent(), vec![transaction]);
+    }
+
+    #[tokio::test]
+    async fn broadcast_rejects_undecodable_or_unsigned_transactions() {
+        let cli = cli(&["--chain-id", "1"]);
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[0], vec![AccountMeta::new(payer.pubkey(), true)]);
+        let unsigned = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        let client = MockRpc::default();
+
+        assert!(!broadcast(&client, &cli, "not base64!").await);
+        assert!(!broadcast(&client, &cli, &BASE64_STANDARD.encode([1, 2, 3])).await);
+        assert!(!broadcast(&client, &cli, &encode_transaction(&unsigned).unwrap()).await);
+
+        assert!(client.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_batch_sends_every_package_across_transactions_that_fit() {
+        let cli = cli(&["--chain-id", "1"]);
+        let client = cluster(&cli.program_id);
+        let payer = Keypair::new();
+        let packages: Vec<_> = (0..40u8).map(|data| create_prehashed_package([data; 32], &[0x11; 32]).unwrap()).collect();
+
+        send_batch(&client, &cli, &payer, &[AccountMeta::new(payer.pubkey(), true)], packages.clone()).await.unwrap();
+
+        let sent = client.sent();
+        assert!(sent.len() > 1);
+        let mut sent_packages = Vec::new();
+        for transaction in &sent {
+            assert!(bincode::serialized_size(transaction).unwrap() <= PACKET_DATA_SIZE as u64);
+            let ProgramInstruction::VerifySigBatch(chunk) = sent_instruction(transaction) else {
+                panic!("expected a VerifySigBatch");
+            };
+            sent_packages.extend(chunk);
+        }
+        assert_eq!(sent_packages, packages);
+    }
+
+    /// A cluster where `payer` holds `lamports`.
+    fn with_balance(client: &mut MockRpc, payer: &Pubkey, lamports: u64) {
+        client.accounts.insert(*payer, Account { lamports, ..Account::default() });
+    }
+
+    #[tokio::test]
+    async fn prefund_check_rejects_a_low_balance_with_what_is_needed() {
+        let cli = cli(&["--chain-id", "1", "--compute-units", "200000", "--priority-fee", "1000"]);
+        let payer = Pubkey::new_unique();
+        let mut client = MockRpc::default();
+        with_balance(&mut client, &payer, 10_000);
+
+        let err = prefund_check(&client, &cli, &payer, true).await.unwrap_err();
+
+        // The two verifications and InitializeState, each with a 200 lamport priority fee, and the state's rent
+        let need = 3 * (LAMPORTS_PER_SIGNATURE + 200) + solana_sdk::rent::Rent::default().minimum_balance(ProgramState::LEN);
+        assert_eq!(err.to_string(), format!("insufficient funds: have 10000 lamports, need more than {} for 3 transactions", need));
+    }
+
+    #[tokio::test]
+    async fn prefund_check_accepts_a_balance_above_the_estimate() {
+        let cli = cli(&["--chain-id", "1"]);
+        let payer = Pubkey::new_unique();
+        let mut client = MockRpc::default();
+        let need = 2 * LAMPORTS_PER_SIGNATURE;
+
+        with_balance(&mut client, &payer, need);
+        assert!(prefund_check(&client, &cli, &payer, false).await.is_err());
+        with_balance(&mut client, &payer, need + 1);
+        assert!(prefund_check(&client, &cli, &payer, false).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn run_sends_nothing_when_the_prefund_check_fails() {
+        let cli = cli(&["--generate", "--prefund-check", "--secret", SECRET, "--chain-id", &CHAIN_ID.to_string()]);
+        let client = cluster(&cli.program_id);
+
+        let result = run(&client, &cli).await;
+
+        assert!(matches!(result, Err(RunError::Prefund(_))));
+
+        assert!(client.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_instruction_retries_transient_failures_with_a_new_blockhash() {
+        let cli = cli(&["--chain-id", "1"]);
+        let client = cluster(&cli.program_id);
+        for _ in 0..SEND_MAX_ATTEMPTS - 1 {
+            let io_error = io::Error::new(io::ErrorKind::TimedOut, "request timed out");
+            client.send_results.lock().unwrap().push_back(Err(io_error.into()));
+        }
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[0], vec![AccountMeta::new(payer.pubkey(), true)]);
+
+        let submission = send_instruction(&client, &cli, &payer, instruction).await;
+
+        let sent = client.sent();
+        assert_eq!(sent.len(), SEND_MAX_ATTEMPTS);
+        let blockhashes: HashSet<_> = sent.iter().map(|transaction| transaction.message.recent_blockhash).collect();
+        assert_eq!(blockhashes.len(), SEND_MAX_ATTEMPTS);
+        assert_eq!(submission, Submission::success(&sent[SEND_MAX_ATTEMPTS - 1].signatures[0], Some(0)));
+    }
+
+    #[tokio::test]
+    async fn send_instruction_gives_up_after_the_max_attempts() {
+        let cli = cli(&["--chain-id", "1"]);
+        let client = cluster(&cli.program_id);
+        for _ in 0..SEND_MAX_ATTEMPTS + 1 {
+            client.send_results.lock().unwrap().push_back(Err(TransactionError::BlockhashNotFound.into()));
+        }
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[0], vec![AccountMeta::new(payer.pubkey(), true)]);
+
+        let submission = send_instruction(&client, &cli, &payer, instruction).await;
+
+        assert_eq!(client.sent().len(), SEND_MAX_ATTEMPTS);
+        assert_eq!(submission.status, Status::Failed);
+    }
+
+    #[tokio::test]
+    async fn send_instruction_fails_without_sending_when_no_blockhash_can_be_fetched() {
+        let cli = cli(&["--chain-id", "1"]);
+        let client = cluster(&cli.program_id);
+        for _ in 0..SEND_MAX_ATTEMPTS {
+            let io_error = io::Error::new(io::ErrorKind::TimedOut, "request timed out");
+            client.blockhash_results.lock().unwrap().push_back(Err(io_error.into()));
+        }
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[0], vec![AccountMeta::new(payer.pubkey(), true)]);
+
+        let submission = send_instruction(&client, &cli, &payer, instruction).await;
+
+        assert!(client.sent().is_empty());
+        assert_eq!(client.blockhashes_fetched.load(Ordering::Relaxed), SEND_MAX_ATTEMPTS);
+        assert_eq!(submission, Submission::unsigned());
+    }
+
+    #[tokio::test]
+    async fn send_instruction_counts_an_already_processed_send_as_a_success() {
+        let cli = cli(&["--chain-id", "1"]);
+        let client = cluster(&cli.program_id);
+        client.send_results.lock().unwrap().push_back(Err(TransactionError::AlreadyProcessed.into()));
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[0], vec![AccountMeta::new(payer.pubkey(), true)]);
+
+        let submission = send_instruction(&client, &cli, &payer, instruction).await;
+
+        let sent = client.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(submission, Submission::success(&sent[0].signatures[0], Some(0)));
+    }
+
+    #[tokio::test]
+    async fn send_instruction_does_not_retry_a_program_error() {
+        let cli = cli(&["--chain-id", "1"]);
+        let client = cluster(&cli.program_id);
+        let program_error = TransactionError::InstructionError(1, solana_program::instruction::InstructionError::Custom(1));
+        client.send_results.lock().unwrap().push_back(Err(program_error.into()));
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[0], vec![AccountMeta::new(payer.pubkey(), true)]);
+
+        let submission = send_instruction(&client, &cli, &payer, instruction).await;
+
+        let sent = client.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(submission.error_code, Some(1));
+    }
+
+    #[test]
+    fn load_payer_reads_a_written_keypair_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("payer.json");
+        let keypair = Keypair::new();
+        write_keypair_file(&keypair, &path).unwrap();
+
+        let loaded = load_payer(Some(path.to_str().unwrap())).unwrap();
+
+        assert_eq!(loaded.to_bytes(), keypair.to_bytes());
+    }
+
+    #[test]
+    fn load_payer_fails_on_a_missing_or_malformed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("payer.json");
+        assert!(load_payer(Some(path.to_str().unwrap())).is_err());
+
+        fs::write(&path, "[1, 2, 3]").unwrap();
+        assert!(load_payer(Some(path.to_str().unwrap())).is_err());
+    }
+
+    #[test]
+    fn keygen_writes_a_key_readable_by_secret() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signer.hex");
+        let path = path.to_str().unwrap();
+
+        let (public_key, eth_address) = keygen(path, None).unwrap();
+
+        let secret = cli(&["--secret", path]).secret.unwrap();
+        assert_eq!(public_key, PublicKey::from_secret_key(&SecretKey::parse(&secret).unwrap()).serialize());
+        // The address is the last 20 bytes of the Keccak-256 hash of the key without its prefix
+        assert_eq!(eth_address[..], keccak::hash(&public_key[1..]).0[12..]);
+    }
+
+    #[test]
+    fn keygen_with_a_seed_always_writes_the_same_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.hex");
+        let second = dir.path().join("second.hex");
+
+        let first_keys = keygen(first.to_str().unwrap(), Some(&[0x11; 32])).unwrap();
+        let second_keys = keygen(second.to_str().unwrap(), Some(&[0x11; 32])).unwrap();
+
+        assert_eq!(first_keys, second_keys);
+        assert_eq!(fs::read_to_string(&first).unwrap(), format!("{}\n", SECRET));
+        assert_eq!(fs::read_to_string(&second).unwrap(), fs::read_to_string(&first).unwrap());
+    }
+
+    #[test]
+    fn keygen_refuses_to_overwrite_an_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signer.hex");
+        fs::write(&path, "existing key").unwrap();
+
+        assert!(keygen(path.to_str().unwrap(), Some(&[0x11; 32])).is_err());
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "existing key");
+    }
+
+    #[test]
+    fn keygen_rejects_an_invalid_seed_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signer.hex");
+
+        assert!(keygen(path.to_str().unwrap(), Some(&[0; 32])).is_err());
+
+        assert!(!path.exists());
+    }
+
+    /// `web3.eth.accounts.sign("Some data", "0x4c08…2318")` from the web3.js documentation: the
+    /// EIP-191 message a wallet signs, its Keccak-256 hash, the signature `r || s` with `v = 0x1c`
+    /// and the signing account's address.
+    const WEB3_MESSAGE: &[u8] = b"\x19Ethereum Signed Message:\n9Some data";
+    const WEB3_HASH: &str = "1da44b586eb0729ff70a73c326926f6ed5a25f5b056e7f47fbc6e58d86871655";
+    const WEB3_SIGNATURE: &str = "b91467e570a6466aa9e9876cbcd013baba02900b8979d43fe208a4a4f339f5fd6007e74cd82e037b800186422fc2da167c747ef045e5d18a5f5d4300f8e1a029";
+    const WEB3_ADDRESS: &str = "2c7536e3605d9c16a7a3d7b1898e529396a65c23";
+
+    #[test]
+    fn recover_address_recovers_the_known_signer_from_a_message_or_its_hash() {
+        let signature = cli::parse_signature(WEB3_SIGNATURE).unwrap();
+        let recovery_id = cli::parse_recovery_id("28").unwrap();
+
+        let (public_key, eth_address) = recover_address(&signature, recovery_id, WEB3_MESSAGE, false).unwrap();
+        assert_eq!(hex::encode(eth_address), WEB3_ADDRESS);
+
+        let hash = hex::decode(WEB3_HASH).unwrap();
+        assert_eq!(recover_address(&signature, recovery_id, &hash, true).unwrap(), (public_key, eth_address));
+    }
+
+    #[test]
+    fn recover_address_rejects_a_prehashed_message_that_is_not_a_digest() {
+        let signature = cli::parse_signature(WEB3_SIGNATURE).unwrap();
+
+        assert!(recover_address(&signature, 1, WEB3_MESSAGE, true).is_err());
+        assert!(recover_address(&signature, 1, &[0x33; 31], true).is_err());
+    }
+
+    #[tokio::test]
+    async fn run_airdrops_to_a_generated_payer_only_on_localhost() {
+        let chain_id = CHAIN_ID.to_string();
+        for (name, airdrops) in [("localhost", 1), ("devnet", 0), ("mainnet-beta", 0)] {
+            let cli = cli(&["--generate", "--cluster", name, "--secret", SECRET, "--chain-id", &chain_id]);
+            let client = cluster(&cli.program_id);
+            run(&client, &cli).await.unwrap();
+
+            assert_eq!(client.airdrops().len(), airdrops, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn send_instruction_sends_despite_a_failed_simulation_with_force() {
+        let cli = cli(&["--chain-id", "1", "--force"]);
+        let mut client = cluster(&cli.program_id);
+        client.simulation_error = Some(TransactionError::InstructionError(0, solana_program::instruction::InstructionError::Custom(1)));
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[], vec![]);
+
+        let submission = send_instruction(&client, &cli, &payer, instruction).await;
+
+        assert_eq!(client.sent().len(), 1);
+        assert_eq!(submission.status, Status::Success);
+    }
+
+    #[test]
+    fn with_compute_budget_prepends_the_limit_and_price() {
+        let cli = cli(&["--compute-units", "400000", "--priority-fee", "1000"]);
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[1, 2, 3], vec![]);
+
+        let instructions = with_compute_budget(&cli, instruction.clone());
+
+        assert_eq!(
+            instructions,
+            [
+                ComputeBudgetInstruction::set_compute_unit_limit(400_000),
+                ComputeBudgetInstruction::set_compute_unit_price(1_000),
+                instruction,
+            ]
+        );
+    }
+
+    #[test]
+    fn with_compute_budget_defaults_to_the_limit_only() {
+        let cli = cli(&[]);
+        let instruction = Instruction::new_with_bytes(cli.program_id, &[1, 2, 3], vec![]);
+
+        let instructions = with_compute_budget(&cli, instruction.clone());
+
+        assert_eq!(instructions, [ComputeBudgetInstruction::set_compute_unit_limit(DEFAULT_COMPUTE_UNITS), instruction]);
+    }
+
+    #[test]
+    fn load_data_hashes_the_file_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("attestation.txt");
+        fs::write(&path, "validator 7 attests to epoch 512").unwrap();
+
+        let data = load_data(path.to_str().unwrap(), false).unwrap();
+        let (program_id, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let package = create_and_sign_package(&program_id, &payer, CHAIN_ID, data, i64::MAX, [0; 8], HASH_ALGO_KECCAK256, &[0x11; 32]).unwrap();
+
+        assert_eq!(package.data, keccak::hash(b"validator 7 attests to epoch 512").0);
+    }
+
+    #[test]
+    fn load_data_uses_raw_contents_as_is() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        fs::write(&path, [0x33; 32]).unwrap();
+
+        assert_eq!(load_data(path.to_str().unwrap(), true).unwrap(), [0x33; 32]);
+    }
+
+    #[test]
+    fn load_data_rejects_raw_contents_that_are_not_32_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        fs::write(&path, [0x33; 31]).unwrap();
+
+        let err = load_data(path.to_str().unwrap(), true).unwrap_err();
+        assert_eq!(err.to_string(), "--raw data must be exactly 32 bytes, got 31");
+    }
+
+    #[test]
+    fn load_data_reports_an_unreadable_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.txt");
+
+        let err = load_data(path.to_str().unwrap(), false).unwrap_err();
+        assert!(err.to_string().starts_with("unable to read data file"), "{}", err);
+    }
+
+    #[test]
+    fn written_package_reads_back_byte_for_byte() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("package.json");
+        let (program_id, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let package = create_and_sign_package(&program_id, &payer, CHAIN_ID, [0x33; 32], 1_700_000_000, [7; 8], HASH_ALGO_KECCAK256, &[0x11; 32]).unwrap();
+
+        write_package(path.to_str().unwrap(), &package).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+        let read = read_package(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(read, package);
+        assert!(json.contains(&hex::encode(package.verifier_signature)), "{}", json);
+    }
+
+    #[test]
+    fn verify_package_file_accepts_a_signed_package_and_rejects_a_tampered_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let (program_id, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let package = create_and_sign_package(&program_id, &payer, CHAIN_ID, [0x33; 32], i64::MAX, [0; 8], HASH_ALGO_KECCAK256, &[0x11; 32]).unwrap();
+        let good = dir.path().join("good.json");
+        write_package(good.to_str().unwrap(), &package).unwrap();
+        let mut tampered_package = package;
+        tampered_package.data[0] ^= 1;
+        let tampered = dir.path().join("tampered.json");
+        write_package(tampered.to_str().unwrap(), &tampered_package).unwrap();
+
+        assert!(verify_package_file(&program_id, &payer, CHAIN_ID, good.to_str().unwrap()).unwrap());
+        assert!(!verify_package_file(&program_id, &payer, CHAIN_ID, tampered.to_str().unwrap()).unwrap());
+        // Valid only for the payer and cluster it was signed for
+        assert!(!verify_package_file(&program_id, &Pubkey::new_unique(), CHAIN_ID, good.to_str().unwrap()).unwrap());
+        assert!(!verify_package_file(&program_id, &payer, CHAIN_ID + 1, good.to_str().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn read_package_rejects_malformed_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("package.json");
+        fs::write(&path, r#"{"recovery_id": 0}"#).unwrap();
+
+        let err = read_package(path.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().starts_with("invalid package"), "{}", err);
+    }
+
+    /// A JSONL file of a valid package, a blank line, a malformed line and a tampered package.
+    fn write_package_lines(dir: &Path, program_id: &Pubkey, payer: &Pubkey) -> (String, SignaturePackage) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let package = create_and_sign_package(program_id, payer, CHAIN_ID, [0x33; 32], now + 300, [0; 8], HASH_ALGO_KECCAK256, &[0x11; 32]).unwrap();
+        let mut tampered = package.clone();
+        tampered.data = [0x34; 32];
+        let contents = [serde_json::to_string(&package).unwrap(), String::new(), "{not json".to_string(), serde_json::to_string(&tampered).unwrap()];
+        let path = dir.join("packages.jsonl");
+        fs::write(&path, contents.join("\n")).unwrap();
+        (path.to_str().unwrap().to_string(), package)
+    }
+
+    #[test]
+    fn check_package_lines_reports_each_line_by_its_number() {
+        let dir = tempfile::tempdir().unwrap();
+        let (program_id, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (path, package) = write_package_lines(dir.path(), &program_id, &payer);
+        let mut report = Vec::new();
+
+        let lines = read_package_lines(&path, false).unwrap();
+        let (valid, malformed) = check_package_lines(&program_id, &payer, CHAIN_ID, lines, &mut report).unwrap();
+
+        assert_eq!((valid, malformed), (vec![package.clone()], 1));
+        let report = String::from_utf8(report).unwrap();
+        let report: Vec<_> = report.lines().collect();
+        let eth_address = hex::encode(pubkey_to_eth_address(&parse_pubkey(&package.public_key).unwrap()));
+        assert_eq!(report.len(), 3);
+        assert_eq!(report[0], format!("line 1: valid, signed by 0x{}", eth_address));
+        assert!(report[1].starts_with("line 3: malformed, skipped: "), "{}", report[1]);
+        assert_eq!(report[2], "line 4: invalid: PublicKeyMismatch");
+    }
+
+    #[test]
+    fn read_package_lines_fails_on_the_first_malformed_line_when_strict() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _package) = write_package_lines(dir.path(), &Pubkey::new_unique(), &Pubkey::new_unique());
+
+        let err = read_package_lines(&path, true).unwrap_err();
+
+        assert!(err.to_string().starts_with("line 3: malformed package: "), "{}", err);
+        assert!(read_package_lines("no-such-file.jsonl", false).is_err());
+    }
+
+    #[tokio::test]
+    async fn run_submits_a_package_file_without_resigning() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair_path = dir.path().join("payer.json");
+        let package_path = dir.path().join("package.json");
+        let payer = Keypair::new();
+        write_keypair_file(&payer, &keypair_path).unwrap();
+        let cli = cli(&[
+            "--keypair",
+            keypair_path.to_str().unwrap(),
+            "--package",
+            package_path.to_str().unwrap(),
+            "--chain-id",
+            &CHAIN_ID.to_string(),
+        ]);
+        let package =
+            create_and_sign_package(&cli.program_id, &payer.pubkey(), CHAIN_ID, [0x33; 32], i64::MAX, [0; 8], HASH_ALGO_KECCAK256, &[0x11; 32]).unwrap();
+        write_package(package_path.to_str().unwrap(), &package).unwrap();
+
+        let client = cluster(&cli.program_id);
+        run(&client, &cli).await.unwrap();
+
+        let sent: Vec<_> = client.sent().iter().map(sent_instruction).collect();
+        assert!(sent.contains(&ProgramInstruction::VerifySig(package)), "{:?}", sent);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn request_airdrop_recovers_after_failed_confirmations() {
+        let cli = cli(&[]);
+        let client = MockRpc::default();
+        *client.confirm_results.lock().unwrap() =
+            VecDeque::from([Err(RpcError::ForUser("connection reset".to_string()).into()), Ok(false), Ok(true)]);
+        let start = tokio::time::Instant::now();
+
+        request_airdrop(&client, &cli, &Pubkey::new_unique(), AIRDROP_LAMPORTS).await.unwrap();
+
+        assert!(client.confirm_results.lock().unwrap().is_empty());
+        // Waited 500ms, then 1s, before the third attempt
+        assert_eq!(start.elapsed(), AIRDROP_INITIAL_BACKOFF * 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn request_airdrop_gives_up_after_the_max_attempts() {
+        let cli = cli(&[]);
+        let client = MockRpc::default();
+        for _ in 0..AIRDROP_MAX_ATTEMPTS {
+            client.confirm_results.lock().unwrap().push_back(Ok(false));
+        }
+
+        let err = request_airdrop(&client, &cli, &Pubkey::new_unique(), AIRDROP_LAMPORTS).await.unwrap_err();
+
+        assert!(matches!(err, SignerError::AirdropTimeout(_, AIRDROP_MAX_ATTEMPTS)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn request_airdrop_polls_when_the_websocket_is_unreachable() {
+        // Nothing listens on port 1, so the subscription fails and the airdrop is polled instead
+        let cli = cli(&["--ws", "--ws-url", "ws://127.0.0.1:1"]);
+        let client = MockRpc::default();
+        client.confirm_results.lock().unwrap().extend([Ok(false), Ok(true)]);
+
+        request_airdrop(&client, &cli, &Pubkey::new_unique(), AIRDROP_LAMPORTS).await.unwrap();
+
+        assert!(client.confirm_results.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn request_airdrop_reports_a_failed_request_as_an_rpc_error() {
+        let cli = cli(&[]);
+        let client = MockRpc { fail_airdrops: true, ..MockRpc::default() };
+
+        let err = request_airdrop(&client, &cli, &Pubkey::new_unique(), AIRDROP_LAMPORTS).await.unwrap_err();
+
+        assert!(matches!(err, SignerError::Rpc(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn fund_keypairs_writes_and_funds_each_keypair() {
+        let dir = tempfile::tempdir().unwrap();
+        let cli = cli(&[]);
+        let client = MockRpc::default();
+
+        let funded = fund_keypairs(&client, &cli, 3, dir.path()).await.unwrap();
+
+        assert_eq!(funded.len(), 3);
+        assert!(funded.iter().all(|(_, ok)| *ok));
+        assert_eq!(client.airdrops(), funded.iter().map(|(pubkey, _)| *pubkey).collect::<Vec<_>>());
+        for (pubkey, _) in &funded {
+            let keypair = load_payer(dir.path().join(format!("{}.json", pubkey)).to_str()).unwrap();
+            assert_eq!(keypair.pubkey(), *pubkey);
+        }
+    }
+
+    #[tokio::test]
+    async fn fund_keypairs_reports_failed_airdrops_per_keypair() {
+        let dir = tempfile::tempdir().unwrap();
+        let cli = cli(&[]);
+        let client = MockRpc { fail_airdrops: true, ..MockRpc::default() };
+
+        let funded = fund_keypairs(&client, &cli, 3, dir.path()).await.unwrap();
+
+        // Every airdrop was still attempted, and the keypairs kept
+        assert_eq!(funded.len(), 3);
+        assert!(funded.iter().all(|(_, ok)| !*ok));
+        assert_eq!(client.airdrops().len(), 3);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn log_filter_maps_verbosity_to_this_crates_level() {
+        let enabled = |verbose: u8, target: &str, level: log::Level| {
+            let logger = env_logger::Builder::new().parse_filters(&log_filter(verbose)).build();
+            log::Log::enabled(&logger, &log::Metadata::builder().target(target).level(level).build())
+        };
+
+        assert!(enabled(0, "secp256k1_signer_example", log::Level::Info));
+        assert!(!enabled(0, "secp256k1_signer_example", log::Level::Debug));
+        assert!(enabled(1, "secp256k1_signer_example", log::Level::Debug));
+        assert!(!enabled(1, "secp256k1_signer_example", log::Level::Trace));
+        assert!(enabled(2, "secp256k1_signer_example", log::Level::Trace));
+        // Dependencies stay at `warn` whatever the verbosity
+        assert!(enabled(2, "solana_client", log::Level::Warn));
+        assert!(!enabled(2, "solana_client", log::Level::Info));
+    }
+
+    // The only test installing the global logger, a second `init` would panic
+    #[test]
+    fn init_logger_installs_the_logger() {
+        init_logger(1);
+        assert!(log::log_enabled!(log::Level::Debug) || std::env::var_os("RUST_LOG").is_some());
+    }
+
+    #[tokio::test]
+    async fn submit_many_sends_count_transactions_over_distinct_data() {
+        let cli = cli(&["--count", "12", "--concurrency", "4", "--chain-id", &CHAIN_ID.to_string()]);
+        let client = MockRpc::default();
+        // A program error fails its transaction without a retry
+        let program_error = TransactionError::InstructionError(0, solana_program::instruction::InstructionError::Custom(1));
+        client.send_results.lock().unwrap().push_back(Err(program_error.into()));
+        let payer = Keypair::new();
+
+        let summary = submit_many(&client, &cli, &payer, &[0x11; 32], CHAIN_ID, &[], 12, future::pending).await;
+
+        let data: HashSet<_> = client
+            .sent()
+            .iter()
+            .map(|transaction| match sent_instruction(transaction) {
+                ProgramInstruction::VerifySig(package) => package.data,
+                instruction => panic!("unexpected instruction {:?}", instruction),
+            })
+            .collect();
+        assert_eq!(client.sent().len(), 12);
+        assert_eq!(data.len(), 12);
+        assert_eq!(summary, CountSummary { submitted: 12, confirmed: 11, failed: 1 });
+    }
+
+    #[tokio::test]
+    async fn submit_many_signs_an_expired_send_again_with_a_new_blockhash() {
+        let cli = cli(&["--count", "1", "--chain-id", &CHAIN_ID.to_string()]);
+        let client = MockRpc::default();
+        client.send_results.lock().unwrap().push_back(Err(TransactionError::BlockhashNotFound.into()));
+
+        submit_many(&client, &cli, &Keypair::new(), &[0x11; 32], CHAIN_ID, &[], 1, future::pending).await;
+
+        let sent = client.sent();
+        assert_eq!(sent.len(), 2);
+        assert_ne!(sent[0].message.recent_blockhash, sent[1].message.recent_blockhash);
+        assert_eq!(sent_instruction(&sent[0]), sent_instruction(&sent[1]));
+    }
+
+    #[tokio::test]
+    async fn submit_many_counts_an_already_processed_send_as_confirmed() {
+        let cli = cli(&["--count", "1", "--chain-id", &CHAIN_ID.to_string()]);
+        let client = MockRpc::default();
+        client.send_results.lock().unwrap().push_back(Err(TransactionError::AlreadyProcessed.into()));
+
+        let summary = submit_many(&client, &cli, &Keypair::new(), &[0x11; 32], CHAIN_ID, &[], 1, future::pending).await;
+
+        assert_eq!(client.sent().len(), 1);
+        assert_eq!(summary, CountSummary { submitted: 1, confirmed: 1, failed: 0 });
+    }
+
+    /// An interrupt after a second, then again after `later` if given, or never.
+    fn interrupts(later: Option<Duration>) -> impl Fn() -> future::BoxFuture<'static, io::Result<()>> {
+        let calls = AtomicUsize::new(0);
+        move || {
+            let delay = if calls.fetch_add(1, Ordering::Relaxed) == 0 { Some(Duration::from_secs(1)) } else { later };
+            Box::pin(async move {
+                match delay {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => future::pending().await,
+                }
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn submit_many_stops_submitting_when_interrupted_and_waits_out_the_grace_period() {
+        let cli = cli(&["--count", "10", "--concurrency", "2", "--chain-id", &CHAIN_ID.to_string()]);
+        let client = MockRpc { stall_sends: true, ..MockRpc::default() };
+        let start = tokio::time::Instant::now();
+
+        let summary = submit_many(&client, &cli, &Keypair::new(), &[0x11; 32], CHAIN_ID, &[], 10, interrupts(None)).await;
+
+        // Only the two in flight when interrupted were ever sent, and they never settled
+        assert_eq!(client.sent().len(), 2);
+        assert_eq!(summary, CountSummary { submitted: 2, confirmed: 0, failed: 0 });
+        assert!(start.elapsed() >= Duration::from_secs(1) + SHUTDOWN_GRACE_PERIOD);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn submit_many_stops_waiting_at_a_second_interrupt() {
+        let cli = cli(&["--count", "10", "--concurrency", "2", "--chain-id", &CHAIN_ID.to_string()]);
+        let client = MockRpc { stall_sends: true, ..MockRpc::default() };
+        let start = tokio::time::Instant::now();
+
+        let summary = submit_many(&client, &cli, &Keypair::new(), &[0x11; 32], CHAIN_ID, &[], 10, interrupts(Some(Duration::from_secs(1)))).await;
+
+        assert_eq!(summary, CountSummary { submitted: 2, confirmed: 0, failed: 0 });
+        assert!(start.elapsed() < SHUTDOWN_GRACE_PERIOD);
+    }
+
+    /// `client` with `data` at `payer`'s state PDA, owned by `owner`.
+    fn with_state(client: &mut MockRpc, program_id: &Pubkey, payer: &Pubkey, owner: Pubkey, data: Vec<u8>) {
+        let (state_pda, _bump) = Pubkey::find_program_address(&[STATE_SEED, payer.as_ref()], program_id);
+        client.accounts.insert(state_pda, Account { owner, data, ..Account::default() });
+    }
+
+    #[tokio::test]
+    async fn query_state_reads_current_and_older_layouts() {
+        let (program_id, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let state = ProgramState { verification_count: 3, last_data: [0x33; 32], admin: payer, last_tag: [7; 8] };
+        let mut current = vec![0u8; ProgramState::LEN];
+        state.pack(&mut current).unwrap();
+        let unversioned = [&3u64.to_le_bytes()[..], &[0x33; 32]].concat();
+
+        for data in [current, unversioned] {
+            let mut client = MockRpc::default();
+            with_state(&mut client, &program_id, &payer, program_id, data);
+            assert!(query_state(&client, &program_id, &payer).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn query_state_fails_for_a_missing_foreign_or_unreadable_account() {
+        let (program_id, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(!query_state(&MockRpc::default(), &program_id, &payer).await);
+
+        let mut client = MockRpc::default();
+        with_state(&mut client, &program_id, &payer, system_program::ID, Vec::new());
+        assert!(!query_state(&client, &program_id, &payer).await);
+
+        // An unknown version
+        let mut client = MockRpc::default();
+        with_state(&mut client, &program_id, &payer, program_id, vec![0xff; ProgramState::LEN]);
+        assert!(!query_state(&client, &program_id, &payer).await);
+    }
+
+    #[tokio::test]
+    async fn check_deploy_accepts_a_deployed_program() {
+        let program_id = Pubkey::new_unique();
+
+        assert!(check_deploy(&cluster(&program_id), &program_id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn check_deploy_rejects_a_non_executable_account() {
+        let program_id = Pubkey::new_unique();
+        let mut client = MockRpc::default();
+        client.accounts.insert(program_id, Account { owner: bpf_loader_upgradeable::ID, ..Account::default() });
+
+        let err = check_deploy(&client, &program_id).await.unwrap_err();
+
+        assert!(matches!(err, DeployError::NotExecutable(key) if key == program_id), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn check_deploy_rejects_a_missing_or_non_bpf_account() {
+        let program_id = Pubkey::new_unique();
+        let err = check_deploy(&MockRpc::default(), &program_id).await.unwrap_err();
+        assert!(matches!(err, DeployError::Unavailable(key, _) if key == program_id), "{:?}", err);
+
+        let mut client = MockRpc::default();
+        let account = Account { executable: true, owner: system_program::ID, ..Account::default() };
+        client.accounts.insert(program_id, account);
+        let err = check_deploy(&client, &program_id).await.unwrap_err();
+        assert!(matches!(err, DeployError::NotBpfProgram(key, owner) if key == program_id && owner == system_program::ID), "{:?}", err);
+    }
+}
@@ -0,0 +1,117 @@
+//! The `--json-output` result, a single JSON object on stdout for scripts to parse. Everything
+//! else the signer reports goes through the logger to stderr.
+
+use serde::Serialize;
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::TransactionError;
+
+/// Whether the verification transaction succeeded.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Success,
+    Failed,
+}
+
+/// The outcome of sending a transaction.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Submission {
+    /// The transaction's signature, base58.
+    pub signature: String,
+    /// Slot the transaction was processed in, `null` when it never landed.
+    pub slot: Option<u64>,
+    pub status: Status,
+    /// The `ProgramError::Custom` code the transaction failed with, `null` when it succeeded or
+    /// failed some other way.
+    pub error_code: Option<u32>,
+}
+
+impl Submission {
+    /// A transaction that landed in `slot`.
+    pub fn success(signature: &Signature, slot: Option<u64>) -> Self {
+        Self {
+            signature: signature.to_string(),
+            slot,
+            status: Status::Success,
+            error_code: None,
+        }
+    }
+
+    /// A transaction that failed, with the transaction error if the cluster reported one.
+    pub fn failed(signature: &Signature, error: Option<&TransactionError>) -> Self {
+        Self {
+            signature: signature.to_string(),
+            slot: None,
+            status: Status::Failed,
+            error_code: error.and_then(custom_error_code),
+        }
+    }
+
+    /// A transaction that was never signed, for want of a blockhash. Its signature is the all-zero
+    /// default one.
+    pub fn unsigned() -> Self {
+        Self::failed(&Signature::default(), None)
+    }
+
+    /// The submission as one line of JSON.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    /// Prints the submission to stdout as one line of JSON.
+    pub fn print_json(&self) -> serde_json::Result<()> {
+        println!("{}", self.to_json()?);
+        Ok(())
+    }
+}
+
+/// The custom code of a transaction that failed with `ProgramError::Custom`, whichever program
+/// returned it.
+fn custom_error_code(error: &TransactionError) -> Option<u32> {
+    match error {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => Some(*code),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn parse(submission: &Submission) -> Value {
+        let line = submission.to_json().unwrap();
+        assert!(!line.contains('\n'));
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn success_has_a_slot_and_no_error_code() {
+        let signature = Signature::new_unique();
+
+        let json = parse(&Submission::success(&signature, Some(1234)));
+
+        assert_eq!(json, json!({ "signature": signature.to_string(), "slot": 1234, "status": "success", "error_code": null }));
+    }
+
+    #[test]
+    fn failure_has_the_custom_error_code() {
+        let signature = Signature::new_unique();
+        let program_error = TransactionError::InstructionError(0, InstructionError::Custom(6));
+
+        let json = parse(&Submission::failed(&signature, Some(&program_error)));
+
+        assert_eq!(json, json!({ "signature": signature.to_string(), "slot": null, "status": "failed", "error_code": 6 }));
+    }
+
+    #[test]
+    fn failure_without_a_custom_error_has_no_code() {
+        let signature = Signature::new_unique();
+
+        for error in [None, Some(TransactionError::InsufficientFundsForFee)] {
+            let json = parse(&Submission::failed(&signature, error.as_ref()));
+            assert_eq!((&json["status"], &json["error_code"]), (&json!("failed"), &Value::Null));
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! The RPC calls the signer makes, behind a trait so the same flow runs on the nonblocking client
+//! or, with `--blocking`, on the blocking one. Everything after the client is created takes
+//! `&impl SolanaRpc`, so an in-memory implementation returning canned responses can drive the
+//! whole submission flow without a validator.
+
+use solana_client::client_error::Result as ClientResult;
+use solana_client::rpc_client::RpcClient as BlockingRpcClient;
+use solana_client::rpc_response::{Response, RpcSimulateTransactionResult};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::account::Account;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::Transaction;
+
+/// The cluster as the signer sees it. Each method mirrors the `RpcClient` method of the same name.
+pub trait SolanaRpc {
+    /// The account at `pubkey`, failing if it doesn't exist. Used to check the program and to
+    /// find out whether the config and state PDAs have been created.
+    async fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account>;
+
+    /// The balance of `pubkey` in lamports, 0 for an account that doesn't exist.
+    async fn get_balance(&self, pubkey: &Pubkey) -> ClientResult<u64>;
+
+    /// The lamports an account of `data_len` bytes needs to be rent exempt.
+    async fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ClientResult<u64>;
+
+    /// A recent blockhash to sign transactions with.
+    async fn get_latest_blockhash(&self) -> ClientResult<Hash>;
+
+    /// The cluster's genesis hash, which the chain id packages are signed for is derived from.
+    async fn get_genesis_hash(&self) -> ClientResult<Hash>;
+
+    /// The current slot, which `--slot-bound` signs.
+    async fn get_slot(&self) -> ClientResult<u64>;
+
+    /// Simulates the transaction, returning its logs, compute units and error.
+    async fn simulate_transaction(&self, transaction: &Transaction) -> ClientResult<Response<RpcSimulateTransactionResult>>;
+
+    /// Sends the transaction and waits for it to be confirmed at the client's commitment.
+    async fn send_and_confirm_transaction(&self, transaction: &Transaction) -> ClientResult<Signature>;
+
+    /// Requests an airdrop of `lamports` to `pubkey`, returning the airdrop's signature without
+    /// waiting for it.
+    async fn request_airdrop(&self, pubkey: &Pubkey, lamports: u64) -> ClientResult<Signature>;
+
+    /// Whether the transaction with `signature` has been confirmed at the client's commitment.
+    async fn confirm_transaction(&self, signature: &Signature) -> ClientResult<bool>;
+
+    /// Slot a transaction was processed in, or `None` if the cluster doesn't know it.
+    async fn get_signature_slot(&self, signature: &Signature) -> ClientResult<Option<u64>>;
+}
+
+impl SolanaRpc for RpcClient {
+    async fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account> {
+        RpcClient::get_account(self, pubkey).await
+    }
+
+    async fn get_balance(&self, pubkey: &Pubkey) -> ClientResult<u64> {
+        RpcClient::get_balance(self, pubkey).await
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ClientResult<u64> {
+        RpcClient::get_minimum_balance_for_rent_exemption(self, data_len).await
+    }
+
+    async fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        RpcClient::get_latest_blockhash(self).await
+    }
+
+    async fn get_genesis_hash(&self) -> ClientResult<Hash> {
+        RpcClient::get_genesis_hash(self).await
+    }
+
+    async fn get_slot(&self) -> ClientResult<u64> {
+        RpcClient::get_slot(self).await
+    }
+
+    async fn simulate_transaction(&self, transaction: &Transaction) -> ClientResult<Response<RpcSimulateTransactionResult>> {
+        RpcClient::simulate_transaction(self, transaction).await
+    }
+
+    async fn send_and_confirm_transaction(&self, transaction: &Transaction) -> ClientResult<Signature> {
+        RpcClient::send_and_confirm_transaction(self, transaction).await
+    }
+
+    async fn request_airdrop(&self, pubkey: &Pubkey, lamports: u64) -> ClientResult<Signature> {
+        RpcClient::request_airdrop(self, pubkey, lamports).await
+    }
+
+    async fn confirm_transaction(&self, signature: &Signature) -> ClientResult<bool> {
+        RpcClient::confirm_transaction(self, signature).await
+    }
+
+    async fn get_signature_slot(&self, signature: &Signature) -> ClientResult<Option<u64>> {
+        let statuses = RpcClient::get_signature_statuses(self, &[*signature]).await?;
+        Ok(statuses.value.into_iter().flatten().next().map(|status| status.slot))
+    }
+}
+
+// The blocking client runs each call on its own runtime, inside `block_in_place`, so these
+// futures complete without yielding. That needs the multi-threaded tokio runtime `main` uses, and
+// `--blocking` still runs the async flow, only each call blocks its task.
+impl SolanaRpc for BlockingRpcClient {
+    async fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account> {
+        BlockingRpcClient::get_account(self, pubkey)
+    }
+
+    async fn get_balance(&self, pubkey: &Pubkey) -> ClientResult<u64> {
+        BlockingRpcClient::get_balance(self, pubkey)
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ClientResult<u64> {
+        BlockingRpcClient::get_minimum_balance_for_rent_exemption(self, data_len)
+    }
+
+    async fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        BlockingRpcClient::get_latest_blockhash(self)
+    }
+
+    async fn get_genesis_hash(&self) -> ClientResult<Hash> {
+        BlockingRpcClient::get_genesis_hash(self)
+    }
+
+    async fn get_slot(&self) -> ClientResult<u64> {
+        BlockingRpcClient::get_slot(self)
+    }
+
+    async fn simulate_transaction(&self, transaction: &Transaction) -> ClientResult<Response<RpcSimulateTransactionResult>> {
+        BlockingRpcClient::simulate_transaction(self, transaction)
+    }
+
+    async fn send_and_confirm_transaction(&self, transaction: &Transaction) -> ClientResult<Signature> {
+        BlockingRpcClient::send_and_confirm_transaction(self, transaction)
+    }
+
+    async fn request_airdrop(&self, pubkey: &Pubkey, lamports: u64) -> ClientResult<Signature> {
+        BlockingRpcClient::request_airdrop(self, pubkey, lamports)
+    }
+
+    async fn confirm_transaction(&self, signature: &Signature) -> ClientResult<bool> {
+        BlockingRpcClient::confirm_transaction(self, signature)
+    }
+
+    async fn get_signature_slot(&self, signature: &Signature) -> ClientResult<Option<u64>> {
+        let statuses = BlockingRpcClient::get_signature_statuses(self, &[*signature])?;
+        Ok(statuses.value.into_iter().flatten().next().map(|status| status.slot))
+    }
+}
+
+#[cfg(test)]
+pub use mock::{MockRpc, OfflineRpc};
+
+#[cfg(test)]
+mod mock {
+    use super::*;
+    use solana_client::rpc_request::RpcError;
+    use solana_client::rpc_response::RpcResponseContext;
+    use solana_sdk::rent::Rent;
+    use solana_sdk::transaction::TransactionError;
+    use std::collections::{HashMap, HashSet, VecDeque};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// An in-memory cluster for tests. It records every transaction sent, returns `send_results`
+    /// in order and succeeds once they run out, and hands out a new blockhash on each request.
+    #[derive(Default)]
+    pub struct MockRpc {
+        pub accounts: HashMap<Pubkey, Account>,
+        /// Accounts whose lookup fails like a timed out request, rather than as not found.
+        pub unreachable: HashSet<Pubkey>,
+        pub slot: u64,
+        /// Error every simulation fails with, `None` to simulate successfully.
+        pub simulation_error: Option<TransactionError>,
+        pub send_results: Mutex<VecDeque<ClientResult<Signature>>>,
+        /// Whether `send_and_confirm_transaction` never returns, like a stalled cluster.
+        pub stall_sends: bool,
+        /// Results `confirm_transaction` returns in order, confirming once they run out.
+        pub confirm_results: Mutex<VecDeque<ClientResult<bool>>>,
+        /// Results `get_latest_blockhash` returns in order, a new blockhash once they run out.
+        pub blockhash_results: Mutex<VecDeque<ClientResult<Hash>>>,
+        /// Transactions passed to `send_and_confirm_transaction`, in order.
+        pub sent: Mutex<Vec<Transaction>>,
+        /// Accounts passed to `request_airdrop`, in order.
+        pub airdrops: Mutex<Vec<Pubkey>>,
+        /// Whether `request_airdrop` fails, like a faucet that is down.
+        pub fail_airdrops: bool,
+        pub blockhashes_fetched: AtomicUsize,
+    }
+
+    impl MockRpc {
+        pub fn sent(&self) -> Vec<Transaction> {
+            self.sent.lock().unwrap().clone()
+        }
+
+        pub fn airdrops(&self) -> Vec<Pubkey> {
+            self.airdrops.lock().unwrap().clone()
+        }
+    }
+
+    impl SolanaRpc for MockRpc {
+        async fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account> {
+            if self.unreachable.contains(pubkey) {
+                return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "request timed out").into());
+            }
+            self.accounts
+                .get(pubkey)
+                .cloned()
+                .ok_or_else(|| RpcError::ForUser(format!("AccountNotFound: pubkey={}", pubkey)).into())
+        }
+
+        async fn get_balance(&self, pubkey: &Pubkey) -> ClientResult<u64> {
+            Ok(self.accounts.get(pubkey).map_or(0, |account| account.lamports))
+        }
+
+        async fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ClientResult<u64> {
+            Ok(Rent::default().minimum_balance(data_len))
+        }
+
+        async fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+            self.blockhashes_fetched.fetch_add(1, Ordering::Relaxed);
+            match self.blockhash_results.lock().unwrap().pop_front() {
+                Some(result) => result,
+                None => Ok(Hash::new_unique()),
+            }
+        }
+
+        async fn get_genesis_hash(&self) -> ClientResult<Hash> {
+            Ok(Hash::default())
+        }
+
+        async fn get_slot(&self) -> ClientResult<u64> {
+            Ok(self.slot)
+        }
+
+        async fn simulate_transaction(&self, _transaction: &Transaction) -> ClientResult<Response<RpcSimulateTransactionResult>> {
+            Ok(Response {
+                context: RpcResponseContext { slot: self.slot, api_version: None },
+                value: RpcSimulateTransactionResult {
+                    err: self.simulation_error.clone(),
+                    logs: Some(Vec::new()),
+                    accounts: None,
+                    units_consumed: Some(0),
+                    return_data: None,
+                    inner_instructions: None,
+                    replacement_blockhash: None,
+                },
+            })
+        }
+
+        async fn send_and_confirm_transaction(&self, transaction: &Transaction) -> ClientResult<Signature> {
+            self.sent.lock().unwrap().push(transaction.clone());
+            if self.stall_sends {
+                std::future::pending::<()>().await;
+            }
+            self.send_results.lock().unwrap().pop_front().unwrap_or(Ok(transaction.signatures[0]))
+        }
+
+        async fn request_airdrop(&self, pubkey: &Pubkey, _lamports: u64) -> ClientResult<Signature> {
+            self.airdrops.lock().unwrap().push(*pubkey);
+            if self.fail_airdrops {
+                return Err(RpcError::ForUser("airdrop request failed".to_string()).into());
+            }
+            Ok(Signature::new_unique())
+        }
+
+        async fn confirm_transaction(&self, _signature: &Signature) -> ClientResult<bool> {
+            self.confirm_results.lock().unwrap().pop_front().unwrap_or(Ok(true))
+        }
+
+        async fn get_signature_slot(&self, _signature: &Signature) -> ClientResult<Option<u64>> {
+            Ok(Some(self.slot))
+        }
+    }
+
+    /// A client for code paths that must not touch the network: every call panics.
+    pub struct OfflineRpc;
+
+    impl SolanaRpc for OfflineRpc {
+        async fn get_account(&self, _pubkey: &Pubkey) -> ClientResult<Account> {
+            panic!("get_account called on an offline client")
+        }
+
+        async fn get_balance(&self, _pubkey: &Pubkey) -> ClientResult<u64> {
+            panic!("get_balance called on an offline client")
+        }
+
+        async fn get_minimum_balance_for_rent_exemption(&self, _data_len: usize) -> ClientResult<u64> {
+            panic!("get_minimum_balance_for_rent_exemption called on an offline client")
+        }
+
+        async fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+            panic!("get_latest_blockhash called on an offline client")
+        }
+
+        async fn get_genesis_hash(&self) -> ClientResult<Hash> {
+            panic!("get_genesis_hash called on an offline client")
+        }
+
+        async fn get_slot(&self) -> ClientResult<u64> {
+            panic!("get_slot called on an offline client")
+        }
+
+        async fn simulate_transaction(&self, _transaction: &Transaction) -> ClientResult<Response<RpcSimulateTransactionResult>> {
+            panic!("simulate_transaction called on an offline client")
+        }
+
+        async fn send_and_confirm_transaction(&self, _transaction: &Transaction) -> ClientResult<Signature> {
+            panic!("send_and_confirm_transaction called on an offline client")
+        }
+
+        async fn request_airdrop(&self, _pubkey: &Pubkey, _lamports: u64) -> ClientResult<Signature> {
+            panic!("request_airdrop called on an offline client")
+        }
+
+        async fn confirm_transaction(&self, _signature: &Signature) -> ClientResult<bool> {
+            panic!("confirm_transaction called on an offline client")
+        }
+
+        async fn get_signature_slot(&self, _signature: &Signature) -> ClientResult<Option<u64>> {
+            panic!("get_signature_slot called on an offline client")
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! A fixed signing vector for checking other implementations of the signer against this one.
+//! Hex values are lowercase without a `0x` prefix, and Solana public keys are base58.
+//!
+//! `create_and_sign_package(PROGRAM_ID, PAYER, CHAIN_ID, DATA, EXPIRY_UNIX, TAG, HASH_ALGO,
+//! SECRET_KEY)` hashes `DOMAIN_SEPARATOR || PROGRAM_ID || PAYER || DATA || EXPIRY_UNIX || CHAIN_ID
+//! || TAG` (expiry and chain id little-endian) to `MESSAGE_HASH`, and signs it with RFC 6979 deterministic nonces,
+//! so the signature is always `SIGNATURE` with `RECOVERY_ID`. Recovering the signer from them gives
+//! `PUBLIC_KEY`.
+
+/// secp256k1 secret key, 32 bytes.
+pub const SECRET_KEY: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+
+/// Program the package is signed for, the signer's default `PROGRAM_ID`.
+pub const PROGRAM_ID: &str = "4muvyr2m6AFioKUjuyMXyLTYztykfXTTUemg4ZnD38bi";
+
+/// Payer the package is bound to, the key with all 32 bytes set to 0x22.
+pub const PAYER: &str = "3JF3sEqM796hk5WFqA6EtmEwJQ9quALszsfJyvXNQKy3";
+
+/// Chain id the package is signed for, all 8 bytes set to 0x44.
+pub const CHAIN_ID: u64 = 0x4444_4444_4444_4444;
+
+/// Signed data, 32 bytes.
+pub const DATA: &str = "3333333333333333333333333333333333333333333333333333333333333333";
+
+/// Expiry signed together with the data.
+pub const EXPIRY_UNIX: i64 = 1_700_000_000;
+
+/// Tag signed with the data, all 8 bytes set to 0x55.
+pub const TAG: &str = "5555555555555555";
+
+/// `HASH_ALGO_KECCAK256`.
+pub const HASH_ALGO: u8 = 0;
+
+/// Keccak-256 hash of the signed message, 32 bytes.
+pub const MESSAGE_HASH: &str = "29cb42749c2e9d0fa3774f1e56b2c7ea64ee3ba303b3fd561b42b0ad3e118710";
+
+/// Signature `r || s`, 64 bytes.
+pub const SIGNATURE: &str = "9c64738391f07b3498042d28a716b962e9a295bedfd78783d47b50c285ee96eb59bfffd593dfd5bce23b80d863b9d9e21169cba969d80f6ae86eac50e17d40d8";
+
+/// Recovery id of `SIGNATURE`.
+pub const RECOVERY_ID: u8 = 1;
+
+/// Uncompressed public key of `SECRET_KEY`, `0x04 || x || y`, 65 bytes.
+pub const PUBLIC_KEY: &str = "044f355bdcb7cc0af728ef3cceb9615d90684bb5b2ca5f859ab0f0b704075871aa385b6b1b8ead809ca67454d9683fcf2ba03456d6fe2c4abe2b07f0fbdbb2f1c1";
+
+/// `eip712::domain_separator(DOMAIN_NAME, DOMAIN_VERSION, CHAIN_ID)`, 32 bytes.
+pub const TYPED_DATA_DOMAIN_SEPARATOR: &str = "15a2560c5fb748b4927dd66605e0bdb3e153da314d25c91f5cfca6d298792c31";
+
+/// `eip712::attestation_hash(DATA)`, 32 bytes.
+pub const TYPED_DATA_STRUCT_HASH: &str = "6c718cf62a1d9003580686394865b52aeb8eab6c36203e5b7f71d321a80ef4fa";
+
+/// `eip712::typed_data_digest` of the domain separator and struct hash, 32 bytes.
+pub const TYPED_DATA_DIGEST: &str = "e8ae7e4e3fd4f0106d85623a865a13dafcfb40ec43cd8f776c5dc9d42033b33e";
+
+/// `sign_typed_data(TYPED_DATA_DIGEST, SECRET_KEY)` signature `r || s`, 64 bytes, with recovery
+/// id 0.
+pub const TYPED_DATA_SIGNATURE: &str = "198f35d14f3a122c8afd3d37ec9536f768a6306f372140920ce22f83ed9573387002e71c7504a2c9e4a6846dfeb46126f5baf782b11b69071cd99d7e599363b7";
+
+/// Ethereum address of `SECRET_KEY`, 20 bytes.
+pub const ETH_ADDRESS: &str = "19e7e376e7c213b7e7e7e46cc70a5dd086daff2a";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_and_sign_package, eip712, sign_typed_data};
+    use shared_types::signed_message_hash;
+    use solana_program::pubkey::Pubkey;
+    use std::str::FromStr;
+
+    fn bytes<const N: usize>(hex_value: &str) -> [u8; N] {
+        hex::decode(hex_value).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn package_matches_the_vector() {
+        let program_id = Pubkey::from_str(PROGRAM_ID).unwrap();
+        let payer = Pubkey::from_str(PAYER).unwrap();
+        assert_eq!(payer, Pubkey::new_from_array([0x22; 32]));
+
+        let package =
+            create_and_sign_package(&program_id, &payer, CHAIN_ID, bytes(DATA), EXPIRY_UNIX, bytes(TAG), HASH_ALGO, &bytes(SECRET_KEY)).unwrap();
+
+        let message_hash = signed_message_hash(&program_id, &payer, CHAIN_ID, &package.data, EXPIRY_UNIX, &package.tag, HASH_ALGO).unwrap();
+        assert_eq!(hex::encode(message_hash), MESSAGE_HASH);
+        assert_eq!(hex::encode(package.verifier_signature), SIGNATURE);
+        assert_eq!(package.recovery_id, RECOVERY_ID);
+        assert_eq!(hex::encode(package.public_key), PUBLIC_KEY);
+        assert_eq!(hex::encode(package.data), DATA);
+        assert_eq!((package.expiry_unix, package.hash_algo), (EXPIRY_UNIX, HASH_ALGO));
+    }
+
+    #[test]
+    fn typed_data_matches_the_vector() {
+        let domain_separator = eip712::domain_separator(eip712::DOMAIN_NAME, eip712::DOMAIN_VERSION, CHAIN_ID);
+        let struct_hash = eip712::attestation_hash(&bytes(DATA));
+        let digest = eip712::typed_data_digest(&domain_separator, &struct_hash);
+        assert_eq!(hex::encode(domain_separator), TYPED_DATA_DOMAIN_SEPARATOR);
+        assert_eq!(hex::encode(struct_hash), TYPED_DATA_STRUCT_HASH);
+        assert_eq!(hex::encode(digest), TYPED_DATA_DIGEST);
+
+        let (signature, recovery_id, eth_address) = sign_typed_data(&digest, &bytes(SECRET_KEY)).unwrap();
+        assert_eq!(hex::encode(signature), TYPED_DATA_SIGNATURE);
+        assert_eq!(recovery_id, 0);
+        assert_eq!(hex::encode(eth_address), ETH_ADDRESS);
+    }
+}
@@ -0,0 +1,16 @@
+#![no_main]
+
+// Feeds arbitrary instruction data through the program's entrypoint. Any outcome other than a
+// returned `ProgramResult` (a panic, an abort, an out of bounds slice) is a bug.
+
+use borsh::BorshDeserialize;
+use libfuzzer_sys::fuzz_target;
+use shared_types::ProgramInstruction;
+use solana_program::pubkey::Pubkey;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ProgramInstruction::try_from_slice(data);
+
+    let program_id = Pubkey::new_unique();
+    let _ = signature_verify::process_instruction(&program_id, &[], data);
+});
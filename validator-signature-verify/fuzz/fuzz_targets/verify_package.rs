@@ -0,0 +1,17 @@
+#![no_main]
+
+// Runs the signature checks shared with the program on arbitrary packages, covering the recovery
+// id, low-S and recovery paths that `instruction` can't reach without accounts and a clock.
+
+use borsh::BorshDeserialize;
+use libfuzzer_sys::fuzz_target;
+use shared_types::{verify_package_locally, SignaturePackage};
+use solana_program::pubkey::Pubkey;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(package) = SignaturePackage::try_from_slice(data) else {
+        return;
+    };
+
+    let _ = verify_package_locally(&Pubkey::default(), &Pubkey::default(), 0, &package, 0);
+});
@@ -0,0 +1,346 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::{rent::Rent, Sysvar};
+use solana_program::{msg, system_program};
+use crate::state::{AuditLog, Config, DataRule, Registry, AUDIT_SEED, CONFIG_SEED, REGISTRY_SEED};
+use crate::{check_pda, create_pda_account, ProgramInstruction, VerifyError};
+use shared_types::ALL_MODES_ENABLED;
+
+/// Creates the config, registry and audit PDAs, making the signer the admin and storing the
+/// `chain_id` packages must be signed for. Only the program's upgrade authority can send it, so
+/// nobody watching a deployment can take the admin role by initializing the config first.
+///
+/// Accounts:
+/// 0. `[signer, writable]` Admin, the program's upgrade authority, funds the accounts.
+/// 1. `[writable]` Config PDA derived from `[CONFIG_SEED]`.
+/// 2. `[writable]` Registry PDA derived from `[REGISTRY_SEED]`.
+/// 3. `[]` System program.
+/// 4. `[writable]` Audit PDA derived from `[AUDIT_SEED]`.
+/// 5. `[]` The program's ProgramData account, derived from `[program_id]` by the upgradeable loader.
+pub fn initialize_config(program_id: &Pubkey, accounts: &[AccountInfo], chain_id: u64) -> ProgramResult {
+    msg!("Initializing config");
+
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let registry_account = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let audit_account = next_account_info(account_info_iter)?;
+    let program_data_account = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        msg!("Admin {} must sign to initialize the config", admin.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_upgrade_authority(program_id, program_data_account, admin.key)?;
+
+    let config_bump = check_pda(program_id, config_account, &[CONFIG_SEED])?;
+    let registry_bump = check_pda(program_id, registry_account, &[REGISTRY_SEED])?;
+    let audit_bump = check_pda(program_id, audit_account, &[AUDIT_SEED])?;
+    if config_account.owner != &system_program::ID
+        || registry_account.owner != &system_program::ID
+        || audit_account.owner != &system_program::ID
+    {
+        msg!("Config is already initialized");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_pda_account(program_id, admin, config_account, system_program_account, Config::LEN, &[CONFIG_SEED, &[config_bump]])?;
+    create_pda_account(program_id, admin, registry_account, system_program_account, Registry::LEN, &[REGISTRY_SEED, &[registry_bump]])?;
+    create_pda_account(program_id, admin, audit_account, system_program_account, AuditLog::LEN, &[AUDIT_SEED, &[audit_bump]])?;
+
+    let config = Config {
+        admin: *admin.key,
+        enabled_modes: ALL_MODES_ENABLED,
+        chain_id,
+        ..Config::default()
+    };
+    config.pack(&mut config_account.try_borrow_mut_data()?)?;
+    Registry::default().serialize(&mut &mut registry_account.try_borrow_mut_data()?[..])?;
+    AuditLog::default().serialize(&mut &mut audit_account.try_borrow_mut_data()?[..])?;
+
+    msg!("Config initialized with admin {} for chain id {}", admin.key, chain_id);
+
+    Ok(())
+}
+
+/// Adds a signer to the registry.
+///
+/// Accounts:
+/// 0. `[signer]` Admin.
+/// 1. `[]` Config PDA.
+/// 2. `[writable]` Registry PDA.
+pub fn register_signer(program_id: &Pubkey, accounts: &[AccountInfo], pubkey: [u8; 64]) -> ProgramResult {
+    msg!("Registering signer");
+
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let registry_account = next_account_info(account_info_iter)?;
+
+    check_admin(program_id, admin, config_account)?;
+    let mut registry = load_registry(program_id, registry_account)?;
+
+    if registry.contains(&pubkey) {
+        msg!("Signer is already registered");
+        return Err(VerifyError::SignerAlreadyRegistered.into());
+    }
+    if !registry.insert(pubkey) {
+        msg!("Registry is full");
+        return Err(VerifyError::RegistryFull.into());
+    }
+    registry.serialize(&mut &mut registry_account.try_borrow_mut_data()?[..])?;
+
+    msg!("Signer registered, {} signers in registry", registry.count);
+
+    Ok(())
+}
+
+/// Removes a signer from the registry. Takes the same accounts as `register_signer`.
+pub fn revoke_signer(program_id: &Pubkey, accounts: &[AccountInfo], pubkey: [u8; 64]) -> ProgramResult {
+    msg!("Revoking signer");
+
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let registry_account = next_account_info(account_info_iter)?;
+
+    check_admin(program_id, admin, config_account)?;
+    let mut registry = load_registry(program_id, registry_account)?;
+
+    if !registry.remove(&pubkey) {
+        msg!("Signer is not registered");
+        return Err(VerifyError::SignerNotRegistered.into());
+    }
+    registry.serialize(&mut &mut registry_account.try_borrow_mut_data()?[..])?;
+
+    msg!("Signer revoked, {} signers in registry", registry.count);
+
+    Ok(())
+}
+
+/// Sets the config's `enabled_modes` mask.
+///
+/// Accounts:
+/// 0. `[signer]` Admin.
+/// 1. `[writable]` Config PDA.
+pub fn set_enabled_modes(program_id: &Pubkey, accounts: &[AccountInfo], mask: u32) -> ProgramResult {
+    msg!("Setting enabled modes");
+
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    check_admin(program_id, admin, config_account)?;
+    let mut config = Config::unpack(&config_account.try_borrow_data()?)?;
+    config.enabled_modes = mask;
+    store_config(config_account, &config)?;
+
+    msg!("Enabled modes set to {:#034b}", mask);
+
+    Ok(())
+}
+
+/// Sets the rule verified data must satisfy. Data already stored isn't checked again.
+///
+/// Accounts:
+/// 0. `[signer]` Admin.
+/// 1. `[writable]` Config PDA.
+pub fn set_data_rule(program_id: &Pubkey, accounts: &[AccountInfo], rule: DataRule) -> ProgramResult {
+    msg!("Setting data rule");
+
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    check_admin(program_id, admin, config_account)?;
+    let mut config = Config::unpack(&config_account.try_borrow_data()?)?;
+    config.data_rule = rule;
+    store_config(config_account, &config)?;
+
+    msg!("Data rule set to {:?}", rule);
+
+    Ok(())
+}
+
+/// Proposes `new_admin` as the next admin. The admin doesn't change until `new_admin` signs
+/// `accept_admin`, so a typo or a key nobody holds can't lock the admin out.
+///
+/// Accounts:
+/// 0. `[signer]` Admin.
+/// 1. `[writable]` Config PDA.
+pub fn propose_admin(program_id: &Pubkey, accounts: &[AccountInfo], new_admin: Pubkey) -> ProgramResult {
+    msg!("Proposing admin");
+
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    check_admin(program_id, admin, config_account)?;
+    let mut config = Config::unpack(&config_account.try_borrow_data()?)?;
+    config.pending_admin = Some(new_admin);
+    store_config(config_account, &config)?;
+
+    msg!("Proposed {} as admin", new_admin);
+
+    Ok(())
+}
+
+/// Makes the pending admin the admin and clears the proposal.
+///
+/// Accounts:
+/// 0. `[signer]` Pending admin.
+/// 1. `[writable]` Config PDA.
+pub fn accept_admin(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    msg!("Accepting admin");
+
+    let account_info_iter = &mut accounts.iter();
+    let new_admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    if !new_admin.is_signer {
+        msg!("Pending admin {} must sign", new_admin.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut config = load_config(program_id, config_account)?;
+    match config.pending_admin {
+        None => {
+            msg!("No admin has been proposed");
+            return Err(VerifyError::NoPendingAdmin.into());
+        }
+        Some(pending_admin) if pending_admin != *new_admin.key => {
+            msg!("{} is not the pending admin", new_admin.key);
+            return Err(VerifyError::Unauthorized.into());
+        }
+        Some(_) => {}
+    }
+    config.admin = *new_admin.key;
+    config.pending_admin = None;
+    store_config(config_account, &config)?;
+
+    msg!("Admin is now {}", new_admin.key);
+
+    Ok(())
+}
+
+/// Rejects `instruction` with `VerifyError::ModeDisabled` if its bit is cleared in the config's
+/// `enabled_modes`. The config PDA is looked up among the instruction's accounts, which every
+/// toggleable instruction already passes for the verification count.
+pub(crate) fn check_mode_enabled(program_id: &Pubkey, accounts: &[AccountInfo], instruction: &ProgramInstruction) -> ProgramResult {
+    if !instruction.is_toggleable() {
+        return Ok(());
+    }
+
+    let (config_pda, _bump) = Pubkey::find_program_address(&[CONFIG_SEED], program_id);
+    let Some(config_account) = accounts.iter().find(|account| *account.key == config_pda) else {
+        msg!("Config account {} is missing", config_pda);
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    if config_account.owner != program_id {
+        msg!("Config is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    let config = Config::unpack(&config_account.try_borrow_data()?)?;
+    if config.enabled_modes & instruction.mode_bit() == 0 {
+        msg!("Instruction {} is disabled", instruction.discriminant());
+        return Err(VerifyError::ModeDisabled.into());
+    }
+
+    Ok(())
+}
+
+/// Loads the config after checking it is the program's config PDA.
+pub(crate) fn load_config(program_id: &Pubkey, config_account: &AccountInfo) -> Result<Config, ProgramError> {
+    check_pda(program_id, config_account, &[CONFIG_SEED])?;
+    if config_account.owner != program_id {
+        msg!("Config is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    Config::unpack(&config_account.try_borrow_data()?)
+}
+
+/// Writes `config` in the current layout. A config created by an older build is smaller and grows
+/// to `Config::LEN` first, which fails with `AccountNotRentExempt` unless it already holds the rent
+/// for the new size: the admin instructions have no system program to top it up with, so the
+/// difference has to be transferred to the config PDA first, for example in the same transaction.
+/// Verifications top it up from the payer before calling this.
+pub(crate) fn store_config(config_account: &AccountInfo, config: &Config) -> ProgramResult {
+    if config_account.data_len() < Config::LEN {
+        let rent = Rent::get()?.minimum_balance(Config::LEN);
+        if config_account.lamports() < rent {
+            msg!("Config {} needs {} lamports to grow to the current layout, it has {}", config_account.key, rent, config_account.lamports());
+            return Err(ProgramError::AccountNotRentExempt);
+        }
+        config_account.realloc(Config::LEN, true)?;
+    }
+    config.pack(&mut config_account.try_borrow_mut_data()?)
+}
+
+/// Loads the registry after checking it is the program's registry PDA.
+pub(crate) fn load_registry(program_id: &Pubkey, registry_account: &AccountInfo) -> Result<Registry, ProgramError> {
+    check_pda(program_id, registry_account, &[REGISTRY_SEED])?;
+    if registry_account.owner != program_id {
+        msg!("Registry is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    Ok(Registry::deserialize(&mut &registry_account.try_borrow_data()?[..])?)
+}
+
+/// Fails with `Unauthorized` unless `authority` is the upgrade authority stored in the program's
+/// ProgramData account. A program deployed as immutable has none, and its config can't be
+/// initialized.
+fn check_upgrade_authority(program_id: &Pubkey, program_data_account: &AccountInfo, authority: &Pubkey) -> ProgramResult {
+    let program_data = bpf_loader_upgradeable::get_program_data_address(program_id);
+    if *program_data_account.key != program_data {
+        msg!("Account {} is not the program data account {}", program_data_account.key, program_data);
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if program_data_account.owner != &bpf_loader_upgradeable::ID {
+        msg!("Program data account {} is not owned by the upgradeable loader", program_data_account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Only the metadata is decoded, the program's bytes follow it
+    let data = program_data_account.try_borrow_data()?;
+    let metadata = data.get(..UpgradeableLoaderState::size_of_programdata_metadata()).ok_or(ProgramError::InvalidAccountData)?;
+    match bincode::deserialize(metadata) {
+        Ok(UpgradeableLoaderState::ProgramData { upgrade_authority_address: Some(upgrade_authority), .. }) if upgrade_authority == *authority => Ok(()),
+        Ok(UpgradeableLoaderState::ProgramData { upgrade_authority_address, .. }) => {
+            msg!("{} is not the program's upgrade authority {:?}", authority, upgrade_authority_address);
+            Err(VerifyError::Unauthorized.into())
+        }
+        _ => {
+            msg!("Unable to decode program data account {}", program_data_account.key);
+            Err(ProgramError::InvalidAccountData)
+        }
+    }
+}
+
+fn check_admin(program_id: &Pubkey, admin: &AccountInfo, config_account: &AccountInfo) -> ProgramResult {
+    if !admin.is_signer {
+        msg!("Admin {} must sign", admin.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_pda(program_id, config_account, &[CONFIG_SEED])?;
+    if config_account.owner != program_id {
+        msg!("Config is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    let config = Config::unpack(&config_account.try_borrow_data()?)?;
+    if config.admin != *admin.key {
+        msg!("{} is not the admin", admin.key);
+        return Err(VerifyError::Unauthorized.into());
+    }
+
+    Ok(())
+}
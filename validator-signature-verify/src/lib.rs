@@ -1,67 +1,2482 @@
+pub mod admin;
+pub mod post_verify;
+pub mod state;
+
 use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program::{invoke, invoke_signed, set_return_data};
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::log::{sol_log_compute_units, sol_log_data};
 use solana_program::program_error::ProgramError;
-use solana_program::secp256k1_recover::{secp256k1_recover, Secp256k1Pubkey};
-use solana_program::{account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, keccak, msg, pubkey::Pubkey};
-
-#[derive(BorshDeserialize, BorshSerialize)]
-pub struct SignaturePackage {
-    pub verifier_signature: [u8; 64],
-    pub recovery_id: u8,
-    pub public_key: [u8; 65],
-    pub data: [u8; 32],
-}
+use solana_program::secp256k1_recover::{secp256k1_recover, Secp256k1Pubkey, Secp256k1RecoverError};
+use solana_program::sysvar::{clock::Clock, rent::Rent, Sysvar};
+use solana_program::{account_info::{next_account_info, AccountInfo}, entrypoint::ProgramResult, keccak, msg, pubkey::Pubkey, system_instruction, system_program};
+use crate::post_verify::{EmitVerificationEvent, PostVerify};
+use crate::state::{
+    AuditEntry, AuditLog, Committee, Config, NonceState, ProgramState, AUDIT_SEED, COMMITTEE_VERSION, NONCE_SEED,
+    STATE_SEED,
+};
 
-entrypoint!(process_instruction);
+// The instruction types and wire constants live in shared-types so clients can't drift from them
+pub use shared_types::{
+    pubkey_to_eth_address, ProgramInstruction, SignaturePackage, SignaturePackageCompressed, SignaturePackageV2,
+    SignaturePackageVar, VerifyError, DOMAIN_SEPARATOR, HASH_ALGO_KECCAK256, HASH_ALGO_SHA256, MAX_BATCH_SIZE,
+    MAX_MESSAGE_LEN,
+};
+use shared_types::{
+    check_not_degenerate, check_valid_point, ct_eq, is_low_s, nonce_message_hash, record_message_hash, slot_bound_message_hash, AttestationRecord, MAX_RECOVERY_ID,
+};
 
-#[derive(BorshSerialize, BorshDeserialize)]
-pub enum ProgramInstruction {
-    VerifySig(SignaturePackage)
-}
+/// Length of the return data set after a successful verification: the 64-byte recovered public
+/// key, its 20-byte Ethereum address and the 8-byte total verification count.
+pub const SIGNER_RETURN_DATA_LEN: usize = 64 + 20 + 8;
+
+/// Recovery ids `VerifySigAnyRecovery` tries, 0 and 1. Ids 2 and 3 only occur when `r` overflows
+/// the curve order, which practically never happens, so they aren't worth a recovery each.
+pub const ANY_RECOVERY_ID_ATTEMPTS: u8 = 2;
+
+/// Tag stored for instructions whose signed message has none.
+const NO_TAG: [u8; 8] = [0u8; 8];
+
+// Left out of host unit tests and of crates linking the program as a library, such as the fuzz
+// targets, which would otherwise define a second `entrypoint` symbol
+#[cfg(not(any(test, feature = "no-entrypoint")))]
+solana_program::entrypoint!(process_instruction);
 
 pub fn process_instruction(
-    _program_id: &Pubkey,
-    _accounts: &[AccountInfo],
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    process_instruction_with::<EmitVerificationEvent>(program_id, accounts, instruction_data)
+}
+
+/// Processes an instruction like `process_instruction`, running `P::on_verified` after each
+/// successful verification instead of the default `EmitVerificationEvent`. For programs that
+/// embed this one's verification and add their own logic, from their own entrypoint.
+pub fn process_instruction_with<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    let instruction = ProgramInstruction::try_from_slice(instruction_data)?;
+    let instruction = ProgramInstruction::try_from_slice(instruction_data).map_err(|err| {
+        msg!("Unable to decode instruction data: {}", err);
+        VerifyError::MalformedPackage
+    })?;
+    admin::check_mode_enabled(program_id, accounts, &instruction)?;
+
+    // Logging the remaining units around the handler shows its cost in the transaction logs
+    sol_log_compute_units();
+    let result = match instruction {
+        ProgramInstruction::VerifySig(signature_package) => verify_signature_with_recover::<P>(program_id, accounts, &signature_package).map(Some),
+        ProgramInstruction::VerifySigBatch(signature_packages) => verify_signature_batch::<P>(program_id, accounts, &signature_packages),
+        ProgramInstruction::VerifySigFromAccount(signature_package) => verify_signature_from_account::<P>(program_id, accounts, &signature_package).map(Some),
+        ProgramInstruction::InitializeConfig { chain_id } => admin::initialize_config(program_id, accounts, chain_id).map(|()| None),
+        ProgramInstruction::RegisterSigner { pubkey } => admin::register_signer(program_id, accounts, pubkey).map(|()| None),
+        ProgramInstruction::RevokeSigner { pubkey } => admin::revoke_signer(program_id, accounts, pubkey).map(|()| None),
+        ProgramInstruction::VerifySigAgainstRegistry(signature_package) => verify_signature_against_registry::<P>(program_id, accounts, &signature_package).map(Some),
+        ProgramInstruction::VerifyEthSig { signature, recovery_id, eth_address, data, expiry_unix } => {
+            verify_eth_signature::<P>(program_id, accounts, &signature, recovery_id, &eth_address, &data, expiry_unix).map(Some)
+        }
+        ProgramInstruction::VerifySigWithNonce(signature_package) => verify_signature_with_nonce::<P>(program_id, accounts, &signature_package).map(Some),
+        ProgramInstruction::VerifyThreshold { packages, threshold } => verify_threshold::<P>(program_id, accounts, &packages, threshold).map(Some),
+        ProgramInstruction::VerifySigVar(signature_package) => verify_signature_var::<P>(program_id, accounts, &signature_package).map(Some),
+        ProgramInstruction::VerifySigCompressed(signature_package) => verify_signature_compressed::<P>(program_id, accounts, &signature_package).map(Some),
+        ProgramInstruction::InitializeState => initialize_state(program_id, accounts).map(|()| None),
+        ProgramInstruction::VerifyAndForward(signature_package) => verify_and_forward::<P>(program_id, accounts, &signature_package).map(Some),
+        ProgramInstruction::VerifyPersonalSign { signature, recovery_id, eth_address, message } => verify_personal_sign::<P>(program_id, accounts, &signature, recovery_id, &eth_address, &message).map(Some),
+        ProgramInstruction::SetEnabledModes { mask } => admin::set_enabled_modes(program_id, accounts, mask).map(|()| None),
+        ProgramInstruction::VerifyAgainstCommittee(signature_package) => verify_signature_against_committee::<P>(program_id, accounts, &signature_package).map(Some),
+        ProgramInstruction::VerifyPrehashed(signature_package) => verify_prehashed::<P>(program_id, accounts, &signature_package).map(Some),
+        ProgramInstruction::ProposeAdmin { new_admin } => admin::propose_admin(program_id, accounts, new_admin).map(|()| None),
+        ProgramInstruction::AcceptAdmin => admin::accept_admin(program_id, accounts).map(|()| None),
+        ProgramInstruction::VerifySigAnyRecovery { signature, public_key, data, expiry_unix } => {
+            verify_signature_any_recovery::<P>(program_id, accounts, &signature, &public_key, &data, expiry_unix).map(Some)
+        }
+        ProgramInstruction::VerifyTypedData { digest, signature, recovery_id, eth_address } => verify_typed_data::<P>(program_id, accounts, &digest, &signature, recovery_id, &eth_address).map(Some),
+        ProgramInstruction::VerifySlotBound { signature, recovery_id, public_key, data, slot, max_slot_age } => verify_slot_bound::<P>(program_id, accounts, &signature, recovery_id, &public_key, &data, slot, max_slot_age).map(Some),
+        ProgramInstruction::SetDataRule { rule } => admin::set_data_rule(program_id, accounts, rule).map(|()| None),
+        ProgramInstruction::VerifySameSigner { pkg_a, pkg_b } => verify_same_signer::<P>(program_id, accounts, &pkg_a, &pkg_b).map(Some),
+        ProgramInstruction::VerifyAndClose(signature_package) => verify_and_close::<P>(program_id, accounts, &signature_package).map(Some),
+        ProgramInstruction::VerifyRecord { signature, recovery_id, public_key, record } => verify_record::<P>(program_id, accounts, &signature, recovery_id, &public_key, &record).map(Some),
+    };
+    sol_log_compute_units();
 
-    match instruction {
-        ProgramInstruction::VerifySig(signature_package) => verify_signature_with_recover(&signature_package)
+    if let Some(verification) = result? {
+        set_signer_return_data(verification.signer.as_ref(), verification.total_verifications);
     }
+
+    Ok(())
 }
 
-fn verify_signature_with_recover(
-    signature_package: &SignaturePackage
-) -> ProgramResult {
+/// Outcome of a successful verification handler. `process_instruction` turns it into the return
+/// data, and host tests can check the recovered signer and count directly.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Verification {
+    /// The recovered signer, `None` when no single key signed the data.
+    pub signer: Option<Secp256k1Pubkey>,
+    /// Total verifications across all instructions, including this one.
+    pub total_verifications: u64,
+}
+
+// `Secp256k1Pubkey` isn't `Debug`, the signer is shown as its bytes
+impl std::fmt::Debug for Verification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Verification")
+            .field("signer", &self.signer.map(|signer| signer.to_bytes()))
+            .field("total_verifications", &self.total_verifications)
+            .finish()
+    }
+}
+
+fn verify_signature_batch<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_packages: &[SignaturePackage],
+) -> Result<Option<Verification>, ProgramError> {
+    msg!("Attempting to verify batch of {} signatures", signature_packages.len());
+    check_state_accounts(accounts)?;
+
+    if signature_packages.len() > MAX_BATCH_SIZE {
+        msg!("Batch size {} exceeds maximum of {}", signature_packages.len(), MAX_BATCH_SIZE);
+        return Err(VerifyError::BatchTooLarge.into());
+    }
+
+    // The whole instruction fails on the first invalid package, so either every package is accepted or none are.
+    let mut last_verification = None;
+    for (index, signature_package) in signature_packages.iter().enumerate() {
+        match verify_signature_with_recover::<P>(program_id, accounts, signature_package) {
+            Ok(verification) => last_verification = Some(verification),
+            Err(err) => {
+                msg!("Signature verification failed for package at index {}", index);
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(last_verification)
+}
+
+fn verify_signature_with_recover<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_package: &SignaturePackage,
+) -> Result<Verification, ProgramError> {
     msg!("Attempting to verify signature");
+    check_state_accounts(accounts)?;
 
-    // Verify the signature
-    let message_hash = {
-        let mut hasher = keccak::Hasher::default();
-        hasher.hash(&signature_package.data);
-        hasher.result()
+    // Zero signatures and keys can't verify, so they are rejected before paying for recovery
+    check_not_degenerate(&signature_package.verifier_signature, &signature_package.public_key)?;
+
+    // In this example we got the public key from the data we passed to the program, but it would also be possible to load it from an account.
+    let expected_pubkey = parse_pubkey(&signature_package.public_key)?;
+    let recovered_pubkey = recover_pubkey(program_id, payer_key(accounts)?, config_chain_id(program_id, accounts)?, signature_package)?;
+    check_signer(&recovered_pubkey, &expected_pubkey)?;
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, accounts, &recovered_pubkey, &signature_package.data, &signature_package.tag)
+}
+
+fn verify_prehashed<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_package: &SignaturePackage,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify prehashed signature");
+    check_state_accounts(accounts)?;
+
+    // The data is the digest that was signed, so it goes to recovery as is. Nothing binds it to
+    // this program, the payer, an expiry or the tag, that is up to whoever produced the digest,
+    // so the unsigned tag isn't stored.
+    let expected_pubkey = parse_pubkey(&signature_package.public_key)?;
+    let recovered_pubkey = recover_signer(&signature_package.data, signature_package.recovery_id, &signature_package.verifier_signature)?;
+    check_signer(&recovered_pubkey, &expected_pubkey)?;
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, accounts, &recovered_pubkey, &signature_package.data, &NO_TAG)
+}
+
+fn verify_and_forward<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_package: &SignaturePackage,
+) -> Result<Verification, ProgramError> {
+    if accounts.len() < STATE_ACCOUNTS_LEN + 1 {
+        msg!("Expected the state accounts followed by the target program");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let forward_accounts = &accounts[STATE_ACCOUNTS_LEN..];
+
+    let account_info_iter = &mut forward_accounts.iter();
+    let target_program = next_account_info(account_info_iter)?;
+    let target_accounts = account_info_iter.as_slice();
+
+    // Forwarding to the system program could move the payer's lamports, and to ourselves could recurse
+    if target_program.key == &system_program::ID || target_program.key == program_id {
+        msg!("Cannot forward to {}", target_program.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !target_program.executable {
+        msg!("Target {} is not a program", target_program.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // The hook gets the accounts from the payer on, the target program and its accounts included
+    let verification = verify_signature_with_recover::<P>(program_id, accounts, signature_package)?;
+
+    msg!("Forwarding verified data to {}", target_program.key);
+    let instruction = Instruction {
+        program_id: *target_program.key,
+        accounts: target_accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: *account.key,
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data: signature_package.data.to_vec(),
     };
+    invoke(&instruction, forward_accounts)?;
+
+    Ok(verification)
+}
 
-    // Perform the secp256k1 recovery
-    let recovered_pubkey = secp256k1_recover(&message_hash.0, signature_package.recovery_id, &signature_package.verifier_signature).expect("Error recovering public key");
+fn verify_and_close<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_package: &SignaturePackage,
+) -> Result<Verification, ProgramError> {
+    if accounts.len() < STATE_ACCOUNTS_LEN + 1 {
+        msg!("Expected the state accounts followed by the account to close");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let payer = &accounts[0];
+    let close_account = &accounts[STATE_ACCOUNTS_LEN];
 
-    // In this example we got the public key from the data we passed to the program, but it would also be possible to load it from an account.
-    let expected_pubkey = Secp256k1Pubkey::new(&signature_package.public_key[1..65]);
-    // Check if the recovered public key matches the expected one
-    if recovered_pubkey != expected_pubkey {
+    // Only this program can take lamports from an account, and the signature keeps anyone from
+    // closing an account that isn't theirs, including the program's PDAs, which can't sign
+    if close_account.owner != program_id {
+        msg!("Account {} is not owned by this program", close_account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !close_account.is_signer {
+        msg!("Account {} must sign to be closed", close_account.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let verification = verify_signature_with_recover::<P>(program_id, accounts, signature_package)?;
+
+    msg!("Closing {} and refunding {} lamports to the payer", close_account.key, close_account.lamports());
+    close_account.try_borrow_mut_data()?.fill(0);
+    let lamports = close_account.lamports();
+    **payer.try_borrow_mut_lamports()? = payer
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    **close_account.try_borrow_mut_lamports()? = 0;
+
+    Ok(verification)
+}
+
+fn verify_signature_compressed<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_package: &SignaturePackageCompressed,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify signature with compressed public key");
+    check_state_accounts(accounts)?;
+
+    if signature_package.public_key[0] != 0x02 && signature_package.public_key[0] != 0x03 {
+        msg!("Public key has prefix {:#04x}, expected 0x02 or 0x03 for a compressed key", signature_package.public_key[0]);
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    check_expiry(signature_package.expiry_unix)?;
+    let message_hash = signed_message_hash(
+        program_id,
+        payer_key(accounts)?,
+        config_chain_id(program_id, accounts)?,
+        &signature_package.data,
+        signature_package.expiry_unix,
+        &signature_package.tag,
+        signature_package.hash_algo,
+    )?;
+    let recovered_pubkey = recover_signer(&message_hash, signature_package.recovery_id, &signature_package.verifier_signature)?;
+
+    // Decompressing the expected key on-chain is expensive, compressing the recovered key is just
+    // a parity check, so compare in compressed form
+    if !ct_eq(&compress_pubkey(&recovered_pubkey), &signature_package.public_key) {
         msg!("Signature verification failed");
-        return Err(ProgramError::MissingRequiredSignature.into());
+        return Err(VerifyError::PublicKeyMismatch.into());
+    }
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, accounts, &recovered_pubkey, &signature_package.data, &signature_package.tag)
+}
+
+fn verify_signature_from_account<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_package: &SignaturePackage,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify signature against public key account");
+
+    let account_info_iter = &mut accounts.iter();
+    let pubkey_account = next_account_info(account_info_iter)?;
+    let state_accounts = account_info_iter.as_slice();
+    check_state_accounts(state_accounts)?;
+
+    // Only trust a key stored in an account this program owns, otherwise the caller could supply any key.
+    if pubkey_account.owner != program_id {
+        msg!("Public key account {} is not owned by this program", pubkey_account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let expected_pubkey = {
+        let data = pubkey_account.try_borrow_data()?;
+        if data.len() != 64 && data.len() != 65 {
+            msg!("Public key account has invalid data length {}", data.len());
+            return Err(ProgramError::InvalidAccountData);
+        }
+        parse_pubkey(&data)?
+    };
+
+    let recovered_pubkey = recover_pubkey(program_id, payer_key(state_accounts)?, config_chain_id(program_id, state_accounts)?, signature_package)?;
+    check_signer(&recovered_pubkey, &expected_pubkey)?;
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, state_accounts, &recovered_pubkey, &signature_package.data, &signature_package.tag)
+}
+
+fn verify_signature_against_registry<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_package: &SignaturePackage,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify signature against registry");
+
+    let account_info_iter = &mut accounts.iter();
+    let registry_account = next_account_info(account_info_iter)?;
+    let registry = admin::load_registry(program_id, registry_account)?;
+    let state_accounts = account_info_iter.as_slice();
+    check_state_accounts(state_accounts)?;
+
+    let recovered_pubkey = recover_pubkey(program_id, payer_key(state_accounts)?, config_chain_id(program_id, state_accounts)?, signature_package)?;
+    if !registry.contains(&recovered_pubkey.to_bytes()) {
+        msg!("Recovered public key is not a registered signer");
+        return Err(VerifyError::SignerNotRegistered.into());
+    }
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, state_accounts, &recovered_pubkey, &signature_package.data, &signature_package.tag)
+}
+
+fn verify_signature_against_committee<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_package: &SignaturePackage,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify signature against committee");
+
+    let account_info_iter = &mut accounts.iter();
+    let committee_account = next_account_info(account_info_iter)?;
+    let state_accounts = account_info_iter.as_slice();
+    check_state_accounts(state_accounts)?;
+
+    // Only trust a committee this program owns, otherwise the caller could supply any members.
+    if committee_account.owner != program_id {
+        msg!("Committee account {} is not owned by this program", committee_account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // The account may be larger than the committee, so trailing bytes are ignored
+    let committee = Committee::deserialize(&mut &committee_account.try_borrow_data()?[..]).map_err(|err| {
+        msg!("Unable to decode committee account: {}", err);
+        ProgramError::InvalidAccountData
+    })?;
+    if committee.version != COMMITTEE_VERSION {
+        msg!("Unsupported committee version {}", committee.version);
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let recovered_pubkey = recover_pubkey(program_id, payer_key(state_accounts)?, config_chain_id(program_id, state_accounts)?, signature_package)?;
+    if !committee.contains(&recovered_pubkey.to_bytes()) {
+        msg!("Recovered public key is not a committee member");
+        return Err(VerifyError::NotCommitteeMember.into());
+    }
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, state_accounts, &recovered_pubkey, &signature_package.data, &signature_package.tag)
+}
+
+fn verify_eth_signature<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature: &[u8; 64],
+    recovery_id: u8,
+    eth_address: &[u8; 20],
+    data: &[u8; 32],
+    expiry_unix: i64,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify signature against Ethereum address");
+    check_state_accounts(accounts)?;
+    check_expiry(expiry_unix)?;
+
+    let chain_id = config_chain_id(program_id, accounts)?;
+    let message_hash = signed_message_hash(program_id, payer_key(accounts)?, chain_id, data, expiry_unix, &NO_TAG, HASH_ALGO_KECCAK256)?;
+    let recovered_pubkey = recover_signer(&message_hash, recovery_id, signature)?;
+
+    if pubkey_to_eth_address(&recovered_pubkey) != *eth_address {
+        msg!("Recovered Ethereum address does not match");
+        return Err(VerifyError::EthAddressMismatch.into());
+    }
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, accounts, &recovered_pubkey, data, &NO_TAG)
+}
+
+fn verify_signature_any_recovery<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature: &[u8; 64],
+    public_key: &[u8; 65],
+    data: &[u8; 32],
+    expiry_unix: i64,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify signature without a recovery id");
+    check_state_accounts(accounts)?;
+    check_expiry(expiry_unix)?;
+
+    let expected_pubkey = parse_pubkey(public_key)?;
+    let chain_id = config_chain_id(program_id, accounts)?;
+    let message_hash = signed_message_hash(program_id, payer_key(accounts)?, chain_id, data, expiry_unix, &NO_TAG, HASH_ALGO_KECCAK256)?;
+
+    // Each attempt is a full recovery, so the attempts are bounded. If neither recovers the
+    // expected key, the error is a mismatch when some key was recovered, and otherwise the first
+    // attempt's error, such as a malleable signature.
+    let mut recovered_pubkey = None;
+    let mut last_error = None;
+    for recovery_id in 0..ANY_RECOVERY_ID_ATTEMPTS {
+        match recover_signer(&message_hash, recovery_id, signature) {
+            Ok(pubkey) if ct_eq(&pubkey.to_bytes(), &expected_pubkey.to_bytes()) => {
+                msg!("Recovered the expected key with recovery id {}", recovery_id);
+                recovered_pubkey = Some(pubkey);
+                break;
+            }
+            Ok(_) => last_error = Some(VerifyError::PublicKeyMismatch.into()),
+            Err(err) => last_error = last_error.or(Some(err)),
+        }
+    }
+    let Some(recovered_pubkey) = recovered_pubkey else {
+        msg!("No recovery id recovers the expected public key");
+        return Err(last_error.unwrap_or_else(|| VerifyError::PublicKeyMismatch.into()));
+    };
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, accounts, &recovered_pubkey, data, &NO_TAG)
+}
+
+fn verify_same_signer<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pkg_a: &SignaturePackage,
+    pkg_b: &SignaturePackage,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify two signatures from the same key");
+    check_state_accounts(accounts)?;
+
+    // The same signature twice proves nothing beyond the first one
+    if pkg_a.verifier_signature == pkg_b.verifier_signature {
+        msg!("Both packages carry the same signature");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Only the recovered keys are compared, the embedded public keys aren't trusted for either
+    let payer = payer_key(accounts)?;
+    let chain_id = config_chain_id(program_id, accounts)?;
+    let recovered_a = recover_pubkey(program_id, payer, chain_id, pkg_a)?;
+    let recovered_b = recover_pubkey(program_id, payer, chain_id, pkg_b)?;
+    if !ct_eq(&recovered_a.to_bytes(), &recovered_b.to_bytes()) {
+        msg!("The packages were signed by different keys");
+        return Err(VerifyError::PublicKeyMismatch.into());
+    }
+
+    msg!("Both signatures are from the same key!");
+    finish_verification::<P>(program_id, accounts, &recovered_b, &pkg_b.data, &pkg_b.tag)
+}
+
+fn verify_typed_data<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    digest: &[u8; 32],
+    signature: &[u8; 64],
+    recovery_id: u8,
+    eth_address: &[u8; 20],
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify EIP-712 typed data signature");
+    check_state_accounts(accounts)?;
+
+    // The digest already commits to the domain and the struct, so it is recovered from as is
+    let recovered_pubkey = recover_signer(digest, recovery_id, signature)?;
+
+    if pubkey_to_eth_address(&recovered_pubkey) != *eth_address {
+        msg!("Recovered Ethereum address does not match");
+        return Err(VerifyError::EthAddressMismatch.into());
+    }
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, accounts, &recovered_pubkey, digest, &NO_TAG)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn verify_slot_bound<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature: &[u8; 64],
+    recovery_id: u8,
+    public_key: &[u8; 65],
+    data: &[u8; 32],
+    slot: u64,
+    max_slot_age: u64,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify signature bound to slot {}", slot);
+    check_state_accounts(accounts)?;
+
+    // The slot is checked first, a stale attestation isn't worth a recovery
+    check_slot_age(slot, max_slot_age)?;
+
+    let expected_pubkey = parse_pubkey(public_key)?;
+    let chain_id = config_chain_id(program_id, accounts)?;
+    let message_hash = slot_bound_message_hash(program_id, payer_key(accounts)?, chain_id, data, slot, max_slot_age);
+    let recovered_pubkey = recover_signer(&message_hash, recovery_id, signature)?;
+    check_signer(&recovered_pubkey, &expected_pubkey)?;
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, accounts, &recovered_pubkey, data, &NO_TAG)
+}
+
+fn verify_record<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature: &[u8; 64],
+    recovery_id: u8,
+    public_key: &[u8; 65],
+    record: &[u8],
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify signature over a {} byte record", record.len());
+    check_state_accounts(accounts)?;
+
+    // try_from_slice fails on leftover bytes as well as missing ones, so only an exact record
+    // decodes, and it is rejected before paying for recovery
+    let attestation = AttestationRecord::try_from_slice(record).map_err(|err| {
+        msg!("Record is not a valid AttestationRecord: {}", err);
+        ProgramError::from(VerifyError::MalformedPackage)
+    })?;
+
+    let expected_pubkey = parse_pubkey(public_key)?;
+    let chain_id = config_chain_id(program_id, accounts)?;
+    let message_hash = record_message_hash(program_id, payer_key(accounts)?, chain_id, record);
+    let recovered_pubkey = recover_signer(&message_hash, recovery_id, signature)?;
+    check_signer(&recovered_pubkey, &expected_pubkey)?;
+
+    msg!("Signature valid for epoch {}!", attestation.epoch);
+    let tag = attestation.epoch.to_le_bytes();
+    finish_verification::<P>(program_id, accounts, &recovered_pubkey, &attestation.root, &tag)
+}
+
+fn verify_personal_sign<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature: &[u8; 64],
+    recovery_id: u8,
+    eth_address: &[u8; 20],
+    message: &[u8],
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify personal_sign signature over {} byte message", message.len());
+    check_state_accounts(accounts)?;
+
+    check_message_len(message)?;
+
+    let message_hash = personal_sign_hash(message);
+    let recovered_pubkey = recover_signer(&message_hash.0, recovery_id, signature)?;
+
+    if pubkey_to_eth_address(&recovered_pubkey) != *eth_address {
+        msg!("Recovered Ethereum address does not match");
+        return Err(VerifyError::EthAddressMismatch.into());
+    }
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, accounts, &recovered_pubkey, &message_hash.0, &NO_TAG)
+}
+
+/// Prefix `personal_sign` adds before the message length, so a signed message can never be a
+/// valid Ethereum transaction.
+const PERSONAL_SIGN_PREFIX: &[u8] = b"\x19Ethereum Signed Message:\n";
+
+/// EIP-191 hash of a `personal_sign` message: `keccak256(prefix || len || message)`, with the
+/// length in ASCII decimal.
+fn personal_sign_hash(message: &[u8]) -> keccak::Hash {
+    let len = message.len().to_string();
+    keccak::hashv(&[PERSONAL_SIGN_PREFIX, len.as_bytes(), message])
+}
+
+fn verify_threshold<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_packages: &[SignaturePackage],
+    threshold: u8,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify {} of {} signatures", threshold, signature_packages.len());
+
+    if threshold == 0 {
+        msg!("Threshold must be at least 1");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if signature_packages.len() > MAX_BATCH_SIZE {
+        msg!("Batch size {} exceeds maximum of {}", signature_packages.len(), MAX_BATCH_SIZE);
+        return Err(VerifyError::BatchTooLarge.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let registry_account = next_account_info(account_info_iter)?;
+    let registry = admin::load_registry(program_id, registry_account)?;
+    let state_accounts = account_info_iter.as_slice();
+    check_state_accounts(state_accounts)?;
+    let payer = payer_key(state_accounts)?;
+    let chain_id = config_chain_id(program_id, state_accounts)?;
+
+    let (data, tag) = match signature_packages.first() {
+        Some(signature_package) => (signature_package.data, signature_package.tag),
+        None => {
+            msg!("No signatures provided");
+            return Err(VerifyError::ThresholdNotMet.into());
+        }
+    };
+
+    let mut signers: Vec<[u8; 64]> = Vec::with_capacity(signature_packages.len());
+    for (index, signature_package) in signature_packages.iter().enumerate() {
+        if signature_package.data != data || signature_package.tag != tag {
+            msg!("Package at index {} signs different data or a different tag", index);
+            return Err(VerifyError::DataMismatch.into());
+        }
+
+        let recovered_pubkey = recover_pubkey(program_id, payer, chain_id, signature_package)?.to_bytes();
+        // Reject rather than ignore duplicates, a single key must never count towards the threshold twice
+        if signers.contains(&recovered_pubkey) {
+            msg!("Package at index {} is a duplicate signature from the same key", index);
+            return Err(VerifyError::DuplicateSigner.into());
+        }
+        if !registry.contains(&recovered_pubkey) {
+            msg!("Package at index {} is not from a registered signer", index);
+            continue;
+        }
+        signers.push(recovered_pubkey);
+    }
+
+    if signers.len() < threshold as usize {
+        msg!("Only {} registered signers, threshold is {}", signers.len(), threshold);
+        return Err(VerifyError::ThresholdNotMet.into());
+    }
+
+    msg!("Threshold met with {} signers!", signers.len());
+    let total_verifications = match update_on_chain_state(program_id, state_accounts, &data, &tag, None) {
+        Ok(total_verifications) => total_verifications,
+        Err(err) => {
+            msg!("Error updating on chain state: {:?}", err);
+            return Err(err);
+        }
+    };
+    // No single key signed the data, the hook sees each registered signer that counted
+    for signer in &signers {
+        P::on_verified(&Secp256k1Pubkey::new(signer), &data, state_accounts)?;
+    }
+    Ok(Verification {
+        signer: None,
+        total_verifications,
+    })
+}
+
+fn verify_signature_var<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_package: &SignaturePackageVar,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify signature over {} byte message", signature_package.message.len());
+    check_state_accounts(accounts)?;
+
+    // Check the length before hashing so an oversized message fails without burning compute
+    check_message_len(&signature_package.message)?;
+
+    check_expiry(signature_package.expiry_unix)?;
+
+    // The message's hash takes the place of a package's data, so the signed message is bound to
+    // the program, payer and cluster the same way
+    let expected_pubkey = parse_pubkey(&signature_package.public_key)?;
+    let message_hash = keccak::hash(&signature_package.message);
+    let chain_id = config_chain_id(program_id, accounts)?;
+    let signed_hash =
+        signed_message_hash(program_id, payer_key(accounts)?, chain_id, &message_hash.0, signature_package.expiry_unix, &NO_TAG, HASH_ALGO_KECCAK256)?;
+    let recovered_pubkey = recover_signer(&signed_hash, signature_package.recovery_id, &signature_package.verifier_signature)?;
+    check_signer(&recovered_pubkey, &expected_pubkey)?;
+
+    msg!("Signature valid!");
+    finish_verification::<P>(program_id, accounts, &recovered_pubkey, &message_hash.0, &NO_TAG)
+}
+
+fn verify_signature_with_nonce<P: PostVerify>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signature_package: &SignaturePackageV2,
+) -> Result<Verification, ProgramError> {
+    msg!("Attempting to verify signature with nonce {}", signature_package.nonce);
+
+    let account_info_iter = &mut accounts.iter();
+    let nonce_account = next_account_info(account_info_iter)?;
+    let state_accounts = account_info_iter.as_slice();
+    check_state_accounts(state_accounts)?;
+    let payer = next_account_info(account_info_iter)?;
+    let _state_account = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    // The nonce is part of the signed message, so it can't be changed without invalidating the
+    // signature, and the payer too, so nobody else can submit the package first and burn the nonce
+    let expected_pubkey = parse_pubkey(&signature_package.public_key)?;
+    let chain_id = config_chain_id(program_id, state_accounts)?;
+    let message_hash = nonce_message_hash(program_id, payer.key, chain_id, &signature_package.data, signature_package.nonce);
+    let recovered_pubkey = recover_signer(&message_hash, signature_package.recovery_id, &signature_package.verifier_signature)?;
+    check_signer(&recovered_pubkey, &expected_pubkey)?;
+
+    let pubkey_bytes = recovered_pubkey.to_bytes();
+    let nonce_seeds: &[&[u8]] = &[NONCE_SEED, &pubkey_bytes[..32], &pubkey_bytes[32..]];
+    let bump = check_pda(program_id, nonce_account, nonce_seeds)?;
+    if nonce_account.owner == &system_program::ID {
+        create_pda_account(program_id, payer, nonce_account, system_program_account, NonceState::LEN, &[NONCE_SEED, &pubkey_bytes[..32], &pubkey_bytes[32..], &[bump]])?;
+    } else if nonce_account.owner != program_id {
+        msg!("Nonce account {} is not owned by this program", nonce_account.key);
+        return Err(ProgramError::IncorrectProgramId);
     }
 
+    let mut nonce_state = NonceState::deserialize(&mut &nonce_account.try_borrow_data()?[..])?;
+    if signature_package.nonce <= nonce_state.nonce {
+        msg!("Nonce {} is not greater than last used nonce {}", signature_package.nonce, nonce_state.nonce);
+        return Err(VerifyError::StaleNonce.into());
+    }
+    nonce_state.nonce = signature_package.nonce;
+    nonce_state.serialize(&mut &mut nonce_account.try_borrow_mut_data()?[..])?;
+
     msg!("Signature valid!");
-    update_on_chain_state(&signature_package.data).expect("Error updating on chain state.");
-    
+    finish_verification::<P>(program_id, state_accounts, &recovered_pubkey, &signature_package.data, &NO_TAG)
+}
+
+/// Event logged with `sol_log_data` after a successful verification, so indexers can decode
+/// verifications from transaction logs without parsing `msg!` output.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct VerificationEvent {
+    /// Recovered secp256k1 public key (x || y).
+    pub signer: [u8; 64],
+    pub data: [u8; 32],
+    pub slot: u64,
+}
+
+pub(crate) fn emit_verification_event(recovered_pubkey: &Secp256k1Pubkey, data: &[u8; 32]) -> ProgramResult {
+    let event = VerificationEvent {
+        signer: recovered_pubkey.to_bytes(),
+        data: *data,
+        slot: Clock::get()?.slot,
+    };
+    sol_log_data(&[&borsh::to_vec(&event)?]);
+
     Ok(())
 }
 
+/// Returns the signer to the caller through return data, so a program invoking this one via CPI
+/// can read it with `get_return_data`. The layout is the 64-byte recovered public key (x || y),
+/// its 20-byte Ethereum address, and the program's total verification count (u64 little-endian).
+/// The key and address are zeroed when there is no single signer.
+fn set_signer_return_data(recovered_pubkey: Option<&Secp256k1Pubkey>, total_verifications: u64) {
+    let mut return_data = [0u8; SIGNER_RETURN_DATA_LEN];
+    if let Some(recovered_pubkey) = recovered_pubkey {
+        return_data[..64].copy_from_slice(&recovered_pubkey.to_bytes());
+        return_data[64..84].copy_from_slice(&pubkey_to_eth_address(recovered_pubkey));
+    }
+    return_data[84..].copy_from_slice(&total_verifications.to_le_bytes());
+    set_return_data(&return_data);
+}
+
+fn recover_pubkey(program_id: &Pubkey, payer: &Pubkey, chain_id: u64, signature_package: &SignaturePackage) -> Result<Secp256k1Pubkey, ProgramError> {
+    check_expiry(signature_package.expiry_unix)?;
+
+    // Verify the signature
+    let message_hash = signed_message_hash(program_id, payer, chain_id, &signature_package.data, signature_package.expiry_unix, &signature_package.tag, signature_package.hash_algo)?;
+
+    recover_signer(&message_hash, signature_package.recovery_id, &signature_package.verifier_signature)
+}
+
+/// Number of accounts `update_on_chain_state` reads: payer, state PDA, system program, config PDA
+/// and audit PDA.
+const STATE_ACCOUNTS_LEN: usize = 5;
+
+/// Fails with `NotEnoughAccountKeys` unless all the state accounts are present, and with
+/// `MissingRequiredSignature` unless the payer signed. Handlers check this before recovering a
+/// signature, so a transaction missing accounts fails without spending the compute on recovery
+/// first.
+fn check_state_accounts(state_accounts: &[AccountInfo]) -> ProgramResult {
+    if state_accounts.len() < STATE_ACCOUNTS_LEN {
+        msg!("Expected {} state accounts, got {}", STATE_ACCOUNTS_LEN, state_accounts.len());
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    check_payer_signed(&state_accounts[0])
+}
+
+/// Fails with `MissingRequiredSignature` unless `payer` signed the transaction. Packages are bound
+/// to the payer and the state PDA is derived from it, so it must be an account the sender controls.
+fn check_payer_signed(payer: &AccountInfo) -> ProgramResult {
+    if !payer.is_signer {
+        msg!("Payer {} did not sign the transaction", payer.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    Ok(())
+}
+
+/// Key of the payer, the first of the state accounts, which a `SignaturePackage` is signed for.
+fn payer_key<'a>(state_accounts: &'a [AccountInfo]) -> Result<&'a Pubkey, ProgramError> {
+    let payer = next_account_info(&mut state_accounts.iter())?;
+    Ok(payer.key)
+}
+
+/// Chain id stored in the config, the fourth of the state accounts, which a `SignaturePackage` is
+/// signed for. A package signed for another cluster hashes a different message and recovers a
+/// different key, so it fails the signer check.
+fn config_chain_id(program_id: &Pubkey, state_accounts: &[AccountInfo]) -> Result<u64, ProgramError> {
+    let config_account = state_accounts.get(3).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    Ok(admin::load_config(program_id, config_account)?.chain_id)
+}
+
+/// Fails with `MessageTooLarge` if `message` is longer than `MAX_MESSAGE_LEN`.
+fn check_message_len(message: &[u8]) -> ProgramResult {
+    if message.len() > MAX_MESSAGE_LEN {
+        msg!("Message length {} exceeds maximum of {}", message.len(), MAX_MESSAGE_LEN);
+        return Err(VerifyError::MessageTooLarge.into());
+    }
+
+    Ok(())
+}
+
+/// Fails with `SlotOutOfRange` unless `slot` is at most the current slot and at most
+/// `max_slot_age` slots behind it.
+fn check_slot_age(slot: u64, max_slot_age: u64) -> ProgramResult {
+    let current_slot = Clock::get()?.slot;
+    if slot > current_slot || current_slot - slot > max_slot_age {
+        msg!("Signed slot {} is not within {} slots of current slot {}", slot, max_slot_age, current_slot);
+        return Err(VerifyError::SlotOutOfRange.into());
+    }
+
+    Ok(())
+}
+
+fn check_expiry(expiry_unix: i64) -> ProgramResult {
+    let now = Clock::get()?.unix_timestamp;
+    if now > expiry_unix {
+        msg!("Signature expired at {}, current time {}", expiry_unix, now);
+        return Err(VerifyError::SignatureExpired.into());
+    }
+
+    Ok(())
+}
+
+/// Hash of the message signed for a `SignaturePackage` or `SignaturePackageCompressed`.
+fn signed_message_hash(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    chain_id: u64,
+    data: &[u8; 32],
+    expiry_unix: i64,
+    tag: &[u8; 8],
+    hash_algo: u8,
+) -> Result<[u8; 32], ProgramError> {
+    // The domain separator and program id bind the signature to this program, so it can't be
+    // replayed against another program that hashes the same data, the payer binds it to the
+    // account submitting it, so nobody else can relay it, and the chain id to this cluster, so it
+    // can't be replayed on a fork or another cluster running the same program
+    shared_types::signed_message_hash(program_id, payer, chain_id, data, expiry_unix, tag, hash_algo).ok_or_else(|| {
+        msg!("Unknown hash algorithm {}", hash_algo);
+        ProgramError::InvalidArgument
+    })
+}
+
+fn recover_signer(message_hash: &[u8; 32], recovery_id: u8, signature: &[u8; 64]) -> Result<Secp256k1Pubkey, ProgramError> {
+    // Reject out of range ids before spending compute on the recovery syscall
+    if recovery_id > MAX_RECOVERY_ID {
+        msg!("Invalid recovery id: {}, must be between 0 and {}", recovery_id, MAX_RECOVERY_ID);
+        return Err(VerifyError::InvalidRecoveryId.into());
+    }
+
+    // (r, s) and (r, n - s) are both valid signatures for the same message, only accept the low-S form
+    // so there is exactly one valid signature per message and key
+    if !is_low_s(signature) {
+        msg!("Signature s value is in the upper half of the curve order");
+        return Err(VerifyError::MalleableSignature.into());
+    }
+
+    // Perform the secp256k1 recovery. The syscall takes the signature as r || s, both big-endian,
+    // the order libsecp256k1 serializes it in on the client
+    let recovered_pubkey = match secp256k1_recover(message_hash, recovery_id, signature) {
+        Ok(pubkey) => pubkey,
+        Err(Secp256k1RecoverError::InvalidRecoveryId) => {
+            msg!("Invalid recovery id: {}", recovery_id);
+            return Err(VerifyError::InvalidRecoveryId.into());
+        }
+        Err(err) => {
+            msg!("Unable to recover public key from signature: {:?}", err);
+            return Err(VerifyError::RecoveryFailed.into());
+        }
+    };
+
+    // Every caller compares the key next, make sure it is a real point first
+    if check_valid_point(&recovered_pubkey).is_err() {
+        msg!("Recovered public key is not a point on the curve");
+        return Err(VerifyError::RecoveryFailed.into());
+    }
+
+    Ok(recovered_pubkey)
+}
+
+/// Parses a public key with `shared_types::parse_pubkey`, logging why it was rejected.
+fn parse_pubkey(public_key: &[u8]) -> Result<Secp256k1Pubkey, ProgramError> {
+    shared_types::parse_pubkey(public_key).map_err(|err| {
+        msg!("Public key is not 64 bytes (x || y) or 65 bytes with the 0x04 prefix, got {} bytes", public_key.len());
+        err.into()
+    })
+}
+
+/// Compresses a public key to 33 bytes: 0x02 if y is even or 0x03 if y is odd, followed by x.
+pub fn compress_pubkey(pubkey: &Secp256k1Pubkey) -> [u8; 33] {
+    let bytes = pubkey.to_bytes();
+    let mut compressed = [0u8; 33];
+    compressed[0] = if bytes[63] & 1 == 0 { 0x02 } else { 0x03 };
+    compressed[1..].copy_from_slice(&bytes[..32]);
+    compressed
+}
+
+fn check_signer(recovered_pubkey: &Secp256k1Pubkey, expected_pubkey: &Secp256k1Pubkey) -> ProgramResult {
+    // Check if the recovered public key matches the expected one, in constant time
+    if !ct_eq(&recovered_pubkey.to_bytes(), &expected_pubkey.to_bytes()) {
+        msg!("Signature verification failed");
+        return Err(VerifyError::PublicKeyMismatch.into());
+    }
+
+    Ok(())
+}
 
-fn update_on_chain_state(message_data: &[u8; 32]) -> ProgramResult {
-    msg!("Updating state with data {:?}", &message_data);
+
+/// Creates the payer's state PDA and writes a zeroed `ProgramState` with the payer as admin.
+///
+/// Accounts:
+/// 0. `[signer, writable]` Payer, funds the state account.
+/// 1. `[writable]` State PDA derived from `[STATE_SEED, payer]`.
+/// 2. `[]` System program.
+fn initialize_state(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    msg!("Initializing state");
+
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+    let state_account = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    check_payer_signed(payer)?;
+    let bump = check_pda(program_id, state_account, &[STATE_SEED, payer.key.as_ref()])?;
+    if state_account.owner != &system_program::ID {
+        msg!("State account {} is already initialized", state_account.key);
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_pda_account(program_id, payer, state_account, system_program_account, ProgramState::LEN, &[STATE_SEED, payer.key.as_ref(), &[bump]])?;
+
+    let state = ProgramState {
+        admin: *payer.key,
+        ..ProgramState::default()
+    };
+    state.pack(&mut state_account.try_borrow_mut_data()?)?;
+
+    msg!("State initialized for {}", payer.key);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// The common end of a handler whose signature recovered to `signer`: updates the on-chain state
+/// with `update_on_chain_state`, then runs the post-verify hook with `state_accounts`, the
+/// instruction's accounts from the payer on.
+fn finish_verification<P: PostVerify>(
+    program_id: &Pubkey,
+    state_accounts: &[AccountInfo],
+    signer: &Secp256k1Pubkey,
+    data: &[u8; 32],
+    tag: &[u8; 8],
+) -> Result<Verification, ProgramError> {
+    let total_verifications = match update_on_chain_state(program_id, state_accounts, data, tag, Some(signer)) {
+        Ok(total_verifications) => total_verifications,
+        Err(err) => {
+            msg!("Error updating on chain state: {:?}", err);
+            return Err(err);
+        }
+    };
+    P::on_verified(signer, data, state_accounts)?;
+    Ok(Verification {
+        signer: Some(*signer),
+        total_verifications,
+    })
+}
+
+/// Stores the verified data and its tag in the payer's state, increments the global verification
+/// count in the config and records the verification in the audit log, returning the new count.
+/// `signer` is `None` when no single key signed the data.
+fn update_on_chain_state(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    message_data: &[u8; 32],
+    tag: &[u8; 8],
+    signer: Option<&Secp256k1Pubkey>,
+) -> Result<u64, ProgramError> {
+    msg!("Updating state with data {:?}", &message_data);
+
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+    let state_account = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let audit_account = next_account_info(account_info_iter)?;
+
+    check_payer_signed(payer)?;
+    check_pda(program_id, state_account, &[STATE_SEED, payer.key.as_ref()])?;
+
+    // The signature already verified, the data still has to have the shape the operator requires
+    let mut config = admin::load_config(program_id, config_account)?;
+    if !config.data_rule.accepts(message_data) {
+        msg!("Data does not satisfy the data rule {:?}", config.data_rule);
+        return Err(VerifyError::InvalidData.into());
+    }
+
+    let rent = Rent::get()?;
+    if state_account.owner == &system_program::ID {
+        msg!("State account {} is not initialized, send InitializeState first", state_account.key);
+        return Err(ProgramError::UninitializedAccount);
+    } else if state_account.owner != program_id {
+        msg!("State account {} is not owned by this program", state_account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Read before growing the account, older layouts without a version byte are told apart by size
+    let mut state = ProgramState::unpack(&state_account.try_borrow_data()?).map_err(|err| {
+        msg!("Unable to read state account {}: {:?}", state_account.key, err);
+        err
+    })?;
+    if state_account.data_len() < ProgramState::LEN {
+        // Grow accounts created with an older, smaller layout and keep them rent exempt, the state
+        // is rewritten in the current one below
+        let shortfall = rent.minimum_balance(ProgramState::LEN).saturating_sub(state_account.lamports());
+        if shortfall > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, state_account.key, shortfall),
+                &[payer.clone(), state_account.clone(), system_program_account.clone()],
+            )?;
+        }
+        state_account.realloc(ProgramState::LEN, true)?;
+    }
+    state.verification_count = state.verification_count.saturating_add(1);
+    state.last_data = *message_data;
+    state.last_tag = *tag;
+    state.pack(&mut state_account.try_borrow_mut_data()?)?;
+
+    msg!("State updated, verification count {}", state.verification_count);
+
+    config.total_verifications = config.total_verifications.saturating_add(1);
+    if config_account.data_len() < Config::LEN {
+        // A config written by an older build grows to the current layout here, the payer covers
+        // the extra rent as for the state
+        let shortfall = rent.minimum_balance(Config::LEN).saturating_sub(config_account.lamports());
+        if shortfall > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, config_account.key, shortfall),
+                &[payer.clone(), config_account.clone(), system_program_account.clone()],
+            )?;
+        }
+    }
+    admin::store_config(config_account, &config)?;
+
+    msg!("Total verifications {}", config.total_verifications);
+
+    check_pda(program_id, audit_account, &[AUDIT_SEED])?;
+    if audit_account.owner != program_id {
+        msg!("Audit log is not initialized");
+        return Err(ProgramError::UninitializedAccount);
+    }
+    let entry = AuditEntry {
+        slot: Clock::get()?.slot,
+        signer_eth_address: signer.map(pubkey_to_eth_address).unwrap_or_default(),
+        data_hash: keccak::hash(message_data).to_bytes(),
+    };
+    AuditLog::append(&mut audit_account.try_borrow_mut_data()?, &entry)?;
+
+    Ok(config.total_verifications)
+}
+
+/// Checks `account` is the PDA derived from `seeds`, returning its bump seed.
+pub(crate) fn check_pda(program_id: &Pubkey, account: &AccountInfo, seeds: &[&[u8]]) -> Result<u8, ProgramError> {
+    let (pda, bump) = Pubkey::find_program_address(seeds, program_id);
+    if pda != *account.key {
+        msg!("Account {} does not match derived address {}", account.key, pda);
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(bump)
+}
+
+/// Creates a rent-exempt PDA of `space` bytes owned by the program, funded by `payer`.
+/// `signer_seeds` must include the bump seed.
+pub(crate) fn create_pda_account<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    msg!("Creating account {}", account.key);
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            account.key,
+            rent.minimum_balance(space),
+            space as u64,
+            program_id,
+        ),
+        &[payer.clone(), account.clone(), system_program_account.clone()],
+        &[signer_seeds],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Registry, CONFIG_SEED, REGISTRY_SEED};
+    use libsecp256k1::{Message, PublicKey, SecretKey};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use shared_types::ALL_MODES_ENABLED;
+    use solana_program::entrypoint::SUCCESS;
+    use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+    use std::cell::RefCell;
+    use std::sync::Once;
+
+    const PROGRAM_ID: Pubkey = Pubkey::new_from_array([0x42; 32]);
+    const PAYER: Pubkey = Pubkey::new_from_array([0x22; 32]);
+    const CHAIN_ID: u64 = 0x4444_4444_4444_4444;
+    const SECRET_KEY: [u8; 32] = [0x11; 32];
+    /// Clock the stubs return.
+    const NOW: i64 = 1_700_000_000;
+    const SLOT: u64 = 1_000;
+
+    thread_local! {
+        static RETURN_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+        static LOGGED_DATA: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Sysvars, return data and logged data for the host, where the default stubs fail
+    /// `Clock::get` and `Rent::get`, drop the return data and print the logged data.
+    struct TestStubs;
+
+    impl SyscallStubs for TestStubs {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                slot: SLOT,
+                unix_timestamp: NOW,
+                ..Clock::default()
+            };
+            unsafe { *(var_addr as *mut Clock) = clock };
+            SUCCESS
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            SUCCESS
+        }
+
+        fn sol_set_return_data(&self, data: &[u8]) {
+            RETURN_DATA.with(|return_data| *return_data.borrow_mut() = data.to_vec());
+        }
+
+        fn sol_log_data(&self, fields: &[&[u8]]) {
+            LOGGED_DATA.with(|logged_data| logged_data.borrow_mut().extend(fields.iter().map(|field| field.to_vec())));
+        }
+    }
+
+    fn install_stubs() {
+        static STUBS: Once = Once::new();
+        STUBS.call_once(|| {
+            set_syscall_stubs(Box::new(TestStubs));
+        });
+    }
+
+    /// Return data set by the last instruction processed on this thread.
+    fn return_data() -> Vec<u8> {
+        RETURN_DATA.with(|return_data| return_data.borrow().clone())
+    }
+
+    /// Fields logged with `sol_log_data` on this thread since the last call.
+    fn take_logged_data() -> Vec<Vec<u8>> {
+        LOGGED_DATA.with(|logged_data| logged_data.take())
+    }
+
+    /// Owns the key, lamports and data behind an `AccountInfo`.
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+        is_signer: bool,
+    }
+
+    impl TestAccount {
+        fn new(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> Self {
+            Self {
+                key,
+                owner,
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                is_signer: false,
+            }
+        }
+
+        fn signer(mut self) -> Self {
+            self.is_signer = true;
+            self
+        }
+
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(&self.key, self.is_signer, true, &mut self.lamports, &mut self.data, &self.owner, false, 0)
+        }
+    }
+
+    fn infos(accounts: &mut [TestAccount]) -> Vec<AccountInfo<'_>> {
+        accounts.iter_mut().map(TestAccount::info).collect()
+    }
+
+    fn pda(seeds: &[&[u8]]) -> Pubkey {
+        Pubkey::find_program_address(seeds, &PROGRAM_ID).0
+    }
+
+    /// Payer, initialized state PDA, system program, config PDA and audit PDA, as after
+    /// `InitializeConfig` and `InitializeState`. Installs the stubs the handlers need.
+    fn state_accounts() -> Vec<TestAccount> {
+        install_stubs();
+
+        let mut state = vec![0u8; ProgramState::LEN];
+        ProgramState { admin: PAYER, ..ProgramState::default() }.pack(&mut state).unwrap();
+        let config = Config {
+            admin: PAYER,
+            enabled_modes: ALL_MODES_ENABLED,
+            chain_id: CHAIN_ID,
+            ..Config::default()
+        };
+        let mut config_data = vec![0u8; Config::LEN];
+        config.pack(&mut config_data).unwrap();
+        let mut audit = vec![0u8; AuditLog::LEN];
+        AuditLog::default().serialize(&mut &mut audit[..]).unwrap();
+
+        vec![
+            TestAccount::new(PAYER, system_program::ID, Vec::new()).signer(),
+            TestAccount::new(pda(&[STATE_SEED, PAYER.as_ref()]), PROGRAM_ID, state),
+            TestAccount::new(system_program::ID, Pubkey::default(), Vec::new()),
+            TestAccount::new(pda(&[CONFIG_SEED]), PROGRAM_ID, config_data),
+            TestAccount::new(pda(&[AUDIT_SEED]), PROGRAM_ID, audit),
+        ]
+    }
+
+    fn load_state(accounts: &[TestAccount]) -> ProgramState {
+        ProgramState::unpack(&accounts[1].data).unwrap()
+    }
+
+    fn load_config(accounts: &[TestAccount]) -> Config {
+        Config::unpack(&accounts[3].data).unwrap()
+    }
+
+    fn public_key(secret: &[u8; 32]) -> [u8; 65] {
+        PublicKey::from_secret_key(&SecretKey::parse(secret).unwrap()).serialize()
+    }
+
+    /// Signs `digest` as is, returning the signature `r || s` and its recovery id.
+    fn sign_digest(digest: &[u8; 32], secret: &[u8; 32]) -> ([u8; 64], u8) {
+        let (signature, recovery_id) = libsecp256k1::sign(&Message::parse(digest), &SecretKey::parse(secret).unwrap());
+        (signature.serialize(), recovery_id.serialize())
+    }
+
+    /// Signs `data` for `PAYER` the way the signer does, expiring a minute after `NOW`.
+    fn sign_package(data: [u8; 32]) -> SignaturePackage {
+        sign_package_with(data, NOW + 60, &SECRET_KEY)
+    }
+
+    fn sign_package_with(data: [u8; 32], expiry_unix: i64, secret: &[u8; 32]) -> SignaturePackage {
+        let message_hash =
+            shared_types::signed_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &data, expiry_unix, &NO_TAG, HASH_ALGO_KECCAK256).unwrap();
+        let (verifier_signature, recovery_id) = sign_digest(&message_hash, secret);
+
+        SignaturePackage {
+            verifier_signature,
+            recovery_id,
+            public_key: public_key(secret),
+            data,
+            expiry_unix,
+            hash_algo: HASH_ALGO_KECCAK256,
+            tag: NO_TAG,
+        }
+    }
+
+    /// The key a handler returns for a package signed with `secret`.
+    fn signer(secret: &[u8; 32]) -> Secp256k1Pubkey {
+        parse_pubkey(&public_key(secret)).unwrap()
+    }
+
+    fn verify_sig(accounts: &mut [TestAccount], package: &SignaturePackage) -> Result<Verification, ProgramError> {
+        verify_signature_with_recover::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), package)
+    }
+
+    #[test]
+    fn verify_sig_updates_state() {
+        let mut accounts = state_accounts();
+        let verification = verify_sig(&mut accounts, &sign_package([0x33; 32]));
+
+        assert_eq!(verification, Ok(Verification { signer: Some(signer(&SECRET_KEY)), total_verifications: 1 }));
+        let state = load_state(&accounts);
+        assert_eq!(state.verification_count, 1);
+        assert_eq!(state.last_data, [0x33; 32]);
+        assert_eq!(load_config(&accounts).total_verifications, 1);
+    }
+
+    #[test]
+    fn verify_sig_rejects_tampered_data() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package([0x33; 32]);
+        package.data[31] ^= 1;
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::PublicKeyMismatch.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    #[test]
+    fn verify_sig_rejects_another_signer() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package([0x33; 32]);
+        package.public_key = public_key(&[0x12; 32]);
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    #[test]
+    fn verify_sig_rejects_out_of_range_recovery_id() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package([0x33; 32]);
+        package.recovery_id = 4;
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::InvalidRecoveryId.into()));
+    }
+
+    #[test]
+    fn verify_sig_rejects_degenerate_packages_as_malformed() {
+        let valid = sign_package([0x33; 32]);
+        let mut zero_signature = valid.clone();
+        zero_signature.verifier_signature = [0; 64];
+        let mut zero_r = valid.clone();
+        zero_r.verifier_signature[..32].fill(0);
+        let mut zero_s = valid.clone();
+        zero_s.verifier_signature[32..].fill(0);
+        let mut zero_key = valid.clone();
+        zero_key.public_key = [0; 65];
+        // The point at infinity as some libraries encode it, a prefix and zero coordinates
+        let mut infinity_key = valid;
+        infinity_key.public_key[1..].fill(0);
+
+        // Recovery would fail these with InvalidSignature or PublicKeyMismatch, MalformedPackage
+        // shows they were rejected before it ran
+        for package in [zero_signature, zero_r, zero_s, zero_key, infinity_key] {
+            let mut accounts = state_accounts();
+            assert_eq!(verify_sig(&mut accounts, &package), Err(VerifyError::MalformedPackage.into()));
+            assert_eq!(load_state(&accounts).verification_count, 0);
+        }
+    }
+
+    #[test]
+    fn verify_sig_rejects_high_s() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package([0x33; 32]);
+        package.verifier_signature[32] = 0xff;
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::MalleableSignature.into()));
+    }
+
+    #[test]
+    fn verify_sig_rejects_the_high_s_counterpart_of_a_valid_signature() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package([0x33; 32]);
+        // (r, n - s) with the flipped recovery id recovers the same key, so only the low-S check stops it
+        let mut signature = libsecp256k1::Signature::parse_standard(&package.verifier_signature).unwrap();
+        signature.s = -signature.s;
+        package.verifier_signature = signature.serialize();
+        package.recovery_id ^= 1;
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::MalleableSignature.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    #[test]
+    fn verify_sig_rejects_expired_package() {
+        let mut accounts = state_accounts();
+        let package = sign_package_with([0x33; 32], NOW - 1, &SECRET_KEY);
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::SignatureExpired.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    #[test]
+    fn verify_sig_accepts_package_expiring_now() {
+        let mut accounts = state_accounts();
+
+        assert!(verify_sig(&mut accounts, &sign_package_with([0x33; 32], NOW, &SECRET_KEY)).is_ok());
+    }
+
+    #[test]
+    fn verify_sig_rejects_extended_expiry() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package_with([0x33; 32], NOW - 1, &SECRET_KEY);
+        package.expiry_unix = NOW + 60;
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    #[test]
+    fn verify_sig_rejects_missing_accounts() {
+        let mut accounts = state_accounts();
+        accounts.pop();
+
+        assert_eq!(verify_sig(&mut accounts, &sign_package([0x33; 32])).err(), Some(ProgramError::NotEnoughAccountKeys));
+    }
+
+    #[test]
+    fn instructions_without_accounts_fail_cleanly() {
+        let package = sign_package([0x33; 32]);
+        let instructions = [
+            ProgramInstruction::VerifySig(package.clone()),
+            ProgramInstruction::VerifySigBatch(vec![package.clone()]),
+            ProgramInstruction::VerifySigFromAccount(package.clone()),
+            ProgramInstruction::VerifySigAgainstRegistry(package.clone()),
+            ProgramInstruction::VerifyAndForward(package),
+            ProgramInstruction::InitializeConfig { chain_id: 1 },
+            ProgramInstruction::InitializeState,
+            ProgramInstruction::RegisterSigner { pubkey: [0x11; 64] },
+            ProgramInstruction::AcceptAdmin,
+        ];
+
+        for instruction in instructions {
+            let data = borsh::to_vec(&instruction).unwrap();
+            assert_eq!(process_instruction(&PROGRAM_ID, &[], &data), Err(ProgramError::NotEnoughAccountKeys), "{:?}", instruction);
+        }
+    }
+
+    #[test]
+    fn verify_sig_rejects_unsigned_payer() {
+        let mut accounts = state_accounts();
+        accounts[0].is_signer = false;
+
+        assert_eq!(verify_sig(&mut accounts, &sign_package([0x33; 32])).err(), Some(ProgramError::MissingRequiredSignature));
+    }
+
+    #[test]
+    fn process_instruction_sets_return_data() {
+        let mut accounts = state_accounts();
+        let instruction = borsh::to_vec(&ProgramInstruction::VerifySig(sign_package([0x33; 32]))).unwrap();
+        process_instruction(&PROGRAM_ID, &infos(&mut accounts), &instruction).unwrap();
+
+        let return_data = return_data();
+        assert_eq!(return_data.len(), SIGNER_RETURN_DATA_LEN);
+        assert_eq!(return_data[..64], public_key(&SECRET_KEY)[1..]);
+        assert_eq!(return_data[84..], 1u64.to_le_bytes());
+    }
+
+    /// The signer, data and account keys of one `on_verified` call.
+    type Call = ([u8; 64], [u8; 32], Vec<Pubkey>);
+
+    std::thread_local! {
+        /// Calls `RecordCalls` received on this test's thread.
+        static CALLS: std::cell::RefCell<Vec<Call>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    /// A downstream program's `PostVerify`, recording each call instead of logging an event.
+    struct RecordCalls;
+
+    impl PostVerify for RecordCalls {
+        fn on_verified(signer: &Secp256k1Pubkey, data: &[u8; 32], accounts: &[AccountInfo]) -> ProgramResult {
+            CALLS.with_borrow_mut(|calls| calls.push((signer.to_bytes(), *data, accounts.iter().map(|account| *account.key).collect())));
+            Ok(())
+        }
+    }
+
+    /// Fails every verification, like a downstream check that rejects the data.
+    struct Reject;
+
+    impl PostVerify for Reject {
+        fn on_verified(_signer: &Secp256k1Pubkey, _data: &[u8; 32], _accounts: &[AccountInfo]) -> ProgramResult {
+            Err(ProgramError::Custom(0xbad))
+        }
+    }
+
+    fn keys(accounts: &[TestAccount]) -> Vec<Pubkey> {
+        accounts.iter().map(|account| account.key).collect()
+    }
+
+    #[test]
+    fn custom_post_verify_is_called_with_each_signer_and_data() {
+        let signer = public_key(&SECRET_KEY)[1..].try_into().unwrap();
+        let mut accounts = state_accounts();
+        let verify_sig = borsh::to_vec(&ProgramInstruction::VerifySig(sign_package([1; 32]))).unwrap();
+        process_instruction_with::<RecordCalls>(&PROGRAM_ID, &infos(&mut accounts), &verify_sig).unwrap();
+        let batch = borsh::to_vec(&ProgramInstruction::VerifySigBatch(vec![sign_package([2; 32]), sign_package([3; 32])])).unwrap();
+        process_instruction_with::<RecordCalls>(&PROGRAM_ID, &infos(&mut accounts), &batch).unwrap();
+
+        // Once per package, with the state accounts
+        let calls = CALLS.take();
+        assert_eq!(calls, [(signer, [1; 32], keys(&accounts)), (signer, [2; 32], keys(&accounts)), (signer, [3; 32], keys(&accounts))]);
+    }
+
+    #[test]
+    fn custom_post_verify_gets_the_accounts_from_the_payer_on() {
+        let signer = public_key(&SECRET_KEY)[1..].try_into().unwrap();
+        // An account of the embedding program's own, after the state accounts
+        let mut accounts = state_accounts();
+        accounts.push(TestAccount::new(Pubkey::new_unique(), Pubkey::new_unique(), Vec::new()));
+        let verify_sig = borsh::to_vec(&ProgramInstruction::VerifySig(sign_package([1; 32]))).unwrap();
+        process_instruction_with::<RecordCalls>(&PROGRAM_ID, &infos(&mut accounts), &verify_sig).unwrap();
+        // The registry comes before the state accounts, so the hook doesn't see it
+        let mut registry_accounts = registry_and_state();
+        let package = sign_package_with([2; 32], NOW + 60, &COMMITTEE_KEYS[0]);
+        let registry = borsh::to_vec(&ProgramInstruction::VerifySigAgainstRegistry(package)).unwrap();
+        process_instruction_with::<RecordCalls>(&PROGRAM_ID, &infos(&mut registry_accounts), &registry).unwrap();
+
+        let calls = CALLS.take();
+        let registry_signer = public_key(&COMMITTEE_KEYS[0])[1..].try_into().unwrap();
+        assert_eq!(calls, [(signer, [1; 32], keys(&accounts)), (registry_signer, [2; 32], keys(&registry_accounts[1..]))]);
+        assert_eq!(calls[0].2[0], PAYER);
+        assert_eq!(calls[1].2[0], PAYER);
+    }
+
+    #[test]
+    fn custom_post_verify_is_called_with_each_threshold_signer() {
+        let mut accounts = registry_and_state();
+        // The unregistered signer doesn't count towards the threshold, so the hook doesn't see it
+        let packages = committee_packages(&[COMMITTEE_KEYS[0], COMMITTEE_KEYS[3], COMMITTEE_KEYS[1]]);
+        let instruction = borsh::to_vec(&ProgramInstruction::VerifyThreshold { packages, threshold: 2 }).unwrap();
+
+        process_instruction_with::<RecordCalls>(&PROGRAM_ID, &infos(&mut accounts), &instruction).unwrap();
+
+        let signer = |secret: &[u8; 32]| public_key(secret)[1..].try_into().unwrap();
+        let calls = CALLS.take();
+        let state_accounts = keys(&accounts[1..]);
+        assert_eq!(calls, [(signer(&COMMITTEE_KEYS[0]), [0x33; 32], state_accounts.clone()), (signer(&COMMITTEE_KEYS[1]), [0x33; 32], state_accounts)]);
+    }
+
+    #[test]
+    fn failing_post_verify_fails_the_instruction() {
+        let mut accounts = state_accounts();
+        let verify_sig = borsh::to_vec(&ProgramInstruction::VerifySig(sign_package([1; 32]))).unwrap();
+
+        let result = process_instruction_with::<Reject>(&PROGRAM_ID, &infos(&mut accounts), &verify_sig);
+
+        assert_eq!(result, Err(ProgramError::Custom(0xbad)));
+    }
+
+    #[test]
+    fn process_instruction_rejects_undecodable_data() {
+        let mut accounts = state_accounts();
+
+        assert_eq!(
+            process_instruction(&PROGRAM_ID, &infos(&mut accounts), &[0xff, 1, 2]),
+            Err(VerifyError::MalformedPackage.into())
+        );
+    }
+
+    #[test]
+    fn verify_sig_rejects_recovery_id_7_without_panicking() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package([0x33; 32]);
+        package.recovery_id = 7;
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::InvalidRecoveryId.into()));
+    }
+
+    #[test]
+    fn verify_sig_rejects_uninitialized_state_without_panicking() {
+        let mut accounts = state_accounts();
+        accounts[1].owner = system_program::ID;
+        accounts[1].data.clear();
+
+        assert_eq!(verify_sig(&mut accounts, &sign_package([0x33; 32])).err(), Some(ProgramError::UninitializedAccount));
+    }
+
+    fn verify_batch(accounts: &mut [TestAccount], packages: &[SignaturePackage]) -> Result<Option<Verification>, ProgramError> {
+        verify_signature_batch::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), packages)
+    }
+
+    #[test]
+    fn verify_batch_accepts_all_valid_packages() {
+        let mut accounts = state_accounts();
+        let packages = [sign_package([1; 32]), sign_package([2; 32]), sign_package([3; 32])];
+
+        let verification = verify_batch(&mut accounts, &packages);
+
+        assert_eq!(verification, Ok(Some(Verification { signer: Some(signer(&SECRET_KEY)), total_verifications: 3 })));
+        let state = load_state(&accounts);
+        assert_eq!(state.verification_count, 3);
+        assert_eq!(state.last_data, [3; 32]);
+    }
+
+    #[test]
+    fn verify_batch_fails_on_a_bad_package_in_the_middle() {
+        let mut accounts = state_accounts();
+        let mut packages = [sign_package([1; 32]), sign_package([2; 32]), sign_package([3; 32])];
+        packages[1].data = [0x22; 32];
+
+        assert_eq!(verify_batch(&mut accounts, &packages).err(), Some(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    #[test]
+    fn verify_batch_rejects_more_than_max_batch_size() {
+        let mut accounts = state_accounts();
+        let packages = vec![sign_package([1; 32]); MAX_BATCH_SIZE + 1];
+
+        assert_eq!(verify_batch(&mut accounts, &packages).err(), Some(VerifyError::BatchTooLarge.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    /// A program-owned account holding the 64-byte public key of `secret`, followed by the state
+    /// accounts.
+    fn pubkey_account_and_state(secret: &[u8; 32]) -> Vec<TestAccount> {
+        let mut accounts = vec![TestAccount::new(Pubkey::new_unique(), PROGRAM_ID, public_key(secret)[1..].to_vec())];
+        accounts.extend(state_accounts());
+        accounts
+    }
+
+    fn verify_from_account(accounts: &mut [TestAccount], package: &SignaturePackage) -> Result<Verification, ProgramError> {
+        verify_signature_from_account::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), package)
+    }
+
+    #[test]
+    fn verify_from_account_accepts_the_stored_key() {
+        let mut accounts = pubkey_account_and_state(&SECRET_KEY);
+        let mut package = sign_package([0x33; 32]);
+        // The inline key is ignored
+        package.public_key = public_key(&[0x12; 32]);
+
+        assert_eq!(verify_from_account(&mut accounts, &package), Ok(Verification { signer: Some(signer(&SECRET_KEY)), total_verifications: 1 }));
+    }
+
+    #[test]
+    fn verify_from_account_rejects_another_stored_key() {
+        let mut accounts = pubkey_account_and_state(&[0x12; 32]);
+
+        assert_eq!(verify_from_account(&mut accounts, &sign_package([0x33; 32])).err(), Some(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    #[test]
+    fn verify_from_account_rejects_an_account_owned_by_another_program() {
+        let mut accounts = pubkey_account_and_state(&SECRET_KEY);
+        accounts[0].owner = Pubkey::new_unique();
+
+        assert_eq!(verify_from_account(&mut accounts, &sign_package([0x33; 32])).err(), Some(ProgramError::IncorrectProgramId));
+    }
+
+    #[test]
+    fn verify_from_account_rejects_a_key_of_the_wrong_length() {
+        let mut accounts = pubkey_account_and_state(&SECRET_KEY);
+        accounts[0].data.push(0);
+        accounts[0].data.push(0);
+
+        assert_eq!(verify_from_account(&mut accounts, &sign_package([0x33; 32])).err(), Some(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn verify_sig_rejects_a_state_account_that_is_not_the_payer_pda() {
+        let mut accounts = state_accounts();
+        accounts[1].key = pda(&[STATE_SEED, Pubkey::new_unique().as_ref()]);
+
+        assert_eq!(verify_sig(&mut accounts, &sign_package([0x33; 32])).err(), Some(ProgramError::InvalidSeeds));
+    }
+
+    /// The account from the web3.js `eth.accounts` documentation, a widely published Ethereum
+    /// key and address pair.
+    const ETH_SECRET_KEY: [u8; 32] = [
+        0x4c, 0x08, 0x83, 0xa6, 0x91, 0x02, 0x93, 0x7d, 0x62, 0x31, 0x47, 0x1b, 0x5d, 0xbb, 0x62, 0x04,
+        0xfe, 0x51, 0x29, 0x61, 0x70, 0x82, 0x79, 0x2a, 0xe4, 0x68, 0xd0, 0x1a, 0x3f, 0x36, 0x23, 0x18,
+    ];
+    const ETH_ADDRESS: [u8; 20] = [
+        0x2c, 0x75, 0x36, 0xe3, 0x60, 0x5d, 0x9c, 0x16, 0xa7, 0xa3, 0xd7, 0xb1, 0x89, 0x8e, 0x52, 0x93, 0x96, 0xa6, 0x5c, 0x23,
+    ];
+
+    fn verify_eth(accounts: &mut [TestAccount], data: &[u8; 32], eth_address: &[u8; 20]) -> Result<Verification, ProgramError> {
+        let package = sign_package_with(*data, NOW + 60, &ETH_SECRET_KEY);
+        verify_eth_signature::<EmitVerificationEvent>(
+            &PROGRAM_ID,
+            &infos(accounts),
+            &package.verifier_signature,
+            package.recovery_id,
+            eth_address,
+            data,
+            package.expiry_unix,
+        )
+    }
+
+    #[test]
+    fn eth_address_matches_the_known_vector() {
+        let pubkey = parse_pubkey(&public_key(&ETH_SECRET_KEY)).unwrap();
+        assert_eq!(pubkey_to_eth_address(&pubkey), ETH_ADDRESS);
+    }
+
+    #[test]
+    fn verify_eth_sig_accepts_the_signer_address() {
+        let mut accounts = state_accounts();
+
+        assert_eq!(verify_eth(&mut accounts, &[0x33; 32], &ETH_ADDRESS), Ok(Verification { signer: Some(signer(&ETH_SECRET_KEY)), total_verifications: 1 }));
+        assert_eq!(load_state(&accounts).last_data, [0x33; 32]);
+    }
+
+    #[test]
+    fn verify_eth_sig_rejects_another_address() {
+        let mut accounts = state_accounts();
+        let mut eth_address = ETH_ADDRESS;
+        eth_address[19] ^= 1;
+
+        assert_eq!(verify_eth(&mut accounts, &[0x33; 32], &eth_address).err(), Some(VerifyError::EthAddressMismatch.into()));
+    }
+
+    #[test]
+    fn verify_eth_sig_rejects_a_signature_over_the_bare_data() {
+        let mut accounts = state_accounts();
+        let data = [0x33; 32];
+        // Signed over keccak(data) alone, which anyone could replay from any payer
+        let (signature, recovery_id) = sign_digest(&keccak::hash(&data).0, &ETH_SECRET_KEY);
+
+        let result =
+            verify_eth_signature::<EmitVerificationEvent>(&PROGRAM_ID, &infos(&mut accounts), &signature, recovery_id, &ETH_ADDRESS, &data, NOW + 60);
+
+        assert_eq!(result.err(), Some(VerifyError::EthAddressMismatch.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    #[test]
+    fn verify_eth_sig_rejects_an_expired_signature() {
+        let mut accounts = state_accounts();
+        let package = sign_package_with([0x33; 32], NOW - 1, &ETH_SECRET_KEY);
+
+        let result = verify_eth_signature::<EmitVerificationEvent>(
+            &PROGRAM_ID,
+            &infos(&mut accounts),
+            &package.verifier_signature,
+            package.recovery_id,
+            &ETH_ADDRESS,
+            &package.data,
+            package.expiry_unix,
+        );
+
+        assert_eq!(result.err(), Some(VerifyError::SignatureExpired.into()));
+    }
+
+    /// `web3.eth.accounts.sign("Some data", ETH_SECRET_KEY)` from the same documentation, the
+    /// `personal_sign` signature a wallet produces for the message: its EIP-191 hash and `r || s`,
+    /// with `v = 0x1c`.
+    const PERSONAL_SIGN_MESSAGE: &[u8] = b"Some data";
+    const PERSONAL_SIGN_HASH: [u8; 32] = [
+        0x1d, 0xa4, 0x4b, 0x58, 0x6e, 0xb0, 0x72, 0x9f, 0xf7, 0x0a, 0x73, 0xc3, 0x26, 0x92, 0x6f, 0x6e,
+        0xd5, 0xa2, 0x5f, 0x5b, 0x05, 0x6e, 0x7f, 0x47, 0xfb, 0xc6, 0xe5, 0x8d, 0x86, 0x87, 0x16, 0x55,
+    ];
+    const PERSONAL_SIGN_SIGNATURE: [u8; 64] = [
+        0xb9, 0x14, 0x67, 0xe5, 0x70, 0xa6, 0x46, 0x6a, 0xa9, 0xe9, 0x87, 0x6c, 0xbc, 0xd0, 0x13, 0xba,
+        0xba, 0x02, 0x90, 0x0b, 0x89, 0x79, 0xd4, 0x3f, 0xe2, 0x08, 0xa4, 0xa4, 0xf3, 0x39, 0xf5, 0xfd,
+        0x60, 0x07, 0xe7, 0x4c, 0xd8, 0x2e, 0x03, 0x7b, 0x80, 0x01, 0x86, 0x42, 0x2f, 0xc2, 0xda, 0x16,
+        0x7c, 0x74, 0x7e, 0xf0, 0x45, 0xe5, 0xd1, 0x8a, 0x5f, 0x5d, 0x43, 0x00, 0xf8, 0xe1, 0xa0, 0x29,
+    ];
+    const PERSONAL_SIGN_RECOVERY_ID: u8 = 0x1c - 27;
+
+    fn verify_personal(accounts: &mut [TestAccount], message: &[u8], eth_address: &[u8; 20]) -> Result<Verification, ProgramError> {
+        verify_personal_sign::<EmitVerificationEvent>(
+            &PROGRAM_ID,
+            &infos(accounts),
+            &PERSONAL_SIGN_SIGNATURE,
+            PERSONAL_SIGN_RECOVERY_ID,
+            eth_address,
+            message,
+        )
+    }
+
+    #[test]
+    fn personal_sign_hash_matches_the_known_vector() {
+        assert_eq!(personal_sign_hash(PERSONAL_SIGN_MESSAGE).0, PERSONAL_SIGN_HASH);
+
+        // The same signature `sign_digest` produces, as both sign deterministically
+        assert_eq!(sign_digest(&PERSONAL_SIGN_HASH, &ETH_SECRET_KEY), (PERSONAL_SIGN_SIGNATURE, PERSONAL_SIGN_RECOVERY_ID));
+    }
+
+    #[test]
+    fn verify_personal_sign_accepts_the_wallet_signature() {
+        let mut accounts = state_accounts();
+
+        let verification = verify_personal(&mut accounts, PERSONAL_SIGN_MESSAGE, &ETH_ADDRESS);
+
+        assert_eq!(verification, Ok(Verification { signer: Some(signer(&ETH_SECRET_KEY)), total_verifications: 1 }));
+        assert_eq!(load_state(&accounts).last_data, PERSONAL_SIGN_HASH);
+    }
+
+    #[test]
+    fn verify_personal_sign_rejects_another_message() {
+        let mut accounts = state_accounts();
+
+        assert_eq!(verify_personal(&mut accounts, b"Some date", &ETH_ADDRESS).err(), Some(VerifyError::EthAddressMismatch.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    #[test]
+    fn verify_personal_sign_bounds_the_message_length() {
+        let mut accounts = state_accounts();
+        let verify = |accounts: &mut [TestAccount], message: &[u8]| {
+            let (signature, recovery_id) = sign_digest(&personal_sign_hash(message).0, &ETH_SECRET_KEY);
+            verify_personal_sign::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), &signature, recovery_id, &ETH_ADDRESS, message)
+        };
+
+        assert_eq!(verify(&mut accounts, &[0x33; MAX_MESSAGE_LEN + 1]).err(), Some(VerifyError::MessageTooLarge.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+        assert!(verify(&mut accounts, &[0x33; MAX_MESSAGE_LEN]).is_ok());
+    }
+
+    /// `digest` signed as is, the way the signer's `--prehashed` signs it.
+    fn sign_prehashed(digest: [u8; 32]) -> SignaturePackage {
+        let (verifier_signature, recovery_id) = sign_digest(&digest, &SECRET_KEY);
+        SignaturePackage {
+            verifier_signature,
+            recovery_id,
+            public_key: public_key(&SECRET_KEY),
+            data: digest,
+            expiry_unix: 0,
+            hash_algo: HASH_ALGO_KECCAK256,
+            tag: NO_TAG,
+        }
+    }
+
+    fn verify_prehashed_package(accounts: &mut [TestAccount], package: &SignaturePackage) -> Result<Verification, ProgramError> {
+        verify_prehashed::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), package)
+    }
+
+    #[test]
+    fn prehashed_package_verifies_only_as_prehashed() {
+        let mut accounts = state_accounts();
+        let package = sign_prehashed([0x33; 32]);
+
+        let verification = verify_prehashed_package(&mut accounts, &package).unwrap();
+        assert_eq!(verification.signer.map(|signer| signer.to_bytes()), Some(public_key(&SECRET_KEY)[1..].try_into().unwrap()));
+        assert_eq!(load_state(&accounts).last_data, [0x33; 32]);
+
+        // `VerifySig` hashes the data into the signed message first, so the digest's signature
+        // recovers another key
+        let mut hashed = package;
+        hashed.expiry_unix = NOW + 60;
+        assert_eq!(verify_sig(&mut accounts, &hashed).err(), Some(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    #[test]
+    fn hashed_package_does_not_verify_as_prehashed() {
+        let mut accounts = state_accounts();
+
+        assert_eq!(verify_prehashed_package(&mut accounts, &sign_package([0x33; 32])).err(), Some(VerifyError::PublicKeyMismatch.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    /// Keys of the threshold tests, the first three registered.
+    const COMMITTEE_KEYS: [[u8; 32]; 4] = [[0x11; 32], [0x12; 32], [0x13; 32], [0x14; 32]];
+
+    /// The registry PDA holding the first three `COMMITTEE_KEYS`, followed by the state accounts.
+    fn registry_and_state() -> Vec<TestAccount> {
+        let mut registry = Registry::default();
+        for secret in &COMMITTEE_KEYS[..3] {
+            registry.signers[registry.count as usize].copy_from_slice(&public_key(secret)[1..]);
+            registry.count += 1;
+        }
+        let mut registry_data = vec![0u8; Registry::LEN];
+        registry.serialize(&mut &mut registry_data[..]).unwrap();
+
+        let mut accounts = vec![TestAccount::new(pda(&[REGISTRY_SEED]), PROGRAM_ID, registry_data)];
+        accounts.extend(state_accounts());
+        accounts
+    }
+
+    /// Packages signing the same data, one per secret.
+    fn committee_packages(secrets: &[[u8; 32]]) -> Vec<SignaturePackage> {
+        secrets.iter().map(|secret| sign_package_with([0x33; 32], NOW + 60, secret)).collect()
+    }
+
+    fn threshold(accounts: &mut [TestAccount], packages: &[SignaturePackage], threshold: u8) -> Result<Verification, ProgramError> {
+        verify_threshold::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), packages, threshold)
+    }
+
+    #[test]
+    fn verify_threshold_accepts_exactly_the_threshold() {
+        let mut accounts = registry_and_state();
+        let packages = committee_packages(&COMMITTEE_KEYS[..2]);
+
+        // No single key signed the data
+        assert_eq!(threshold(&mut accounts, &packages, 2), Ok(Verification { signer: None, total_verifications: 1 }));
+        let state = load_state(&accounts[1..]);
+        assert_eq!(state.verification_count, 1);
+        assert_eq!(state.last_data, [0x33; 32]);
+    }
+
+    #[test]
+    fn verify_threshold_rejects_one_short() {
+        let mut accounts = registry_and_state();
+        let packages = committee_packages(&COMMITTEE_KEYS[..2]);
+
+        assert_eq!(threshold(&mut accounts, &packages, 3).err(), Some(VerifyError::ThresholdNotMet.into()));
+        assert_eq!(load_state(&accounts[1..]).verification_count, 0);
+    }
+
+    #[test]
+    fn verify_threshold_does_not_count_unregistered_signers() {
+        let mut accounts = registry_and_state();
+        let packages = committee_packages(&[COMMITTEE_KEYS[0], COMMITTEE_KEYS[3]]);
+
+        assert_eq!(threshold(&mut accounts, &packages, 2).err(), Some(VerifyError::ThresholdNotMet.into()));
+    }
+
+    #[test]
+    fn verify_threshold_rejects_the_same_key_twice() {
+        let mut accounts = registry_and_state();
+        let packages = committee_packages(&[COMMITTEE_KEYS[0], COMMITTEE_KEYS[0]]);
+
+        assert_eq!(threshold(&mut accounts, &packages, 2).err(), Some(VerifyError::DuplicateSigner.into()));
+    }
+
+    #[test]
+    fn verify_threshold_rejects_packages_over_different_data() {
+        let mut accounts = registry_and_state();
+        let mut packages = committee_packages(&COMMITTEE_KEYS[..2]);
+        packages[1] = sign_package_with([0x44; 32], NOW + 60, &COMMITTEE_KEYS[1]);
+
+        assert_eq!(threshold(&mut accounts, &packages, 2).err(), Some(VerifyError::DataMismatch.into()));
+    }
+
+    /// Signs `message` with `SECRET_KEY` the way the signer does, its Keccak-256 hash as the data.
+    fn sign_message(message: Vec<u8>) -> SignaturePackageVar {
+        let package = sign_package(keccak::hash(&message).0);
+        SignaturePackageVar {
+            verifier_signature: package.verifier_signature,
+            recovery_id: package.recovery_id,
+            public_key: package.public_key,
+            message,
+            expiry_unix: package.expiry_unix,
+        }
+    }
+
+    fn verify_var(accounts: &mut [TestAccount], package: &SignaturePackageVar) -> Result<Verification, ProgramError> {
+        verify_signature_var::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), package)
+    }
+
+    #[test]
+    fn verify_var_accepts_an_empty_message() {
+        let mut accounts = state_accounts();
+
+        let verification = verify_var(&mut accounts, &sign_message(Vec::new()));
+
+        assert_eq!(verification, Ok(Verification { signer: Some(signer(&SECRET_KEY)), total_verifications: 1 }));
+        assert_eq!(load_state(&accounts).last_data, keccak::hash(&[]).0);
+    }
+
+    #[test]
+    fn verify_var_accepts_a_string_message() {
+        let mut accounts = state_accounts();
+        let message = b"validator 7 attests to epoch 512".to_vec();
+
+        assert!(verify_var(&mut accounts, &sign_message(message.clone())).is_ok());
+        assert_eq!(load_state(&accounts).last_data, keccak::hash(&message).0);
+    }
+
+    #[test]
+    fn verify_var_accepts_a_max_length_message() {
+        let mut accounts = state_accounts();
+
+        assert!(verify_var(&mut accounts, &sign_message(vec![0x33; MAX_MESSAGE_LEN])).is_ok());
+    }
+
+    #[test]
+    fn verify_var_rejects_a_message_over_the_max_length() {
+        let mut accounts = state_accounts();
+
+        assert_eq!(
+            verify_var(&mut accounts, &sign_message(vec![0x33; MAX_MESSAGE_LEN + 1])).err(),
+            Some(VerifyError::MessageTooLarge.into())
+        );
+    }
+
+    #[test]
+    fn verify_var_rejects_a_tampered_message() {
+        let mut accounts = state_accounts();
+        let mut package = sign_message(b"hello".to_vec());
+        package.message.push(b'!');
+
+        assert_eq!(verify_var(&mut accounts, &package).err(), Some(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    #[test]
+    fn verify_var_rejects_a_signature_over_the_bare_message_hash() {
+        let mut accounts = state_accounts();
+        let mut package = sign_message(b"hello".to_vec());
+        // Signed over keccak(message) alone, which anyone could replay from any payer
+        (package.verifier_signature, package.recovery_id) = sign_digest(&keccak::hash(b"hello").0, &SECRET_KEY);
+
+        assert_eq!(verify_var(&mut accounts, &package).err(), Some(VerifyError::PublicKeyMismatch.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    #[test]
+    fn verify_var_rejects_an_expired_package() {
+        let mut accounts = state_accounts();
+        let mut package = sign_message(b"hello".to_vec());
+        package.expiry_unix = NOW - 1;
+
+        assert_eq!(verify_var(&mut accounts, &package).err(), Some(VerifyError::SignatureExpired.into()));
+    }
+
+    /// Signs `data` like `sign_package`, hashing the message with `hash_algo`.
+    fn sign_package_hashed(data: [u8; 32], hash_algo: u8) -> SignaturePackage {
+        let mut package = sign_package(data);
+        let message_hash =
+            shared_types::signed_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &data, package.expiry_unix, &NO_TAG, hash_algo).unwrap();
+        (package.verifier_signature, package.recovery_id) = sign_digest(&message_hash, &SECRET_KEY);
+        package.hash_algo = hash_algo;
+        package
+    }
+
+    #[test]
+    fn verify_sig_accepts_a_sha256_package() {
+        let mut accounts = state_accounts();
+
+        assert!(verify_sig(&mut accounts, &sign_package_hashed([0x33; 32], HASH_ALGO_SHA256)).is_ok());
+    }
+
+    #[test]
+    fn verify_sig_rejects_a_sha256_signature_hashed_as_keccak() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package_hashed([0x33; 32], HASH_ALGO_SHA256);
+        package.hash_algo = HASH_ALGO_KECCAK256;
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    #[test]
+    fn verify_sig_rejects_an_unknown_hash_algo() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package([0x33; 32]);
+        package.hash_algo = 2;
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn verify_sig_rejects_a_signature_without_the_domain_tag() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package([0x33; 32]);
+        (package.verifier_signature, package.recovery_id) = sign_digest(&keccak::hash(&package.data).0, &SECRET_KEY);
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    #[test]
+    fn verify_sig_rejects_a_signature_for_another_program() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package([0x33; 32]);
+        let message_hash = shared_types::signed_message_hash(
+            &Pubkey::new_unique(),
+            &PAYER,
+            CHAIN_ID,
+            &package.data,
+            package.expiry_unix,
+            &NO_TAG,
+            HASH_ALGO_KECCAK256,
+        )
+        .unwrap();
+        (package.verifier_signature, package.recovery_id) = sign_digest(&message_hash, &SECRET_KEY);
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    #[test]
+    fn verify_sig_rejects_a_public_key_without_the_uncompressed_prefix() {
+        let mut accounts = state_accounts();
+        let mut package = sign_package([0x33; 32]);
+        package.public_key[0] = 0x02;
+
+        assert_eq!(verify_sig(&mut accounts, &package).err(), Some(VerifyError::MalformedPackage.into()));
+    }
+
+    // The `Mail` example of the EIP-712 specification: its digest, the signature of "Cow" with
+    // v = 28, and Cow's address
+    const MAIL_DIGEST: [u8; 32] = [
+        0xbe, 0x60, 0x9a, 0xee, 0x34, 0x3f, 0xb3, 0xc4, 0xb2, 0x8e, 0x1d, 0xf9, 0xe6, 0x32, 0xfc, 0xa6,
+        0x4f, 0xcf, 0xae, 0xde, 0x20, 0xf0, 0x2e, 0x86, 0x24, 0x4e, 0xfd, 0xdf, 0x30, 0x95, 0x7b, 0xd2,
+    ];
+    const MAIL_SIGNATURE: [u8; 64] = [
+        0x43, 0x55, 0xc4, 0x7d, 0x63, 0x92, 0x4e, 0x8a, 0x72, 0xe5, 0x09, 0xb6, 0x50, 0x29, 0x05, 0x2e,
+        0xb6, 0xc2, 0x99, 0xd5, 0x3a, 0x04, 0xe1, 0x67, 0xc5, 0x77, 0x5f, 0xd4, 0x66, 0x75, 0x1c, 0x9d,
+        0x07, 0x29, 0x99, 0x36, 0xd3, 0x04, 0xc1, 0x53, 0xf6, 0x44, 0x3d, 0xfa, 0x05, 0xf4, 0x0f, 0xf0,
+        0x07, 0xd7, 0x29, 0x11, 0xb6, 0xf7, 0x23, 0x07, 0xf9, 0x96, 0x23, 0x16, 0x05, 0xb9, 0x15, 0x62,
+    ];
+    const MAIL_RECOVERY_ID: u8 = 28 - 27;
+    const COW_ADDRESS: [u8; 20] = [
+        0xcd, 0x2a, 0x3d, 0x9f, 0x93, 0x8e, 0x13, 0xcd, 0x94, 0x7e, 0xc0, 0x5a, 0xbc, 0x7f, 0xe7, 0x34, 0xdf, 0x8d, 0xd8, 0x26,
+    ];
+
+    fn verify_typed(accounts: &mut [TestAccount], eth_address: &[u8; 20]) -> Result<Verification, ProgramError> {
+        verify_typed_data::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), &MAIL_DIGEST, &MAIL_SIGNATURE, MAIL_RECOVERY_ID, eth_address)
+    }
+
+    #[test]
+    fn verify_typed_data_recovers_the_specification_signer() {
+        let mut accounts = state_accounts();
+
+        let verification = verify_typed(&mut accounts, &COW_ADDRESS).unwrap();
+
+        assert_eq!(verification.signer.map(|signer| pubkey_to_eth_address(&signer)), Some(COW_ADDRESS));
+        assert_eq!(load_state(&accounts).last_data, MAIL_DIGEST);
+    }
+
+    #[test]
+    fn verify_typed_data_rejects_another_address() {
+        let mut accounts = state_accounts();
+
+        assert_eq!(verify_typed(&mut accounts, &ETH_ADDRESS), Err(VerifyError::EthAddressMismatch.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    /// `VerifySlotBound` of `[0x33; 32]` signed for `slot`, accepted up to `max_slot_age` slots old.
+    fn verify_slot(accounts: &mut [TestAccount], slot: u64, max_slot_age: u64) -> Result<Verification, ProgramError> {
+        let data = [0x33; 32];
+        let message_hash = slot_bound_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &data, slot, max_slot_age);
+        let (signature, recovery_id) = sign_digest(&message_hash, &SECRET_KEY);
+        verify_slot_bound::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), &signature, recovery_id, &public_key(&SECRET_KEY), &data, slot, max_slot_age)
+    }
+
+    #[test]
+    fn verify_slot_bound_accepts_slots_within_the_window() {
+        // The current slot and the oldest one still inside the window
+        for slot in [SLOT, SLOT - 10] {
+            let mut accounts = state_accounts();
+            let verification = verify_slot(&mut accounts, slot, 10);
+            assert_eq!(verification, Ok(Verification { signer: Some(signer(&SECRET_KEY)), total_verifications: 1 }), "slot {}", slot);
+        }
+    }
+
+    #[test]
+    fn verify_slot_bound_rejects_stale_and_future_slots() {
+        for slot in [SLOT - 11, SLOT + 1] {
+            let mut accounts = state_accounts();
+            assert_eq!(verify_slot(&mut accounts, slot, 10), Err(VerifyError::SlotOutOfRange.into()), "slot {}", slot);
+        }
+    }
+
+    #[test]
+    fn verify_slot_bound_rejects_a_widened_window() {
+        let mut accounts = state_accounts();
+        let data = [0x33; 32];
+        let message_hash = slot_bound_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &data, SLOT - 20, 10);
+        let (signature, recovery_id) = sign_digest(&message_hash, &SECRET_KEY);
+
+        // The window is signed, so a relayer can't stretch it to fit an old attestation
+        let verification =
+            verify_slot_bound::<EmitVerificationEvent>(&PROGRAM_ID, &infos(&mut accounts), &signature, recovery_id, &public_key(&SECRET_KEY), &data, SLOT - 20, 20);
+
+        assert_eq!(verification, Err(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    const RECORD: AttestationRecord = AttestationRecord { epoch: 7, root: [0x33; 32] };
+
+    /// `RECORD`'s encoding signed, then `sent` in its place.
+    fn verify_signed_record(accounts: &mut [TestAccount], sent: &[u8]) -> Result<Verification, ProgramError> {
+        let encoded = borsh::to_vec(&RECORD).unwrap();
+        let (signature, recovery_id) = sign_digest(&record_message_hash(&PROGRAM_ID, &PAYER, CHAIN_ID, &encoded), &SECRET_KEY);
+        verify_record::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), &signature, recovery_id, &public_key(&SECRET_KEY), sent)
+    }
+
+    #[test]
+    fn verify_record_stores_the_root_and_epoch() {
+        let mut accounts = state_accounts();
+
+        let verification = verify_signed_record(&mut accounts, &borsh::to_vec(&RECORD).unwrap());
+
+        assert_eq!(verification, Ok(Verification { signer: Some(signer(&SECRET_KEY)), total_verifications: 1 }));
+        let state = load_state(&accounts);
+        assert_eq!((state.last_data, state.last_tag), (RECORD.root, 7u64.to_le_bytes()));
+    }
+
+    #[test]
+    fn verify_record_rejects_a_truncated_or_extended_record() {
+        let encoded = borsh::to_vec(&RECORD).unwrap();
+        let extended = [&encoded[..], &[0]].concat();
+
+        for sent in [&encoded[..AttestationRecord::LEN - 1], &extended, &[]] {
+            let mut accounts = state_accounts();
+            assert_eq!(verify_signed_record(&mut accounts, sent), Err(VerifyError::MalformedPackage.into()));
+        }
+    }
+
+    #[test]
+    fn verify_record_rejects_reordered_fields() {
+        let mut accounts = state_accounts();
+        // Root first, then epoch: still 40 bytes that decode, but not the bytes that were signed
+        let reordered = [&RECORD.root[..], &RECORD.epoch.to_le_bytes()].concat();
+
+        assert_eq!(verify_signed_record(&mut accounts, &reordered), Err(VerifyError::PublicKeyMismatch.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    fn verify_any_recovery(accounts: &mut [TestAccount], data: &[u8; 32], public_key: &[u8; 65]) -> Result<Verification, ProgramError> {
+        let package = sign_package(*data);
+        verify_signature_any_recovery::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), &package.verifier_signature, public_key, data, package.expiry_unix)
+    }
+
+    #[test]
+    fn verify_any_recovery_finds_either_recovery_id() {
+        // Data whose signature has recovery id 0 and data whose signature has recovery id 1
+        for expected_id in [0, 1] {
+            let data = (0..=u8::MAX)
+                .map(|byte| [byte; 32])
+                .find(|data| sign_package(*data).recovery_id == expected_id)
+                .unwrap();
+            let mut accounts = state_accounts();
+
+            let verification = verify_any_recovery(&mut accounts, &data, &public_key(&SECRET_KEY));
+
+            assert_eq!(verification, Ok(Verification { signer: Some(signer(&SECRET_KEY)), total_verifications: 1 }));
+            assert_eq!(load_state(&accounts).last_data, data);
+        }
+    }
+
+    #[test]
+    fn verify_any_recovery_rejects_another_key() {
+        let mut accounts = state_accounts();
+
+        let verification = verify_any_recovery(&mut accounts, &[0x33; 32], &public_key(&[0x12; 32]));
+
+        assert_eq!(verification, Err(VerifyError::PublicKeyMismatch.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    #[test]
+    fn verify_any_recovery_rejects_a_signature_over_the_bare_data() {
+        let mut accounts = state_accounts();
+        let data = [0x33; 32];
+        // Signed over keccak(data) alone, which anyone could replay from any payer
+        let (signature, _recovery_id) = sign_digest(&keccak::hash(&data).0, &SECRET_KEY);
+
+        let verification =
+            verify_signature_any_recovery::<EmitVerificationEvent>(&PROGRAM_ID, &infos(&mut accounts), &signature, &public_key(&SECRET_KEY), &data, NOW + 60);
+
+        assert_eq!(verification, Err(VerifyError::PublicKeyMismatch.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    #[test]
+    fn verify_any_recovery_rejects_an_expired_signature() {
+        let mut accounts = state_accounts();
+        let package = sign_package_with([0x33; 32], NOW - 1, &SECRET_KEY);
+
+        let verification = verify_signature_any_recovery::<EmitVerificationEvent>(
+            &PROGRAM_ID,
+            &infos(&mut accounts),
+            &package.verifier_signature,
+            &package.public_key,
+            &package.data,
+            package.expiry_unix,
+        );
+
+        assert_eq!(verification, Err(VerifyError::SignatureExpired.into()));
+    }
+
+    fn verify_same(accounts: &mut [TestAccount], pkg_a: &SignaturePackage, pkg_b: &SignaturePackage) -> Result<Verification, ProgramError> {
+        verify_same_signer::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), pkg_a, pkg_b)
+    }
+
+    #[test]
+    fn verify_same_signer_accepts_two_packages_from_one_key() {
+        let mut accounts = state_accounts();
+        let commitment = sign_package(keccak::hash(&[0x33; 32]).0);
+        // The embedded key isn't what is compared
+        let mut reveal = sign_package([0x33; 32]);
+        reveal.public_key = public_key(&[0x12; 32]);
+
+        let verification = verify_same(&mut accounts, &commitment, &reveal);
+
+        assert_eq!(verification, Ok(Verification { signer: Some(signer(&SECRET_KEY)), total_verifications: 1 }));
+        assert_eq!(load_state(&accounts).last_data, [0x33; 32]);
+    }
+
+    #[test]
+    fn verify_same_signer_rejects_packages_from_different_keys() {
+        let mut accounts = state_accounts();
+        let commitment = sign_package(keccak::hash(&[0x33; 32]).0);
+        let reveal = sign_package_with([0x33; 32], NOW + 60, &[0x12; 32]);
+
+        assert_eq!(verify_same(&mut accounts, &commitment, &reveal), Err(VerifyError::PublicKeyMismatch.into()));
+        assert_eq!(load_state(&accounts).verification_count, 0);
+    }
+
+    #[test]
+    fn verify_same_signer_rejects_the_same_signature_twice() {
+        let mut accounts = state_accounts();
+        let package = sign_package([0x33; 32]);
+
+        assert_eq!(verify_same(&mut accounts, &package, &package), Err(ProgramError::InvalidArgument));
+    }
+
+    /// `package` with its public key in the 33-byte compressed form.
+    fn compressed(package: SignaturePackage) -> SignaturePackageCompressed {
+        let public_key = PublicKey::parse(&package.public_key).unwrap().serialize_compressed();
+        SignaturePackageCompressed {
+            verifier_signature: package.verifier_signature,
+            recovery_id: package.recovery_id,
+            public_key,
+            data: package.data,
+            expiry_unix: package.expiry_unix,
+            hash_algo: package.hash_algo,
+            tag: package.tag,
+        }
+    }
+
+    fn verify_compressed(accounts: &mut [TestAccount], package: &SignaturePackageCompressed) -> Result<Verification, ProgramError> {
+        verify_signature_compressed::<EmitVerificationEvent>(&PROGRAM_ID, &infos(accounts), package)
+    }
+
+    #[test]
+    fn compressed_and_uncompressed_keys_verify_the_same_signature() {
+        let package = sign_package([0x33; 32]);
+
+        let mut accounts = state_accounts();
+        assert!(verify_sig(&mut accounts, &package).is_ok());
+        let mut accounts = state_accounts();
+        let verification = verify_compressed(&mut accounts, &compressed(package));
+        assert_eq!(verification, Ok(Verification { signer: Some(signer(&SECRET_KEY)), total_verifications: 1 }));
+        assert_eq!(load_state(&accounts).last_data, [0x33; 32]);
+    }
+
+    #[test]
+    fn compress_pubkey_matches_libsecp256k1() {
+        let pubkey = parse_pubkey(&public_key(&SECRET_KEY)).unwrap();
+        let expected = PublicKey::parse(&public_key(&SECRET_KEY)).unwrap().serialize_compressed();
+
+        assert_eq!(compress_pubkey(&pubkey), expected);
+    }
+
+    #[test]
+    fn verify_compressed_rejects_the_wrong_parity() {
+        let mut accounts = state_accounts();
+        let mut package = compressed(sign_package([0x33; 32]));
+        package.public_key[0] ^= 1;
+
+        assert_eq!(verify_compressed(&mut accounts, &package).err(), Some(VerifyError::PublicKeyMismatch.into()));
+    }
+
+    #[test]
+    fn verify_compressed_rejects_an_uncompressed_prefix() {
+        let mut accounts = state_accounts();
+        let mut package = compressed(sign_package([0x33; 32]));
+        package.public_key[0] = 0x04;
+
+        assert_eq!(verify_compressed(&mut accounts, &package).err(), Some(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn recover_signer_rejects_recovery_ids_above_3_up_front() {
+        let message_hash = [0x33; 32];
+        let (signature, _) = sign_digest(&message_hash, &SECRET_KEY);
+
+        for recovery_id in 0..=7u8 {
+            let invalid = recover_signer(&message_hash, recovery_id, &signature).err() == Some(VerifyError::InvalidRecoveryId.into());
+            assert_eq!(invalid, recovery_id > 3, "recovery id {}", recovery_id);
+        }
+    }
+
+    #[test]
+    fn verify_sig_logs_the_verification_event() {
+        let mut accounts = state_accounts();
+        take_logged_data();
+        verify_sig(&mut accounts, &sign_package([0x33; 32])).unwrap();
+
+        let logged_data = take_logged_data();
+        assert_eq!(logged_data.len(), 1);
+        let event = VerificationEvent::try_from_slice(&logged_data[0]).unwrap();
+        assert_eq!(
+            event,
+            VerificationEvent {
+                signer: public_key(&SECRET_KEY)[1..].try_into().unwrap(),
+                data: [0x33; 32],
+                slot: SLOT,
+            }
+        );
+    }
+
+    #[test]
+    fn failed_verification_logs_no_event() {
+        let mut accounts = state_accounts();
+        take_logged_data();
+        let mut package = sign_package([0x33; 32]);
+        package.data[0] ^= 1;
+        assert!(verify_sig(&mut accounts, &package).is_err());
+
+        assert!(take_logged_data().is_empty());
+    }
+
+    /// A deterministic stand-in for the `fuzz/` targets that runs with `cargo test`: random bytes,
+    /// and a valid `VerifySig` with random bytes overwritten, must always come back as a
+    /// `ProgramResult` rather than a panic.
+    #[test]
+    fn process_instruction_never_panics_on_random_input() {
+        let mut rng = StdRng::seed_from_u64(37);
+        let valid = borsh::to_vec(&ProgramInstruction::VerifySig(sign_package([0x33; 32]))).unwrap();
+        for _ in 0..2_000 {
+            let mut accounts = state_accounts();
+
+            let len = rng.gen_range(0..512);
+            let random: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let _ = process_instruction(&PROGRAM_ID, &infos(&mut accounts), &random);
+            let _ = process_instruction(&PROGRAM_ID, &[], &random);
+
+            let mut mutated = valid.clone();
+            for _ in 0..rng.gen_range(1..8) {
+                let index = rng.gen_range(0..mutated.len());
+                mutated[index] = rng.gen();
+            }
+            mutated.truncate(rng.gen_range(0..=mutated.len()));
+            let _ = process_instruction(&PROGRAM_ID, &infos(&mut accounts), &mutated);
+        }
+    }
+
+    #[test]
+    fn verify_sig_never_panics_on_random_packages() {
+        let mut rng = StdRng::seed_from_u64(37);
+        for _ in 0..500 {
+            let mut accounts = state_accounts();
+            let mut package = sign_package([0x33; 32]);
+            rng.fill(&mut package.verifier_signature[..]);
+            rng.fill(&mut package.public_key[..]);
+            package.recovery_id = rng.gen();
+            package.hash_algo = rng.gen_range(0..3);
+
+            assert!(verify_sig(&mut accounts, &package).is_err());
+        }
+    }
+}
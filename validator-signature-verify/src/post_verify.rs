@@ -0,0 +1,30 @@
+//! What happens after a signature verifies. The handlers always update the payer's state, config
+//! and audit log, then hand the recovered signer and verified data to a `PostVerify`, so a
+//! program embedding this one's verification can add its own logic through
+//! `process_instruction_with`.
+
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::secp256k1_recover::Secp256k1Pubkey;
+
+/// Runs after each successful verification, once per package of a `VerifySigBatch` and once per
+/// registered signer counted towards a `VerifyThreshold`, in package order. An error fails the
+/// whole instruction, rolling back the state the handler wrote.
+pub trait PostVerify {
+    /// Called with the recovered signer, the verified data as stored in the payer's state, and
+    /// the instruction's accounts from the payer on: the five state accounts (payer, state PDA,
+    /// system program, config PDA, audit PDA) at indices 0 to 4, then any accounts the
+    /// instruction was given after them, such as `VerifyAndForward`'s target program and its
+    /// accounts or the embedding program's own. Accounts an instruction takes before the state
+    /// accounts, such as the registry or nonce PDA, aren't passed. By default it logs a
+    /// `VerificationEvent`.
+    fn on_verified(signer: &Secp256k1Pubkey, data: &[u8; 32], accounts: &[AccountInfo]) -> ProgramResult {
+        let _ = accounts;
+        crate::emit_verification_event(signer, data)
+    }
+}
+
+/// The program's own `PostVerify`, logging a `VerificationEvent` with `sol_log_data` for indexers.
+pub struct EmitVerificationEvent;
+
+impl PostVerify for EmitVerificationEvent {}
@@ -0,0 +1,345 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use shared_types::ALL_MODES_ENABLED;
+
+pub use shared_types::{DataRule, ProgramState, ProgramStateV1, ProgramStateV2, AUDIT_SEED, CONFIG_SEED, NONCE_SEED, REGISTRY_SEED, STATE_SEED, STATE_VERSION};
+
+/// Layout version written to the first byte of the config account.
+pub const CONFIG_VERSION: u8 = 1;
+
+/// Maximum number of signers the registry can hold.
+pub const MAX_REGISTERED_SIGNERS: usize = 16;
+
+/// Program-wide configuration, stored in the config PDA. The account holds a version byte followed
+/// by the Borsh-serialized config, read and written with `unpack` and `pack`.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub admin: Pubkey,
+    /// Number of successful verifications across all instructions.
+    pub total_verifications: u64,
+    /// Bitmask of the enabled instructions, bit `n` for the variant with discriminant `n`.
+    pub enabled_modes: u32,
+    /// Chain id of the cluster the program runs on, appended to every signed message.
+    pub chain_id: u64,
+    /// Admin proposed with `ProposeAdmin`, waiting to accept with `AcceptAdmin`.
+    pub pending_admin: Option<Pubkey>,
+    /// Rule verified data must satisfy, set with `SetDataRule`.
+    pub data_rule: DataRule,
+}
+
+impl Config {
+    /// Size of a config account in bytes, the version byte followed by the Borsh-serialized config
+    /// with a pending admin and a `DataRule::LeadingByte` set.
+    pub const LEN: usize = 1 + 32 + 8 + 4 + 8 + 1 + 32 + 2;
+
+    /// Sizes of the config accounts written before the version byte was introduced, one per
+    /// field added: the admin, the total verification count, the enabled modes, the chain id, the
+    /// pending admin and the data rule. Each build created the account with exactly its size.
+    pub const UNVERSIONED_LENS: [usize; 6] = [32, 40, 44, 52, 85, 87];
+
+    /// Reads a config account of any known layout. Accounts written before the version byte was
+    /// introduced are recognized by their size, which no versioned layout shares, and the fields
+    /// they predate take their defaults: every mode enabled, chain id 0, no pending admin and
+    /// `DataRule::Any`. Fails with `InvalidAccountData` for an unknown size or version.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if Self::UNVERSIONED_LENS.contains(&data.len()) {
+            return Self::unpack_unversioned(data);
+        }
+        let Some((&version, mut config)) = data.split_first() else {
+            return Err(ProgramError::InvalidAccountData);
+        };
+        match version {
+            CONFIG_VERSION => Ok(Config::deserialize(&mut config)?),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    fn unpack_unversioned(mut data: &[u8]) -> Result<Self, ProgramError> {
+        let len = data.len();
+        let mut config = Config {
+            admin: Pubkey::deserialize(&mut data)?,
+            // Everything was enabled before the mask existed
+            enabled_modes: ALL_MODES_ENABLED,
+            ..Config::default()
+        };
+        if len >= 40 {
+            config.total_verifications = u64::deserialize(&mut data)?;
+        }
+        if len >= 44 {
+            config.enabled_modes = u32::deserialize(&mut data)?;
+        }
+        if len >= 52 {
+            config.chain_id = u64::deserialize(&mut data)?;
+        }
+        if len >= 85 {
+            config.pending_admin = Option::<Pubkey>::deserialize(&mut data)?;
+        }
+        if len >= 87 {
+            config.data_rule = DataRule::deserialize(&mut data)?;
+        }
+        Ok(config)
+    }
+
+    /// Writes the config in the current layout, which needs `LEN` bytes.
+    pub fn pack(&self, data: &mut [u8]) -> Result<(), ProgramError> {
+        let Some((version, config)) = data.split_first_mut() else {
+            return Err(ProgramError::AccountDataTooSmall);
+        };
+        *version = CONFIG_VERSION;
+        self.serialize(&mut &mut config[..])?;
+        Ok(())
+    }
+}
+
+/// Fixed-capacity set of authorized secp256k1 signers, stored as 64-byte public keys (x || y).
+/// Only the first `count` entries of `signers` are in use.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct Registry {
+    pub count: u8,
+    pub signers: [[u8; 64]; MAX_REGISTERED_SIGNERS],
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            signers: [[0u8; 64]; MAX_REGISTERED_SIGNERS],
+        }
+    }
+}
+
+impl Registry {
+    /// Size of the Borsh-serialized registry in bytes.
+    pub const LEN: usize = 1 + 64 * MAX_REGISTERED_SIGNERS;
+
+    pub fn signers(&self) -> &[[u8; 64]] {
+        &self.signers[..self.count as usize]
+    }
+
+    pub fn contains(&self, pubkey: &[u8; 64]) -> bool {
+        self.signers().contains(pubkey)
+    }
+
+    /// Adds `pubkey`, returning `false` if the registry is full.
+    pub fn insert(&mut self, pubkey: [u8; 64]) -> bool {
+        let count = self.count as usize;
+        if count == MAX_REGISTERED_SIGNERS {
+            return false;
+        }
+        self.signers[count] = pubkey;
+        self.count += 1;
+        true
+    }
+
+    /// Removes `pubkey`, returning `false` if it was not registered.
+    pub fn remove(&mut self, pubkey: &[u8; 64]) -> bool {
+        let count = self.count as usize;
+        match self.signers().iter().position(|signer| signer == pubkey) {
+            Some(index) => {
+                // Keep the used entries contiguous by moving the last one into the gap.
+                self.signers[index] = self.signers[count - 1];
+                self.signers[count - 1] = [0u8; 64];
+                self.count -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// `Committee` layout version the program reads.
+pub const COMMITTEE_VERSION: u8 = 1;
+
+/// Fixed set of authorized signers, stored as 64-byte public keys (x || y) in one account
+/// written at deployment. Unlike the `Registry` it has no size limit and isn't changed by
+/// instructions.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Committee {
+    /// Layout version, must be `COMMITTEE_VERSION`.
+    pub version: u8,
+    pub members: Vec<[u8; 64]>,
+}
+
+impl Committee {
+    pub fn contains(&self, pubkey: &[u8; 64]) -> bool {
+        self.members.contains(pubkey)
+    }
+}
+
+/// Last nonce accepted for a signer, stored in the signer's nonce PDA.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct NonceState {
+    pub nonce: u64,
+}
+
+impl NonceState {
+    /// Size of the Borsh-serialized nonce state in bytes.
+    pub const LEN: usize = 8;
+}
+
+/// Number of entries the audit log keeps before overwriting the oldest.
+pub const AUDIT_LOG_CAPACITY: usize = 32;
+
+/// One successful verification recorded in the audit log.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub slot: u64,
+    /// Ethereum address of the recovered signer, zeroed when there is no single signer.
+    pub signer_eth_address: [u8; 20],
+    /// Keccak-256 hash of the verified data.
+    pub data_hash: [u8; 32],
+}
+
+impl AuditEntry {
+    /// Size of the Borsh-serialized entry in bytes.
+    pub const LEN: usize = 8 + 20 + 32;
+}
+
+/// Ring buffer of the last `AUDIT_LOG_CAPACITY` verifications, stored in the audit PDA.
+/// `head` is the index the next entry is written to, so once the log is full it is also the
+/// oldest entry.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditLog {
+    pub head: u32,
+    /// Number of entries ever written. Only the first `total.min(AUDIT_LOG_CAPACITY)` entries
+    /// are in use.
+    pub total: u64,
+    pub entries: [AuditEntry; AUDIT_LOG_CAPACITY],
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self {
+            head: 0,
+            total: 0,
+            entries: [AuditEntry::default(); AUDIT_LOG_CAPACITY],
+        }
+    }
+}
+
+impl AuditLog {
+    /// Size of the header holding `head` and `total`.
+    pub const HEADER_LEN: usize = 4 + 8;
+    /// Size of the Borsh-serialized log in bytes.
+    pub const LEN: usize = Self::HEADER_LEN + AuditEntry::LEN * AUDIT_LOG_CAPACITY;
+
+    /// Writes `entry` over the oldest entry of the log serialized in `data` and advances the
+    /// header. Only the header and the one entry are decoded, not the whole log.
+    pub fn append(data: &mut [u8], entry: &AuditEntry) -> Result<(), ProgramError> {
+        if data.len() < Self::LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+
+        let (mut head, mut total) = <(u32, u64)>::deserialize(&mut &data[..Self::HEADER_LEN])?;
+        if head as usize >= AUDIT_LOG_CAPACITY {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let offset = Self::HEADER_LEN + head as usize * AuditEntry::LEN;
+        entry.serialize(&mut &mut data[offset..offset + AuditEntry::LEN])?;
+
+        head = (head + 1) % AUDIT_LOG_CAPACITY as u32;
+        total = total.saturating_add(1);
+        (head, total).serialize(&mut &mut data[..Self::HEADER_LEN])?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADMIN: Pubkey = Pubkey::new_from_array([0x44; 32]);
+    const PENDING: Pubkey = Pubkey::new_from_array([0x66; 32]);
+    const TOTAL: u64 = 0x0102_0304_0506_0708;
+    const MODES: u32 = 0b1011;
+    const CHAIN_ID: u64 = 0x1111_2222_3333_4444;
+
+    /// The bytes each layout was written with, built by hand rather than with Borsh.
+    fn blob(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn unversioned_configs_are_read_by_their_size() {
+        let fields: [&[u8]; 6] = [
+            ADMIN.as_ref(),
+            &TOTAL.to_le_bytes(),
+            &MODES.to_le_bytes(),
+            &CHAIN_ID.to_le_bytes(),
+            &blob(&[&[1], PENDING.as_ref()]),
+            &[2, 0x7f],
+        ];
+        let expected = [
+            Config { admin: ADMIN, enabled_modes: ALL_MODES_ENABLED, ..Config::default() },
+            Config { admin: ADMIN, total_verifications: TOTAL, enabled_modes: ALL_MODES_ENABLED, ..Config::default() },
+            Config { admin: ADMIN, total_verifications: TOTAL, enabled_modes: MODES, ..Config::default() },
+            Config { admin: ADMIN, total_verifications: TOTAL, enabled_modes: MODES, chain_id: CHAIN_ID, ..Config::default() },
+            Config {
+                admin: ADMIN,
+                total_verifications: TOTAL,
+                enabled_modes: MODES,
+                chain_id: CHAIN_ID,
+                pending_admin: Some(PENDING),
+                data_rule: DataRule::Any,
+            },
+            Config {
+                admin: ADMIN,
+                total_verifications: TOTAL,
+                enabled_modes: MODES,
+                chain_id: CHAIN_ID,
+                pending_admin: Some(PENDING),
+                data_rule: DataRule::LeadingByte(0x7f),
+            },
+        ];
+        for (fields_written, (len, expected)) in (1..=fields.len()).zip(Config::UNVERSIONED_LENS.into_iter().zip(expected)) {
+            let data = blob(&fields[..fields_written]);
+            assert_eq!(data.len(), len);
+            assert_eq!(Config::unpack(&data).unwrap(), expected, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn unversioned_configs_without_a_pending_admin_are_zero_padded() {
+        // The account kept room for a pending admin, Borsh wrote the `None` tag and the data rule
+        // right after it
+        let mut data = blob(&[ADMIN.as_ref(), &TOTAL.to_le_bytes(), &MODES.to_le_bytes(), &CHAIN_ID.to_le_bytes(), &[0, 2, 0x7f]]);
+        data.resize(87, 0);
+
+        let config = Config::unpack(&data).unwrap();
+
+        assert_eq!((config.pending_admin, config.data_rule), (None, DataRule::LeadingByte(0x7f)));
+    }
+
+    #[test]
+    fn unknown_versions_and_sizes_are_rejected() {
+        for version in [0, CONFIG_VERSION + 1] {
+            let mut data = vec![0u8; Config::LEN];
+            data[0] = version;
+            assert_eq!(Config::unpack(&data), Err(ProgramError::InvalidAccountData), "version {}", version);
+        }
+        assert_eq!(Config::unpack(&[]), Err(ProgramError::InvalidAccountData));
+        assert_eq!(Config::unpack(&[0u8; 31]), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn pack_writes_the_current_version() {
+        let config = Config {
+            admin: ADMIN,
+            total_verifications: TOTAL,
+            enabled_modes: MODES,
+            chain_id: CHAIN_ID,
+            pending_admin: Some(PENDING),
+            data_rule: DataRule::LeadingByte(0x7f),
+        };
+        let mut data = vec![0u8; Config::LEN];
+
+        config.pack(&mut data).unwrap();
+
+        assert_eq!(data[0], CONFIG_VERSION);
+        assert_eq!(Config::unpack(&data).unwrap(), config);
+    }
+}
@@ -0,0 +1,350 @@
+//! Admin instructions processed end to end by a bank: initializing the config, the signer registry
+//! and the config's settings.
+
+mod common;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use common::*;
+use shared_types::{DataRule, ALL_MODES_ENABLED};
+use signature_verify::state::{Config, Registry, CONFIG_VERSION};
+use signature_verify::{ProgramInstruction, VerifyError};
+use solana_program_test::{BanksClientError, ProgramTestContext};
+use solana_sdk::account::{Account, AccountSharedData};
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+
+/// Sends a registry instruction signed by `admin`, the context's payer unless given.
+async fn send_registry_instruction(
+    context: &mut ProgramTestContext,
+    instruction: ProgramInstruction,
+    admin: Option<&Keypair>,
+) -> Result<(), BanksClientError> {
+    let admin_key = admin.map_or(context.payer.pubkey(), Keypair::pubkey);
+    let instruction = Instruction::new_with_borsh(
+        PROGRAM_ID,
+        &instruction,
+        vec![
+            AccountMeta::new_readonly(admin_key, true),
+            AccountMeta::new_readonly(config_pda(), false),
+            AccountMeta::new(registry_pda(), false),
+        ],
+    );
+    send(context, &[instruction], admin.as_slice()).await
+}
+
+fn signer_pubkey(secret: &[u8; 32]) -> [u8; 64] {
+    public_key(secret)[1..].try_into().unwrap()
+}
+
+async fn load_registry(context: &mut ProgramTestContext) -> Registry {
+    let account = context.banks_client.get_account(registry_pda()).await.unwrap().unwrap();
+    Registry::deserialize(&mut &account.data[..]).unwrap()
+}
+
+/// `VerifySigAgainstRegistry` of a package signed with `secret`.
+fn verify_against_registry(payer: &Pubkey, secret: &[u8; 32]) -> Instruction {
+    let package = sign_package(payer, [0x33; 32], secret);
+    let mut accounts = vec![AccountMeta::new_readonly(registry_pda(), false)];
+    accounts.extend(state_accounts(payer));
+    Instruction::new_with_borsh(PROGRAM_ID, &ProgramInstruction::VerifySigAgainstRegistry(package), accounts)
+}
+
+#[tokio::test]
+async fn only_the_upgrade_authority_initializes_the_config() {
+    let mut context = program_test().start_with_context().await;
+    let payer = context.payer.pubkey();
+
+    set_upgrade_authority(&mut context, Some(Pubkey::new_unique()));
+    let result = send(&mut context, &[initialize_config(&payer)], &[]).await;
+    assert_eq!(custom_error(result), Some(VerifyError::Unauthorized.code()));
+
+    // A program deployed as immutable has no authority, nobody can initialize its config
+    set_upgrade_authority(&mut context, None);
+    context.get_new_latest_blockhash().await.unwrap();
+    let result = send(&mut context, &[initialize_config(&payer)], &[]).await;
+    assert_eq!(custom_error(result), Some(VerifyError::Unauthorized.code()));
+
+    set_upgrade_authority(&mut context, Some(payer));
+    context.get_new_latest_blockhash().await.unwrap();
+    send(&mut context, &[initialize_config(&payer)], &[]).await.unwrap();
+    assert_eq!(load_config(&mut context).await.admin, payer);
+}
+
+#[tokio::test]
+async fn initialize_config_rejects_another_program_data_account() {
+    let mut context = program_test().start_with_context().await;
+    let payer = context.payer.pubkey();
+    set_upgrade_authority(&mut context, Some(payer));
+
+    let mut instruction = initialize_config(&payer);
+    instruction.accounts[5].pubkey = Pubkey::new_unique();
+    let result = send(&mut context, &[instruction], &[]).await;
+
+    assert_eq!(instruction_error(result), InstructionError::InvalidSeeds);
+}
+
+#[tokio::test]
+async fn registered_signer_verifies_against_registry() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let pubkey = signer_pubkey(&SECRET_KEY);
+    send_registry_instruction(&mut context, ProgramInstruction::RegisterSigner { pubkey }, None).await.unwrap();
+    assert_eq!(load_registry(&mut context).await.signers(), [pubkey]);
+
+    send(&mut context, &[verify_against_registry(&payer, &SECRET_KEY)], &[]).await.unwrap();
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 1);
+}
+
+#[tokio::test]
+async fn revoked_signer_is_rejected() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let pubkey = signer_pubkey(&SECRET_KEY);
+    send_registry_instruction(&mut context, ProgramInstruction::RegisterSigner { pubkey }, None).await.unwrap();
+    send_registry_instruction(&mut context, ProgramInstruction::RevokeSigner { pubkey }, None).await.unwrap();
+    assert!(load_registry(&mut context).await.signers().is_empty());
+
+    let result = send(&mut context, &[verify_against_registry(&payer, &SECRET_KEY)], &[]).await;
+    assert_eq!(custom_error(result), Some(VerifyError::SignerNotRegistered.code()));
+}
+
+#[tokio::test]
+async fn unregistered_signer_is_rejected() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let pubkey = signer_pubkey(&SECRET_KEY);
+    send_registry_instruction(&mut context, ProgramInstruction::RegisterSigner { pubkey }, None).await.unwrap();
+
+    let result = send(&mut context, &[verify_against_registry(&payer, &[0x12; 32])], &[]).await;
+    assert_eq!(custom_error(result), Some(VerifyError::SignerNotRegistered.code()));
+}
+
+#[tokio::test]
+async fn only_the_admin_registers_signers() {
+    let mut context = start().await;
+    let intruder = Keypair::new();
+
+    let pubkey = signer_pubkey(&SECRET_KEY);
+    let result = send_registry_instruction(&mut context, ProgramInstruction::RegisterSigner { pubkey }, Some(&intruder)).await;
+    assert_eq!(custom_error(result), Some(VerifyError::Unauthorized.code()));
+    assert!(load_registry(&mut context).await.signers().is_empty());
+}
+
+#[tokio::test]
+async fn revoking_an_unregistered_signer_fails() {
+    let mut context = start().await;
+
+    let pubkey = signer_pubkey(&SECRET_KEY);
+    let result = send_registry_instruction(&mut context, ProgramInstruction::RevokeSigner { pubkey }, None).await;
+    assert_eq!(custom_error(result), Some(VerifyError::SignerNotRegistered.code()));
+}
+
+/// Sends a config instruction signed by `admin`, the context's payer unless given.
+async fn send_config_instruction(
+    context: &mut ProgramTestContext,
+    instruction: ProgramInstruction,
+    admin: Option<&Keypair>,
+) -> Result<(), BanksClientError> {
+    let admin_key = admin.map_or(context.payer.pubkey(), Keypair::pubkey);
+    let instruction = Instruction::new_with_borsh(
+        PROGRAM_ID,
+        &instruction,
+        vec![AccountMeta::new_readonly(admin_key, true), AccountMeta::new(config_pda(), false)],
+    );
+    send(context, &[instruction], admin.as_slice()).await
+}
+
+/// `SetEnabledModes` with `mask`, signed by the context's payer, the admin.
+async fn set_enabled_modes(context: &mut ProgramTestContext, mask: u32) -> Result<(), BanksClientError> {
+    send_config_instruction(context, ProgramInstruction::SetEnabledModes { mask }, None).await
+}
+
+#[tokio::test]
+async fn disabled_mode_is_rejected_while_others_still_verify() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+    let batch = |data: u8| ProgramInstruction::VerifySigBatch(vec![sign_package(&payer, [data; 32], &SECRET_KEY)]);
+
+    set_enabled_modes(&mut context, ALL_MODES_ENABLED & !batch(0).mode_bit()).await.unwrap();
+    assert_eq!(load_config(&mut context).await.enabled_modes, ALL_MODES_ENABLED & !batch(0).mode_bit());
+
+    let result = send(&mut context, &[verify_instruction(&payer, &batch(1))], &[]).await;
+    assert_eq!(custom_error(result), Some(VerifyError::ModeDisabled.code()));
+    let verify_sig = ProgramInstruction::VerifySig(sign_package(&payer, [2; 32], &SECRET_KEY));
+    send(&mut context, &[verify_instruction(&payer, &verify_sig)], &[]).await.unwrap();
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 1);
+
+    // Enabled again, the batch goes through
+    set_enabled_modes(&mut context, ALL_MODES_ENABLED).await.unwrap();
+    send(&mut context, &[verify_instruction(&payer, &batch(3))], &[]).await.unwrap();
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 2);
+}
+
+#[tokio::test]
+async fn only_the_admin_sets_enabled_modes() {
+    let mut context = start().await;
+    let intruder = Keypair::new();
+
+    let result = send_config_instruction(&mut context, ProgramInstruction::SetEnabledModes { mask: 0 }, Some(&intruder)).await;
+
+    assert_eq!(custom_error(result), Some(VerifyError::Unauthorized.code()));
+    assert_eq!(load_config(&mut context).await.enabled_modes, ALL_MODES_ENABLED);
+}
+
+#[tokio::test]
+async fn all_zero_data_is_rejected_only_while_the_non_zero_rule_is_set() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+    let verify_sig = |data: u8| ProgramInstruction::VerifySig(sign_package(&payer, [data; 32], &SECRET_KEY));
+
+    send_config_instruction(&mut context, ProgramInstruction::SetDataRule { rule: DataRule::NonZero }, None).await.unwrap();
+    assert_eq!(load_config(&mut context).await.data_rule, DataRule::NonZero);
+
+    let result = send(&mut context, &[verify_instruction(&payer, &verify_sig(0))], &[]).await;
+    assert_eq!(custom_error(result), Some(VerifyError::InvalidData.code()));
+    send(&mut context, &[verify_instruction(&payer, &verify_sig(1))], &[]).await.unwrap();
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 1);
+
+    // Back to any data, the zeros are stored
+    send_config_instruction(&mut context, ProgramInstruction::SetDataRule { rule: DataRule::Any }, None).await.unwrap();
+    send(&mut context, &[verify_instruction(&payer, &verify_sig(0))], &[]).await.unwrap();
+    let state = load_state(&mut context, &payer).await;
+    assert_eq!((state.verification_count, state.last_data), (2, [0; 32]));
+}
+
+#[tokio::test]
+async fn only_the_admin_sets_the_data_rule() {
+    let mut context = start().await;
+    let intruder = Keypair::new();
+
+    let rule = ProgramInstruction::SetDataRule { rule: DataRule::LeadingByte(0x01) };
+    let result = send_config_instruction(&mut context, rule, Some(&intruder)).await;
+
+    assert_eq!(custom_error(result), Some(VerifyError::Unauthorized.code()));
+    assert_eq!(load_config(&mut context).await.data_rule, DataRule::Any);
+}
+
+#[tokio::test]
+async fn proposed_admin_takes_over_once_it_accepts() {
+    let mut context = start().await;
+    let old_admin = context.payer.pubkey();
+    let new_admin = Keypair::new();
+
+    send_config_instruction(&mut context, ProgramInstruction::ProposeAdmin { new_admin: new_admin.pubkey() }, None).await.unwrap();
+    // Proposing alone doesn't hand over control
+    let config = load_config(&mut context).await;
+    assert_eq!((config.admin, config.pending_admin), (old_admin, Some(new_admin.pubkey())));
+
+    send_config_instruction(&mut context, ProgramInstruction::AcceptAdmin, Some(&new_admin)).await.unwrap();
+    let config = load_config(&mut context).await;
+    assert_eq!((config.admin, config.pending_admin), (new_admin.pubkey(), None));
+
+    // The new admin configures the program, the old one no longer can
+    send_config_instruction(&mut context, ProgramInstruction::SetEnabledModes { mask: 0 }, Some(&new_admin)).await.unwrap();
+    let result = set_enabled_modes(&mut context, ALL_MODES_ENABLED).await;
+    assert_eq!(custom_error(result), Some(VerifyError::Unauthorized.code()));
+}
+
+#[tokio::test]
+async fn only_the_proposed_admin_accepts() {
+    let mut context = start().await;
+    let new_admin = Keypair::new();
+    let intruder = Keypair::new();
+
+    let result = send_config_instruction(&mut context, ProgramInstruction::AcceptAdmin, Some(&new_admin)).await;
+    assert_eq!(custom_error(result), Some(VerifyError::NoPendingAdmin.code()));
+
+    send_config_instruction(&mut context, ProgramInstruction::ProposeAdmin { new_admin: new_admin.pubkey() }, None).await.unwrap();
+    let result = send_config_instruction(&mut context, ProgramInstruction::AcceptAdmin, Some(&intruder)).await;
+    assert_eq!(custom_error(result), Some(VerifyError::Unauthorized.code()));
+    assert_eq!(load_config(&mut context).await.admin, context.payer.pubkey());
+}
+
+#[tokio::test]
+async fn only_the_admin_proposes_a_new_admin() {
+    let mut context = start().await;
+    let intruder = Keypair::new();
+
+    // A key that isn't the admin can't propose itself, let alone take over directly
+    let proposal = ProgramInstruction::ProposeAdmin { new_admin: intruder.pubkey() };
+    let result = send_config_instruction(&mut context, proposal, Some(&intruder)).await;
+
+    assert_eq!(custom_error(result), Some(VerifyError::Unauthorized.code()));
+    let config = load_config(&mut context).await;
+    assert_eq!((config.admin, config.pending_admin), (context.payer.pubkey(), None));
+}
+
+/// Replaces the config with the 87-byte layout written before the version byte, holding the rent
+/// for that size only, as an older program version would have left it.
+async fn write_unversioned_config(context: &mut ProgramTestContext) -> Config {
+    let config = Config {
+        admin: context.payer.pubkey(),
+        total_verifications: 5,
+        enabled_modes: ALL_MODES_ENABLED,
+        chain_id: CHAIN_ID,
+        ..Config::default()
+    };
+    let mut data = vec![0u8; 87];
+    config.serialize(&mut &mut data[..]).unwrap();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let account = Account {
+        lamports: rent.minimum_balance(data.len()),
+        data,
+        owner: PROGRAM_ID,
+        ..Account::default()
+    };
+    context.set_account(&config_pda(), &AccountSharedData::from(account));
+    config
+}
+
+async fn assert_config_migrated(context: &mut ProgramTestContext) {
+    let account = context.banks_client.get_account(config_pda()).await.unwrap().unwrap();
+    assert_eq!((account.data.len(), account.data[0]), (Config::LEN, CONFIG_VERSION));
+    let rent = context.banks_client.get_rent().await.unwrap();
+    assert!(rent.is_exempt(account.lamports, account.data.len()));
+}
+
+#[tokio::test]
+async fn unversioned_config_is_migrated_on_the_next_verification() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+    let config = write_unversioned_config(&mut context).await;
+
+    let verify_sig = ProgramInstruction::VerifySig(sign_package(&payer, [0x55; 32], &SECRET_KEY));
+    send(&mut context, &[verify_instruction(&payer, &verify_sig)], &[]).await.unwrap();
+
+    assert_config_migrated(&mut context).await;
+    assert_eq!(load_config(&mut context).await, Config { total_verifications: 6, ..config });
+}
+
+#[tokio::test]
+async fn admin_instructions_migrate_an_unversioned_config_once_it_holds_the_rent() {
+    let mut context = start().await;
+    let config = write_unversioned_config(&mut context).await;
+    let rule = || ProgramInstruction::SetDataRule { rule: DataRule::NonZero };
+
+    // No system program to take the extra rent from, the admin has to send it along
+    let result = send_config_instruction(&mut context, rule(), None).await;
+    assert_eq!(instruction_error(result), InstructionError::AccountNotRentExempt);
+
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let shortfall = rent.minimum_balance(Config::LEN) - rent.minimum_balance(87);
+    let admin = context.payer.pubkey();
+    let instructions = [
+        system_instruction::transfer(&admin, &config_pda(), shortfall),
+        Instruction::new_with_borsh(
+            PROGRAM_ID,
+            &rule(),
+            vec![AccountMeta::new_readonly(admin, true), AccountMeta::new(config_pda(), false)],
+        ),
+    ];
+    send(&mut context, &instructions, &[]).await.unwrap();
+
+    assert_config_migrated(&mut context).await;
+    assert_eq!(load_config(&mut context).await, Config { data_rule: DataRule::NonZero, ..config });
+}
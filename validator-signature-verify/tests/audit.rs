@@ -0,0 +1,38 @@
+//! The audit log processed end to end by a bank: a ring buffer of the last verifications.
+
+mod common;
+
+use borsh::BorshDeserialize;
+use common::*;
+use shared_types::parse_pubkey;
+use signature_verify::pubkey_to_eth_address;
+use signature_verify::state::{AuditLog, AUDIT_LOG_CAPACITY};
+use signature_verify::ProgramInstruction;
+use solana_program::keccak;
+use solana_sdk::signature::Signer;
+
+#[tokio::test]
+async fn audit_log_overwrites_the_oldest_entry_when_full() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    // One more verification than the log holds, each with its own data
+    for index in 0..=AUDIT_LOG_CAPACITY {
+        let package = sign_package(&payer, [index as u8; 32], &SECRET_KEY);
+        send(&mut context, &[verify_instruction(&payer, &ProgramInstruction::VerifySig(package))], &[]).await.unwrap();
+    }
+
+    let account = context.banks_client.get_account(audit_pda()).await.unwrap().unwrap();
+    let audit = AuditLog::deserialize(&mut &account.data[..]).unwrap();
+    assert_eq!(audit.total, AUDIT_LOG_CAPACITY as u64 + 1);
+    assert_eq!(audit.head, 1);
+
+    // The last verification replaced the first, and the second is now the oldest
+    let data_hash = |index: usize| keccak::hash(&[index as u8; 32]).to_bytes();
+    assert_eq!(audit.entries[0].data_hash, data_hash(AUDIT_LOG_CAPACITY));
+    assert_eq!(audit.entries[1].data_hash, data_hash(1));
+    assert_eq!(audit.entries[AUDIT_LOG_CAPACITY - 1].data_hash, data_hash(AUDIT_LOG_CAPACITY - 1));
+
+    let eth_address = pubkey_to_eth_address(&parse_pubkey(&public_key(&SECRET_KEY)).unwrap());
+    assert!(audit.entries.iter().all(|entry| entry.signer_eth_address == eth_address));
+}
@@ -0,0 +1,92 @@
+//! `VerifyAndClose` processed end to end by a bank, closing a program-owned account into the
+//! payer once the package verifies.
+
+mod common;
+
+use common::*;
+use signature_verify::{ProgramInstruction, VerifyError};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::account::{Account, AccountSharedData};
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+const CLOSE_LAMPORTS: u64 = 2_000_000;
+
+/// A program-owned account holding `CLOSE_LAMPORTS` and some data, for the payer to close.
+fn write_close_account(context: &mut ProgramTestContext, owner: Pubkey) -> Keypair {
+    let close = Keypair::new();
+    let account = Account {
+        lamports: CLOSE_LAMPORTS,
+        data: vec![0x07; 16],
+        owner,
+        ..Account::default()
+    };
+    context.set_account(&close.pubkey(), &AccountSharedData::from(account));
+    close
+}
+
+/// `VerifyAndClose` of a package over `data`, closing `close` into `payer`.
+fn verify_and_close(payer: &Pubkey, data: [u8; 32], close: &Pubkey, close_signs: bool) -> Instruction {
+    let mut accounts = state_accounts(payer);
+    accounts.push(AccountMeta::new(*close, close_signs));
+    let instruction = ProgramInstruction::VerifyAndClose(sign_package(payer, data, &SECRET_KEY));
+    Instruction::new_with_borsh(PROGRAM_ID, &instruction, accounts)
+}
+
+async fn balance(context: &mut ProgramTestContext, pubkey: &Pubkey) -> u64 {
+    context.banks_client.get_balance(*pubkey).await.unwrap()
+}
+
+#[tokio::test]
+async fn verify_and_close_refunds_the_account_to_the_payer() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+    let close = write_close_account(&mut context, PROGRAM_ID);
+    let instruction = verify_and_close(&payer, [0x33; 32], &close.pubkey(), true);
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let message = Message::new_with_blockhash(std::slice::from_ref(&instruction), Some(&payer), &blockhash);
+    let fee = context.banks_client.get_fee_for_message(message).await.unwrap().unwrap();
+    let payer_before = balance(&mut context, &payer).await;
+
+    send(&mut context, &[instruction], &[&close]).await.unwrap();
+
+    assert_eq!(balance(&mut context, &payer).await, payer_before + CLOSE_LAMPORTS - fee);
+    // Without lamports the account is gone once the transaction is processed
+    assert_eq!(context.banks_client.get_account(close.pubkey()).await.unwrap(), None);
+    assert_eq!(load_state(&mut context, &payer).await.last_data, [0x33; 32]);
+}
+
+#[tokio::test]
+async fn verify_and_close_leaves_the_account_on_an_invalid_signature() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+    let close = write_close_account(&mut context, PROGRAM_ID);
+    let mut instruction = verify_and_close(&payer, [0x33; 32], &close.pubkey(), true);
+    // Flip a byte of the signed data, the package no longer recovers to its public key
+    instruction.data[131] ^= 1;
+
+    let result = send(&mut context, &[instruction], &[&close]).await;
+
+    assert_eq!(custom_error(result), Some(VerifyError::PublicKeyMismatch.code()));
+    let account = context.banks_client.get_account(close.pubkey()).await.unwrap().unwrap();
+    assert_eq!((account.lamports, account.data), (CLOSE_LAMPORTS, vec![0x07; 16]));
+}
+
+#[tokio::test]
+async fn verify_and_close_requires_a_signing_account_of_this_program() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let unsigned = write_close_account(&mut context, PROGRAM_ID);
+    let result = send(&mut context, &[verify_and_close(&payer, [0x33; 32], &unsigned.pubkey(), false)], &[]).await;
+    assert_eq!(instruction_error(result), InstructionError::MissingRequiredSignature);
+
+    let foreign = write_close_account(&mut context, Pubkey::new_unique());
+    let result = send(&mut context, &[verify_and_close(&payer, [0x33; 32], &foreign.pubkey(), true)], &[&foreign]).await;
+    assert_eq!(instruction_error(result), InstructionError::IncorrectProgramId);
+
+    assert_eq!(balance(&mut context, &unsigned.pubkey()).await, CLOSE_LAMPORTS);
+    assert_eq!(balance(&mut context, &foreign.pubkey()).await, CLOSE_LAMPORTS);
+}
@@ -0,0 +1,93 @@
+//! `VerifyAgainstCommittee` processed end to end by a bank, against committee accounts written at
+//! genesis the way a deployment would write them.
+
+mod common;
+
+use borsh::to_vec;
+use common::*;
+use signature_verify::state::{Committee, COMMITTEE_VERSION};
+use signature_verify::{ProgramInstruction, VerifyError};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use solana_sdk::{pubkey, system_program};
+
+const COMMITTEE: Pubkey = pubkey!("Committee1111111111111111111111111111111111");
+const V2_COMMITTEE: Pubkey = pubkey!("V2Committee11111111111111111111111111111111");
+const FOREIGN_COMMITTEE: Pubkey = pubkey!("ForeignCommittee111111111111111111111111111");
+
+/// Secret keys of the five members.
+const MEMBERS: [[u8; 32]; 5] = [[0x21; 32], [0x22; 32], [0x23; 32], [0x24; 32], [0x25; 32]];
+
+fn committee_account(version: u8, owner: Pubkey) -> Account {
+    let committee = Committee {
+        version,
+        members: MEMBERS.iter().map(|secret| public_key(secret)[1..].try_into().unwrap()).collect(),
+    };
+    Account {
+        lamports: 1_000_000_000,
+        data: to_vec(&committee).unwrap(),
+        owner,
+        ..Account::default()
+    }
+}
+
+/// `program_test` with the committee, one with an unsupported version and one owned by another
+/// program.
+async fn start_with_committees() -> ProgramTestContext {
+    let mut program_test = program_test();
+    program_test.add_account(COMMITTEE, committee_account(COMMITTEE_VERSION, PROGRAM_ID));
+    program_test.add_account(V2_COMMITTEE, committee_account(COMMITTEE_VERSION + 1, PROGRAM_ID));
+    program_test.add_account(FOREIGN_COMMITTEE, committee_account(COMMITTEE_VERSION, system_program::ID));
+    start_with(program_test).await
+}
+
+fn verify_against_committee(committee: Pubkey, payer: &Pubkey, data: [u8; 32], secret: &[u8; 32]) -> Instruction {
+    let mut accounts = vec![AccountMeta::new_readonly(committee, false)];
+    accounts.extend(state_accounts(payer));
+    Instruction::new_with_borsh(PROGRAM_ID, &ProgramInstruction::VerifyAgainstCommittee(sign_package(payer, data, secret)), accounts)
+}
+
+#[tokio::test]
+async fn every_member_verifies_against_the_committee() {
+    let mut context = start_with_committees().await;
+    let payer = context.payer.pubkey();
+
+    for (index, secret) in MEMBERS.iter().enumerate() {
+        send(&mut context, &[verify_against_committee(COMMITTEE, &payer, [index as u8; 32], secret)], &[]).await.unwrap();
+    }
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, MEMBERS.len() as u64);
+}
+
+#[tokio::test]
+async fn non_member_is_rejected() {
+    let mut context = start_with_committees().await;
+    let payer = context.payer.pubkey();
+
+    let result = send(&mut context, &[verify_against_committee(COMMITTEE, &payer, [0x33; 32], &SECRET_KEY)], &[]).await;
+
+    assert_eq!(custom_error(result), Some(VerifyError::NotCommitteeMember.code()));
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 0);
+}
+
+#[tokio::test]
+async fn unsupported_committee_version_is_rejected() {
+    let mut context = start_with_committees().await;
+    let payer = context.payer.pubkey();
+
+    let result = send(&mut context, &[verify_against_committee(V2_COMMITTEE, &payer, [0x33; 32], &MEMBERS[0])], &[]).await;
+
+    assert_eq!(instruction_error(result), InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn committee_owned_by_another_program_is_rejected() {
+    let mut context = start_with_committees().await;
+    let payer = context.payer.pubkey();
+
+    let result = send(&mut context, &[verify_against_committee(FOREIGN_COMMITTEE, &payer, [0x33; 32], &MEMBERS[0])], &[]).await;
+
+    assert_eq!(instruction_error(result), InstructionError::IncorrectProgramId);
+}
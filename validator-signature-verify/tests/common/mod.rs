@@ -0,0 +1,205 @@
+//! Setup shared by the `solana-program-test` tests: a bank running the program natively with the
+//! config and the payer's state initialized, and helpers to sign packages and send instructions.
+//! Each test file only uses some of them.
+#![allow(dead_code)]
+
+use libsecp256k1::{Message, PublicKey, SecretKey};
+use signature_verify::state::{Config, ProgramState, AUDIT_SEED, CONFIG_SEED, REGISTRY_SEED, STATE_SEED};
+use signature_verify::{process_instruction, ProgramInstruction, SignaturePackage, HASH_ALGO_KECCAK256};
+use solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use solana_program_test::{processor, BanksClientError, BanksTransactionResultWithMetadata, ProgramTest, ProgramTestContext};
+use solana_sdk::account::{Account, AccountSharedData};
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_program;
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+/// Chain id the config is initialized with and packages are signed for.
+pub const CHAIN_ID: u64 = 0x4444_4444_4444_4444;
+
+/// Expiry of the packages signed by `sign_package`, far enough ahead to never expire.
+pub const NO_EXPIRY: i64 = i64::MAX;
+
+/// The secp256k1 key packages are signed with.
+pub const SECRET_KEY: [u8; 32] = [0x11; 32];
+
+/// The program id the signer sends to by default.
+pub const PROGRAM_ID: Pubkey = pubkey!("4muvyr2m6AFioKUjuyMXyLTYztykfXTTUemg4ZnD38bi");
+
+/// `ProgramTest` for the program, run natively unless `SBF_OUT_DIR` points at a built
+/// `signature_verify.so`.
+pub fn program_test() -> ProgramTest {
+    ProgramTest::new("signature_verify", PROGRAM_ID, processor!(process_instruction))
+}
+
+/// Starts `program_test` and sends `InitializeConfig` and `InitializeState` with the context's
+/// payer, which is also the admin and the program's upgrade authority.
+pub async fn start() -> ProgramTestContext {
+    start_with(program_test()).await
+}
+
+/// Like `start`, for a `ProgramTest` with extra accounts or programs added.
+pub async fn start_with(program_test: ProgramTest) -> ProgramTestContext {
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.pubkey();
+    set_upgrade_authority(&mut context, Some(payer));
+
+    let initialize_config = initialize_config(&payer);
+    let initialize_state = Instruction::new_with_borsh(PROGRAM_ID, &ProgramInstruction::InitializeState, state_accounts(&payer));
+    send(&mut context, &[initialize_config, initialize_state], &[]).await.unwrap();
+
+    context
+}
+
+/// Writes the program's ProgramData account with `upgrade_authority`, as deploying it with the
+/// upgradeable loader would. The bank runs the program natively, without one.
+pub fn set_upgrade_authority(context: &mut ProgramTestContext, upgrade_authority: Option<Pubkey>) {
+    let state = UpgradeableLoaderState::ProgramData { slot: 0, upgrade_authority_address: upgrade_authority };
+    // The metadata always takes its full size, the program's bytes follow it
+    let mut data = vec![0u8; UpgradeableLoaderState::size_of_programdata_metadata()];
+    bincode::serialize_into(&mut data[..], &state).unwrap();
+    let account = Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: bpf_loader_upgradeable::ID,
+        ..Account::default()
+    };
+    context.set_account(&program_data_address(), &AccountSharedData::from(account));
+}
+
+pub fn program_data_address() -> Pubkey {
+    bpf_loader_upgradeable::get_program_data_address(&PROGRAM_ID)
+}
+
+/// `InitializeConfig` for `CHAIN_ID`, signed by `admin`.
+pub fn initialize_config(admin: &Pubkey) -> Instruction {
+    Instruction::new_with_borsh(
+        PROGRAM_ID,
+        &ProgramInstruction::InitializeConfig { chain_id: CHAIN_ID },
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(config_pda(), false),
+            AccountMeta::new(registry_pda(), false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new(audit_pda(), false),
+            AccountMeta::new_readonly(program_data_address(), false),
+        ],
+    )
+}
+
+pub fn state_pda(payer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[STATE_SEED, payer.as_ref()], &PROGRAM_ID).0
+}
+
+pub fn config_pda() -> Pubkey {
+    Pubkey::find_program_address(&[CONFIG_SEED], &PROGRAM_ID).0
+}
+
+pub fn registry_pda() -> Pubkey {
+    Pubkey::find_program_address(&[REGISTRY_SEED], &PROGRAM_ID).0
+}
+
+pub fn audit_pda() -> Pubkey {
+    Pubkey::find_program_address(&[AUDIT_SEED], &PROGRAM_ID).0
+}
+
+/// The state accounts every verification takes: payer, state PDA, system program, config PDA
+/// and audit PDA.
+pub fn state_accounts(payer: &Pubkey) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(state_pda(payer), false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new(config_pda(), false),
+        AccountMeta::new(audit_pda(), false),
+    ]
+}
+
+/// Uncompressed public key of `secret`, `0x04 || x || y`.
+pub fn public_key(secret: &[u8; 32]) -> [u8; 65] {
+    PublicKey::from_secret_key(&SecretKey::parse(secret).unwrap()).serialize()
+}
+
+/// Signs `digest` as is with `secret`, returning the signature `r || s` and its recovery id.
+pub fn sign_digest(digest: &[u8; 32], secret: &[u8; 32]) -> ([u8; 64], u8) {
+    let (signature, recovery_id) = libsecp256k1::sign(&Message::parse(digest), &SecretKey::parse(secret).unwrap());
+    (signature.serialize(), recovery_id.serialize())
+}
+
+/// Signs `data` with `secret` for `payer`, the way the signer does.
+pub fn sign_package(payer: &Pubkey, data: [u8; 32], secret: &[u8; 32]) -> SignaturePackage {
+    sign_package_for_chain(payer, CHAIN_ID, data, secret)
+}
+
+/// `sign_package` for the cluster with `chain_id` instead of the one `start` configures.
+pub fn sign_package_for_chain(payer: &Pubkey, chain_id: u64, data: [u8; 32], secret: &[u8; 32]) -> SignaturePackage {
+    let tag = [0u8; 8];
+    let message_hash =
+        shared_types::signed_message_hash(&PROGRAM_ID, payer, chain_id, &data, NO_EXPIRY, &tag, HASH_ALGO_KECCAK256).unwrap();
+    let (verifier_signature, recovery_id) = sign_digest(&message_hash, secret);
+
+    SignaturePackage {
+        verifier_signature,
+        recovery_id,
+        public_key: public_key(secret),
+        data,
+        expiry_unix: NO_EXPIRY,
+        hash_algo: HASH_ALGO_KECCAK256,
+        tag,
+    }
+}
+
+/// `instruction` sent to the program with the payer's state accounts.
+pub fn verify_instruction(payer: &Pubkey, instruction: &ProgramInstruction) -> Instruction {
+    Instruction::new_with_borsh(PROGRAM_ID, instruction, state_accounts(payer))
+}
+
+/// Signs `instructions` with the context's payer and `signers` and processes them.
+pub async fn send(context: &mut ProgramTestContext, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), BanksClientError> {
+    let blockhash = context.banks_client.get_latest_blockhash().await?;
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(instructions, Some(&context.payer.pubkey()), &all_signers, blockhash);
+    context.banks_client.process_transaction(transaction).await
+}
+
+/// Like `send`, returning the transaction's result with its logs, return data and compute units.
+pub async fn send_with_metadata(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> BanksTransactionResultWithMetadata {
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(instructions, Some(&context.payer.pubkey()), &all_signers, blockhash);
+    context.banks_client.process_transaction_with_metadata(transaction).await.unwrap()
+}
+
+/// The `ProgramError::Custom` code a failed transaction returned, if any.
+pub fn custom_error(result: Result<(), BanksClientError>) -> Option<u32> {
+    match result.err()?.unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => Some(code),
+        _ => None,
+    }
+}
+
+/// The instruction error a failed transaction returned.
+pub fn instruction_error(result: Result<(), BanksClientError>) -> InstructionError {
+    match result.expect_err("transaction should fail").unwrap() {
+        TransactionError::InstructionError(_, err) => err,
+        err => panic!("expected an instruction error, got {:?}", err),
+    }
+}
+
+pub async fn load_state(context: &mut ProgramTestContext, payer: &Pubkey) -> ProgramState {
+    let account = context.banks_client.get_account(state_pda(payer)).await.unwrap().unwrap();
+    ProgramState::unpack(&account.data).unwrap()
+}
+
+pub async fn load_config(context: &mut ProgramTestContext) -> Config {
+    let account = context.banks_client.get_account(config_pda()).await.unwrap().unwrap();
+    Config::unpack(&account.data).unwrap()
+}
@@ -0,0 +1,65 @@
+//! Compute units each verification path consumes, printed as a table with `--nocapture`.
+//!
+//! The native build run by default is only charged for the syscalls the bank meters, such as
+//! logging, and not for the instructions it executes, so its numbers are far below the real cost
+//! and the budget check passes trivially. With `SBF_OUT_DIR` pointing at a built
+//! `signature_verify.so` the program runs under the SBF VM, and the table shows what each path
+//! costs on a cluster, checked against the budget `README.md` documents: 50,000 units for a
+//! single `VerifySig`.
+
+mod common;
+
+use common::*;
+use signature_verify::{ProgramInstruction, SignaturePackage, HASH_ALGO_KECCAK256};
+use solana_sdk::signature::Signer;
+
+/// Compute units a single `VerifySig` may consume, most of it the secp256k1 recovery.
+const VERIFY_SIG_BUDGET: u64 = 50_000;
+
+/// `digest` signed as is with `secret`, for `VerifyPrehashed`.
+fn prehashed_package(digest: [u8; 32], secret: &[u8; 32]) -> SignaturePackage {
+    let (verifier_signature, recovery_id) = sign_digest(&digest, secret);
+    SignaturePackage {
+        verifier_signature,
+        recovery_id,
+        public_key: public_key(secret),
+        data: digest,
+        expiry_unix: 0,
+        hash_algo: HASH_ALGO_KECCAK256,
+        tag: [0; 8],
+    }
+}
+
+#[tokio::test]
+async fn verification_paths_report_their_compute_units() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let modes = [
+        ("VerifySig", ProgramInstruction::VerifySig(sign_package(&payer, [1; 32], &SECRET_KEY))),
+        ("VerifyPrehashed", ProgramInstruction::VerifyPrehashed(prehashed_package([2; 32], &SECRET_KEY))),
+        (
+            "VerifySigBatch (2)",
+            ProgramInstruction::VerifySigBatch((3..5).map(|byte| sign_package(&payer, [byte; 32], &SECRET_KEY)).collect()),
+        ),
+        (
+            "VerifySigBatch (4)",
+            ProgramInstruction::VerifySigBatch((5..9).map(|byte| sign_package(&payer, [byte; 32], &SECRET_KEY)).collect()),
+        ),
+    ];
+
+    let mut units = Vec::new();
+    let build = if std::env::var_os("SBF_OUT_DIR").is_some() { "SBF" } else { "native, syscalls only" };
+    println!("{:<20} {:>10} ({})", "mode", "units", build);
+    for (name, instruction) in modes {
+        let result = send_with_metadata(&mut context, &[verify_instruction(&payer, &instruction)], &[]).await;
+        assert_eq!(result.result, Ok(()), "{}", name);
+        let consumed = result.metadata.unwrap().compute_units_consumed;
+        println!("{:<20} {:>10}", name, consumed);
+        units.push(consumed);
+    }
+
+    assert!(units[0] <= VERIFY_SIG_BUDGET, "VerifySig consumed {} units, over its budget of {}", units[0], VERIFY_SIG_BUDGET);
+    // A batch pays for a recovery per package
+    assert!(units[2] < units[3]);
+}
@@ -0,0 +1,85 @@
+//! `VerifyAndForward` processed end to end by a bank, forwarding to a sink program that records
+//! the data it was called with.
+
+mod common;
+
+use common::*;
+use signature_verify::ProgramInstruction;
+use solana_program_test::{processor, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::account_info::AccountInfo;
+use solana_sdk::entrypoint::ProgramResult;
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use solana_sdk::{pubkey, system_program};
+
+const SINK_ID: Pubkey = pubkey!("Sink111111111111111111111111111111111111111");
+const RECORD: Pubkey = pubkey!("Record1111111111111111111111111111111111111");
+
+/// Copies its instruction data into the first account.
+fn sink(_program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    accounts[0].try_borrow_mut_data()?.copy_from_slice(instruction_data);
+    Ok(())
+}
+
+/// `program_test` with the sink program and the sink-owned account it records into.
+async fn start_with_sink() -> ProgramTestContext {
+    let mut program_test = program_test();
+    program_test.add_program("sink", SINK_ID, processor!(sink));
+    program_test.add_account(
+        RECORD,
+        Account {
+            lamports: 1_000_000_000,
+            data: vec![0; 32],
+            owner: SINK_ID,
+            ..Account::default()
+        },
+    );
+    start_with(program_test).await
+}
+
+fn forward_instruction(payer: &Pubkey, data: [u8; 32], target: Pubkey) -> Instruction {
+    let mut accounts = state_accounts(payer);
+    accounts.push(AccountMeta::new_readonly(target, false));
+    accounts.push(AccountMeta::new(RECORD, false));
+    Instruction::new_with_borsh(PROGRAM_ID, &ProgramInstruction::VerifyAndForward(sign_package(payer, data, &SECRET_KEY)), accounts)
+}
+
+#[tokio::test]
+async fn verify_and_forward_calls_the_target_with_the_data() {
+    let mut context = start_with_sink().await;
+    let payer = context.payer.pubkey();
+
+    send(&mut context, &[forward_instruction(&payer, [0x33; 32], SINK_ID)], &[]).await.unwrap();
+
+    let record = context.banks_client.get_account(RECORD).await.unwrap().unwrap();
+    assert_eq!(record.data, [0x33; 32]);
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 1);
+}
+
+#[tokio::test]
+async fn verify_and_forward_does_not_call_the_target_for_a_bad_signature() {
+    let mut context = start_with_sink().await;
+    let payer = context.payer.pubkey();
+
+    let mut instruction = forward_instruction(&payer, [0x33; 32], SINK_ID);
+    let mut package = sign_package(&payer, [0x33; 32], &SECRET_KEY);
+    package.data[0] ^= 1;
+    instruction.data = borsh::to_vec(&ProgramInstruction::VerifyAndForward(package)).unwrap();
+    assert!(send(&mut context, &[instruction], &[]).await.is_err());
+
+    let record = context.banks_client.get_account(RECORD).await.unwrap().unwrap();
+    assert_eq!(record.data, [0; 32]);
+}
+
+#[tokio::test]
+async fn verify_and_forward_rejects_the_system_program_and_itself() {
+    let mut context = start_with_sink().await;
+    let payer = context.payer.pubkey();
+
+    for target in [system_program::ID, PROGRAM_ID] {
+        let result = send(&mut context, &[forward_instruction(&payer, [0x33; 32], target)], &[]).await;
+        assert_eq!(instruction_error(result), InstructionError::IncorrectProgramId, "target {}", target);
+    }
+}
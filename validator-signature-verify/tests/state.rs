@@ -0,0 +1,82 @@
+//! The per-payer state PDA processed end to end by a bank: initialization, the account layout and
+//! migrating accounts written with older layouts.
+
+mod common;
+
+use common::*;
+use signature_verify::state::{ProgramState, STATE_VERSION};
+use signature_verify::ProgramInstruction;
+use solana_program_test::ProgramTestContext;
+use solana_sdk::account::{Account, AccountSharedData};
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+
+#[tokio::test]
+async fn initialize_state_creates_a_zeroed_rent_exempt_account() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let account = context.banks_client.get_account(state_pda(&payer)).await.unwrap().unwrap();
+    assert_eq!(account.owner, PROGRAM_ID);
+    assert_eq!(account.data.len(), ProgramState::LEN);
+    let rent = context.banks_client.get_rent().await.unwrap();
+    assert!(rent.is_exempt(account.lamports, account.data.len()));
+
+    let state = load_state(&mut context, &payer).await;
+    assert_eq!(state.verification_count, 0);
+    assert_eq!(state.last_data, [0; 32]);
+    assert_eq!(state.admin, payer);
+}
+
+#[tokio::test]
+async fn initialize_state_rejects_reinitialization() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let instruction = verify_instruction(&payer, &ProgramInstruction::InitializeState);
+    let result = send(&mut context, &[instruction], &[]).await;
+
+    assert_eq!(instruction_error(result), InstructionError::AccountAlreadyInitialized);
+}
+
+/// Replaces the payer's state account with `data`, as an older program version would have left it.
+async fn write_state(context: &mut ProgramTestContext, payer: &Pubkey, data: Vec<u8>) {
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let account = Account {
+        lamports: rent.minimum_balance(data.len()),
+        data,
+        owner: PROGRAM_ID,
+        ..Account::default()
+    };
+    context.set_account(&state_pda(payer), &AccountSharedData::from(account));
+}
+
+#[tokio::test]
+async fn unversioned_state_accounts_are_migrated_on_the_next_verification() {
+    let count = 7u64.to_le_bytes();
+    let legacy_layouts = [
+        // Count and last data, read with a default admin
+        ([&count[..], &[0x33; 32]].concat(), [0; 32]),
+        // Count, last data and admin
+        ([&count[..], &[0x33; 32], &[0x44; 32]].concat(), [0x44; 32]),
+    ];
+    for (data, admin) in legacy_layouts {
+        let mut context = start().await;
+        let payer = context.payer.pubkey();
+        write_state(&mut context, &payer, data).await;
+
+        let instruction = verify_instruction(&payer, &ProgramInstruction::VerifySig(sign_package(&payer, [0x55; 32], &SECRET_KEY)));
+        send(&mut context, &[instruction], &[]).await.unwrap();
+
+        let account = context.banks_client.get_account(state_pda(&payer)).await.unwrap().unwrap();
+        assert_eq!(account.data.len(), ProgramState::LEN);
+        assert_eq!(account.data[0], STATE_VERSION);
+        let rent = context.banks_client.get_rent().await.unwrap();
+        assert!(rent.is_exempt(account.lamports, account.data.len()));
+        let state = load_state(&mut context, &payer).await;
+        assert_eq!(state.verification_count, 8);
+        assert_eq!(state.last_data, [0x55; 32]);
+        assert_eq!(state.admin.to_bytes(), admin);
+    }
+}
@@ -0,0 +1,279 @@
+//! Verification instructions processed end to end by a bank, signed the way the signer signs them.
+
+mod common;
+
+use common::*;
+use shared_types::nonce_message_hash;
+use signature_verify::state::NONCE_SEED;
+use signature_verify::{pubkey_to_eth_address, ProgramInstruction, SignaturePackageV2, VerifyError, SIGNER_RETURN_DATA_LEN};
+use solana_program::keccak;
+use solana_program::secp256k1_recover::Secp256k1Pubkey;
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+#[tokio::test]
+async fn verify_sig_accepts_a_signed_package() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let package = sign_package(&payer, [0x33; 32], &SECRET_KEY);
+    let instruction = verify_instruction(&payer, &ProgramInstruction::VerifySig(package));
+    let result = send_with_metadata(&mut context, &[instruction], &[]).await;
+
+    assert_eq!(result.result, Ok(()));
+    let logs = result.metadata.unwrap().log_messages;
+    assert!(logs.iter().any(|log| log == "Program log: Signature valid!"), "{:#?}", logs);
+
+    let state = load_state(&mut context, &payer).await;
+    assert_eq!(state.verification_count, 1);
+    assert_eq!(state.last_data, [0x33; 32]);
+    assert_eq!(load_config(&mut context).await.total_verifications, 1);
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_tampered_data() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let mut package = sign_package(&payer, [0x33; 32], &SECRET_KEY);
+    package.data[0] ^= 1;
+    let instruction = verify_instruction(&payer, &ProgramInstruction::VerifySig(package));
+    let result = send(&mut context, &[instruction], &[]).await;
+
+    assert_eq!(custom_error(result), Some(VerifyError::PublicKeyMismatch.code()));
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 0);
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_a_package_signed_for_another_payer() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+    let other_payer = Keypair::new();
+
+    // Submitted by `other_payer`, the package hashes to a different message and recovers to a
+    // different key than the one it carries
+    let package = sign_package(&payer, [0x33; 32], &SECRET_KEY);
+    let instruction = verify_instruction(&other_payer.pubkey(), &ProgramInstruction::VerifySig(package));
+    let result = send(&mut context, &[instruction], &[&other_payer]).await;
+
+    assert_eq!(custom_error(result), Some(VerifyError::PublicKeyMismatch.code()));
+    assert!(context.banks_client.get_account(state_pda(&other_payer.pubkey())).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_a_package_signed_for_another_cluster() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    // The config stores CHAIN_ID, a package for any other chain recovers to a different key
+    let package = sign_package_for_chain(&payer, CHAIN_ID + 1, [0x33; 32], &SECRET_KEY);
+    let result = send(&mut context, &[verify_instruction(&payer, &ProgramInstruction::VerifySig(package))], &[]).await;
+    assert_eq!(custom_error(result), Some(VerifyError::PublicKeyMismatch.code()));
+
+    let package = sign_package_for_chain(&payer, CHAIN_ID, [0x33; 32], &SECRET_KEY);
+    send(&mut context, &[verify_instruction(&payer, &ProgramInstruction::VerifySig(package))], &[]).await.unwrap();
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 1);
+}
+
+#[tokio::test]
+async fn verify_sig_stores_the_signed_tag() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+    let tag = *b"order-42";
+
+    let mut package = sign_package(&payer, [0x33; 32], &SECRET_KEY);
+    let message_hash =
+        shared_types::signed_message_hash(&PROGRAM_ID, &payer, CHAIN_ID, &package.data, package.expiry_unix, &tag, package.hash_algo).unwrap();
+    (package.verifier_signature, package.recovery_id) = sign_digest(&message_hash, &SECRET_KEY);
+    package.tag = tag;
+
+    // The tag is signed, changing it breaks the signature
+    let mut tampered = package.clone();
+    tampered.tag[0] ^= 1;
+    let result = send(&mut context, &[verify_instruction(&payer, &ProgramInstruction::VerifySig(tampered))], &[]).await;
+    assert_eq!(custom_error(result), Some(VerifyError::PublicKeyMismatch.code()));
+
+    send(&mut context, &[verify_instruction(&payer, &ProgramInstruction::VerifySig(package))], &[]).await.unwrap();
+    let state = load_state(&mut context, &payer).await;
+    assert_eq!((state.last_data, state.last_tag), ([0x33; 32], tag));
+}
+
+#[tokio::test]
+async fn verify_sig_rejects_a_payer_that_did_not_sign() {
+    let mut context = start().await;
+    // Not the fee payer, which signs the transaction whatever the instruction says
+    let payer = Keypair::new().pubkey();
+
+    let package = sign_package(&payer, [0x33; 32], &SECRET_KEY);
+    let mut instruction = verify_instruction(&payer, &ProgramInstruction::VerifySig(package));
+    instruction.accounts[0] = AccountMeta::new(payer, false);
+    let result = send(&mut context, &[instruction], &[]).await;
+
+    assert_eq!(instruction_error(result), InstructionError::MissingRequiredSignature);
+}
+
+#[tokio::test]
+async fn verify_sig_batch_is_all_or_nothing() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let mut packages: Vec<_> = (1..=3).map(|byte| sign_package(&payer, [byte; 32], &SECRET_KEY)).collect();
+    packages[1].data = [0x22; 32];
+    let instruction = verify_instruction(&payer, &ProgramInstruction::VerifySigBatch(packages));
+    let result = send(&mut context, &[instruction], &[]).await;
+
+    // The first package verified, but its update is rolled back with the failed instruction
+    assert_eq!(custom_error(result), Some(VerifyError::PublicKeyMismatch.code()));
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 0);
+}
+
+#[tokio::test]
+async fn verify_sig_stores_the_last_data_and_count() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    for data in [[1; 32], [2; 32]] {
+        let instruction = verify_instruction(&payer, &ProgramInstruction::VerifySig(sign_package(&payer, data, &SECRET_KEY)));
+        send(&mut context, &[instruction], &[]).await.unwrap();
+    }
+
+    let state = load_state(&mut context, &payer).await;
+    assert_eq!(state.verification_count, 2);
+    assert_eq!(state.last_data, [2; 32]);
+
+    // InitializeState created the PDA rent exempt
+    let account = context.banks_client.get_account(state_pda(&payer)).await.unwrap().unwrap();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    assert!(rent.is_exempt(account.lamports, account.data.len()));
+}
+
+/// `VerifySigWithNonce` of `data` signed with `nonce`, with the signer's nonce PDA first.
+fn verify_with_nonce(payer: &Pubkey, data: [u8; 32], nonce: u64) -> Instruction {
+    verify_with_nonce_signed_over(payer, data, nonce, nonce_message_hash(&PROGRAM_ID, payer, CHAIN_ID, &data, nonce))
+}
+
+/// `VerifySigWithNonce` of `data` and `nonce` with a signature over `message_hash`.
+fn verify_with_nonce_signed_over(payer: &Pubkey, data: [u8; 32], nonce: u64, message_hash: [u8; 32]) -> Instruction {
+    let (verifier_signature, recovery_id) = sign_digest(&message_hash, &SECRET_KEY);
+    let public_key = public_key(&SECRET_KEY);
+    let package = SignaturePackageV2 { verifier_signature, recovery_id, public_key, data, nonce };
+
+    let nonce_pda = Pubkey::find_program_address(&[NONCE_SEED, &public_key[1..33], &public_key[33..]], &PROGRAM_ID).0;
+    let mut accounts = vec![AccountMeta::new(nonce_pda, false)];
+    accounts.extend(state_accounts(payer));
+    Instruction::new_with_borsh(PROGRAM_ID, &ProgramInstruction::VerifySigWithNonce(package), accounts)
+}
+
+#[tokio::test]
+async fn verify_with_nonce_accepts_increasing_nonces() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    send(&mut context, &[verify_with_nonce(&payer, [1; 32], 1)], &[]).await.unwrap();
+    send(&mut context, &[verify_with_nonce(&payer, [2; 32], 5)], &[]).await.unwrap();
+
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 2);
+}
+
+#[tokio::test]
+async fn verify_with_nonce_rejects_a_reused_nonce() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    send(&mut context, &[verify_with_nonce(&payer, [1; 32], 1)], &[]).await.unwrap();
+    // Different data, so it isn't dropped as the same transaction before reaching the program
+    let result = send(&mut context, &[verify_with_nonce(&payer, [2; 32], 1)], &[]).await;
+
+    assert_eq!(custom_error(result), Some(VerifyError::StaleNonce.code()));
+}
+
+#[tokio::test]
+async fn verify_with_nonce_rejects_a_lower_nonce() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    send(&mut context, &[verify_with_nonce(&payer, [1; 32], 5)], &[]).await.unwrap();
+    let result = send(&mut context, &[verify_with_nonce(&payer, [2; 32], 4)], &[]).await;
+
+    assert_eq!(custom_error(result), Some(VerifyError::StaleNonce.code()));
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 1);
+}
+
+#[tokio::test]
+async fn verify_with_nonce_rejects_a_package_signed_for_another_payer() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let other_payer = nonce_message_hash(&PROGRAM_ID, &Pubkey::new_unique(), CHAIN_ID, &[1; 32], 1);
+    let result = send(&mut context, &[verify_with_nonce_signed_over(&payer, [1; 32], 1, other_payer)], &[]).await;
+    assert_eq!(custom_error(result), Some(VerifyError::PublicKeyMismatch.code()));
+
+    // The unbound `data || nonce` message is rejected too
+    let unbound = keccak::hashv(&[&[1; 32], &1u64.to_le_bytes()]).0;
+    let result = send(&mut context, &[verify_with_nonce_signed_over(&payer, [1; 32], 1, unbound)], &[]).await;
+    assert_eq!(custom_error(result), Some(VerifyError::PublicKeyMismatch.code()));
+
+    // The nonce wasn't burnt by either
+    send(&mut context, &[verify_with_nonce(&payer, [1; 32], 1)], &[]).await.unwrap();
+}
+
+#[tokio::test]
+async fn verify_sig_returns_the_recovered_signer() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let package = sign_package(&payer, [0x33; 32], &SECRET_KEY);
+    let instruction = verify_instruction(&payer, &ProgramInstruction::VerifySig(package));
+    let result = send_with_metadata(&mut context, &[instruction], &[]).await;
+
+    assert_eq!(result.result, Ok(()));
+    let return_data = result.metadata.unwrap().return_data.unwrap();
+    assert_eq!(return_data.program_id, PROGRAM_ID);
+    let data = return_data.data;
+    assert_eq!(data.len(), SIGNER_RETURN_DATA_LEN);
+    let expected_pubkey = Secp256k1Pubkey::new(&public_key(&SECRET_KEY)[1..]);
+    assert_eq!(data[..64], expected_pubkey.to_bytes());
+    assert_eq!(data[64..84], pubkey_to_eth_address(&expected_pubkey));
+    assert_eq!(data[84..], 1u64.to_le_bytes());
+}
+
+#[tokio::test]
+async fn total_verifications_counts_across_instruction_types() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let single = verify_instruction(&payer, &ProgramInstruction::VerifySig(sign_package(&payer, [1; 32], &SECRET_KEY)));
+    send(&mut context, &[single], &[]).await.unwrap();
+    let batch = verify_instruction(
+        &payer,
+        &ProgramInstruction::VerifySigBatch(vec![sign_package(&payer, [2; 32], &SECRET_KEY), sign_package(&payer, [3; 32], &SECRET_KEY)]),
+    );
+    let result = send_with_metadata(&mut context, &[batch], &[]).await;
+
+    assert_eq!(result.result, Ok(()));
+    let return_data = result.metadata.unwrap().return_data.unwrap().data;
+    assert_eq!(return_data[84..], 3u64.to_le_bytes());
+    assert_eq!(load_config(&mut context).await.total_verifications, 3);
+}
+
+#[tokio::test]
+async fn simulating_a_bad_signature_surfaces_the_error_and_logs() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+
+    let mut package = sign_package(&payer, [0x33; 32], &SECRET_KEY);
+    package.data[0] ^= 1;
+    let instruction = verify_instruction(&payer, &ProgramInstruction::VerifySig(package));
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer), &[&context.payer], context.last_blockhash);
+    let simulation = context.banks_client.simulate_transaction(transaction).await.unwrap();
+
+    assert_eq!(
+        simulation.result.unwrap(),
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(VerifyError::PublicKeyMismatch.code())))
+    );
+    let logs = simulation.simulation_details.unwrap().logs;
+    assert!(logs.iter().any(|log| log == "Program log: Signature verification failed"), "{:#?}", logs);
+    assert_eq!(load_state(&mut context, &payer).await.verification_count, 0);
+}